@@ -0,0 +1,34 @@
+//! times a call heavy program, recursive fib.
+//!
+//! run with `cargo bench --bench fib`.
+use rloxv2::interpreter::lox::Lox;
+use rloxv2::lang::tree::parser::Parser;
+use rloxv2::lang::tree::resolver::Resolver;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 3;
+
+const PROGRAM: &str = "
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+var result = fib(30);
+";
+
+fn main() {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut parser = Parser::new(PROGRAM);
+        parser.parse();
+        let stmts = parser.take_statements();
+        Resolver::new()
+            .resolve(&stmts)
+            .expect("benchmark should resolve");
+        let mut lox = Lox::new();
+        let start = Instant::now();
+        lox.interpret(stmts).expect("benchmark should run");
+        best = best.min(start.elapsed());
+    }
+    println!("fib(30): {best:?}");
+}
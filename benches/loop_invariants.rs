@@ -0,0 +1,50 @@
+//! compares a loop heavy program with and without the loop invariant pass.
+//!
+//! run with `cargo bench --bench loop_invariants`.
+use rloxv2::interpreter::lox::Lox;
+use rloxv2::lang::tree::parser::Parser;
+use rloxv2::lang::tree::resolver::Resolver;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 5;
+
+const PROGRAM: &str = "
+var width = 12;
+var height = 7;
+var total = 0;
+var i = 0;
+while (i < 100000) {
+    total = total + (width * height + width / 2) * (height - 1) + i;
+    i = i + 1;
+}
+";
+
+fn time(cache: bool) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut parser = Parser::new(PROGRAM);
+        parser.parse();
+        let stmts = parser.take_statements();
+        let mut resolver = Resolver::new();
+        for stmt in &stmts {
+            stmt.accept(&mut resolver)
+                .expect("benchmark should resolve");
+        }
+        let mut lox = Lox::new().with_invariant_caching(cache);
+        let start = Instant::now();
+        lox.interpret(stmts).expect("benchmark should run");
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+fn main() {
+    let plain = time(false);
+    let cached = time(true);
+    println!("plain:  {plain:?}");
+    println!("cached: {cached:?}");
+    println!(
+        "speedup: {:.2}x",
+        plain.as_secs_f64() / cached.as_secs_f64()
+    );
+}
@@ -0,0 +1,60 @@
+//! counts the allocations made by a loop calling a method, alongside how long it takes.
+//!
+//! run with `cargo bench --bench methods`.
+use rloxv2::interpreter::lox::Lox;
+use rloxv2::lang::tree::parser::Parser;
+use rloxv2::lang::tree::resolver::Resolver;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ITERATIONS: usize = 100_000;
+
+const PROGRAM: &str = "
+class Counter {
+    init() { this.count = 0; }
+    bump(by) { this.count = this.count + by; }
+}
+var counter = Counter();
+var i = 0;
+while (i < 100000) {
+    counter.bump(1);
+    i = i + 1;
+}
+";
+
+fn main() {
+    let mut parser = Parser::new(PROGRAM);
+    parser.parse();
+    let stmts = parser.take_statements();
+    Resolver::new()
+        .resolve(&stmts)
+        .expect("benchmark should resolve");
+    let mut lox = Lox::new();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    lox.interpret(stmts).expect("benchmark should run");
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!("time:        {elapsed:?}");
+    println!("allocations: {allocations}");
+    println!("per call:    {:.2}", allocations as f64 / ITERATIONS as f64);
+}
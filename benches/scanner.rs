@@ -0,0 +1,56 @@
+//! scanning throughput over a large, all ascii source, and the cost of scanning many short lines
+//! with a fresh scanner each, the way the repl does.
+//!
+//! run with `cargo bench --bench scanner`.
+use rloxv2::lang::tokenizer::scanner::Scanner;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 5;
+const REPEAT: usize = 20_000;
+const LINES: usize = 1_000_000;
+
+const CHUNK: &str = r#"
+// a comment that the scanner has to skip over
+class Point {
+    init(x, y) { this.x = x; this.y = y; }
+    length() { return (this.x ** 2 + this.y ** 2) ** 0.5; }
+}
+var total = 0;
+for (var i = 0; i < 100; i = i + 1) {
+    total += Point(i, 12.5).length() * 3 - 1 / 2;
+    print "iteration " + string(i);
+}
+"#;
+
+fn main() {
+    let src = CHUNK.repeat(REPEAT);
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        tokens = 0;
+        for token in Scanner::new(&src) {
+            token.expect("benchmark source should scan");
+            tokens += 1;
+        }
+        best = best.min(start.elapsed());
+    }
+    let mb = src.len() as f64 / (1024.0 * 1024.0);
+    println!("scanned {tokens} tokens ({mb:.1} MiB) in {best:?}");
+    println!("throughput: {:.1} MiB/s", mb / best.as_secs_f64());
+
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        for _ in 0..LINES {
+            for token in Scanner::new("print x;") {
+                token.expect("benchmark source should scan");
+            }
+        }
+        best = best.min(start.elapsed());
+    }
+    println!(
+        "scanned {LINES} lines with a new scanner each in {best:?} ({:?} per line)",
+        best / LINES as u32
+    );
+}
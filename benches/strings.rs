@@ -0,0 +1,54 @@
+//! compares a string heavy program with and without string interning.
+//!
+//! run with `cargo bench --bench strings`.
+use rloxv2::interpreter::lox::Lox;
+use rloxv2::lang::tree::parser::Parser;
+use rloxv2::lang::tree::resolver::Resolver;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 5;
+
+// the keys are built separately every time around, so without interning each comparison has to
+// walk around fifty kilobytes of equal text before it can say yes. `intern` does nothing when it's off.
+const PROGRAM: &str = r#"
+var base = "key";
+for (var n = 0; n < 14; n = n + 1) {
+    base = base + base;
+}
+var hits = 0;
+for (var i = 0; i < 200; i = i + 1) {
+    var key = intern(base + ":" + "name");
+    var other = intern(base + ":" + "name");
+    for (var j = 0; j < 200; j = j + 1) {
+        if (key == other) hits = hits + 1;
+    }
+}
+"#;
+
+fn time(intern: bool) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut parser = Parser::new(PROGRAM);
+        parser.parse();
+        let stmts = parser.take_statements();
+        Resolver::new()
+            .resolve(&stmts)
+            .expect("benchmark should resolve");
+        let mut lox = Lox::new().with_string_interning(intern);
+        let start = Instant::now();
+        lox.interpret(stmts).expect("benchmark should run");
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+fn main() {
+    let plain = time(false);
+    let interned = time(true);
+    println!("plain:    {plain:?}");
+    println!("interned: {interned:?}");
+    println!(
+        "speedup: {:.2}x",
+        plain.as_secs_f64() / interned.as_secs_f64()
+    );
+}
@@ -0,0 +1,111 @@
+//! one error type for everything that can go wrong between reading a program and running it.
+//!
+//! each stage keeps its own error type, this wraps them so a host can collect and report any of
+//! them the same way: `message()` for the one line summary, `span()` for where it points and
+//! `render(src)` for the summary along with the offending line of source.
+use crate::interpreter::runtime::error::RuntimeError;
+use crate::lang::tokenizer::error::ScanError;
+use crate::lang::tokenizer::span::{Span, code_block};
+use crate::lang::tree::error::{ParseError, ResolveError};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LoxDiagnostic {
+    #[error(transparent)]
+    Scan(#[from] ScanError),
+    #[error(transparent)]
+    Parse(ParseError),
+    #[error(transparent)]
+    Resolve(#[from] ResolveError),
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+}
+
+impl From<ParseError> for LoxDiagnostic {
+    // the parser passes on the scanner's errors, those are reported as the scan errors they are.
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::ScanError(e) => Self::Scan(e),
+            e => Self::Parse(e),
+        }
+    }
+}
+
+impl LoxDiagnostic {
+    /// the region of source the diagnostic points at, if it has one. runtime errors only know
+    /// where the failing expression started.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Scan(e) => e.span(),
+            Self::Parse(e) => e.span(),
+            Self::Resolve(e) => Some(e.span()),
+            Self::Runtime(e) => e.place().map(Span::at),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        self.to_string()
+    }
+
+    /// the message followed by the offending line of `src` with the error underlined, and for
+    /// runtime errors raised inside a function, the calls that led there.
+    pub fn render(&self, src: &str) -> String {
+        let block = match self {
+            Self::Scan(e) => e.span().map(|span| code_block(src, span)),
+            Self::Parse(e) => e.code_block(src),
+            Self::Resolve(e) => Some(e.code_block(src)),
+            Self::Runtime(e) => e.code_block(src),
+        };
+        let mut rendered = self.message();
+        if let Some(block) = block {
+            rendered.push('\n');
+            rendered.push_str(&block);
+        }
+        if let Self::Runtime(e) = self
+            && let Some(trace) = e.stack_trace(Some(src))
+        {
+            rendered.push_str(&format!("\ntrace: {trace}"));
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::lox::Lox;
+
+    fn diagnostics(src: &str) -> Vec<LoxDiagnostic> {
+        Lox::new().run_source(src).unwrap_err()
+    }
+
+    #[test]
+    fn test_every_stage_reports_a_diagnostic() {
+        let scan = diagnostics("var a = \"abc;");
+        assert!(matches!(scan[..], [LoxDiagnostic::Scan(_)]));
+
+        let parse = diagnostics("var = 1;\nprint (;");
+        assert_eq!(parse.len(), 2);
+        assert!(parse.iter().all(|d| matches!(d, LoxDiagnostic::Parse(_))));
+
+        let resolve = diagnostics("{ var a = 1; var a = 2; }");
+        assert!(matches!(resolve[..], [LoxDiagnostic::Resolve(_)]));
+        assert_eq!(resolve[0].span(), Some(Span::new(17, 18)));
+
+        let runtime = diagnostics("var a = 1;\nprint a - nil;");
+        let [LoxDiagnostic::Runtime(e)] = &runtime[..] else {
+            panic!("expected a single runtime error");
+        };
+        assert_eq!(e.place(), Some(19));
+        assert_eq!(runtime[0].span(), Some(Span::at(19)));
+    }
+
+    #[test]
+    fn test_render_includes_source_line() {
+        let src = "var a = 1;\nprint a - nil;";
+        let rendered = diagnostics(src)[0].render(src);
+        let mut lines = rendered.lines();
+        assert!(lines.next().unwrap().starts_with("TypeError"));
+        assert!(rendered.contains("print a - nil;"));
+    }
+}
@@ -1,84 +1,375 @@
+use crate::interpreter::diagnostic::LoxDiagnostic;
 use crate::interpreter::runtime::class::{Class, ClassInstance};
-use crate::interpreter::runtime::error::{BinaryError, LoxError, RuntimeError};
+use crate::interpreter::runtime::error::{
+    BinaryError, LoxError, NativeError, RuntimeError, Warning,
+};
 use crate::interpreter::runtime::eval::{Eval, EvalResult};
+use crate::interpreter::runtime::frame::{Frame, UpValue};
 use crate::interpreter::runtime::function::Function;
-use crate::interpreter::runtime::native::setup_native;
+use crate::interpreter::runtime::intern::StringPool;
+use crate::interpreter::runtime::map::{LoxMap, MapKey};
+use crate::interpreter::runtime::native::{NativeFn, is_native, setup_native};
+use crate::interpreter::runtime::number::NumberFormat;
 use crate::interpreter::runtime::object::LoxObject;
-use crate::interpreter::runtime::scope::Scope;
+use crate::interpreter::runtime::profile::{FunctionProfile, Profiler};
+use crate::interpreter::runtime::trace::CallFrame;
+use crate::lang::tokenizer::span::Span;
 use crate::lang::tree::ast::{
-    self, BinaryOperator, Callee, Expr, Identifier, Literal, LogicalOperator, Stmt, UnaryPrefix,
+    self, BinaryOperator, Binding, Callee, Capture, Expr, Identifier, Literal, LogicalOperator,
+    PostfixOperator, Stmt, UnaryPrefix,
 };
+use crate::lang::tree::fold::fold_constants;
+use crate::lang::tree::invariant::cache_loop_invariants;
+use crate::lang::tree::parser::Parser;
+use crate::lang::tree::resolver::Resolver;
 use crate::lang::visitor::Visitor;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{self, Write};
 use std::rc::Rc;
 
+// the name reported for classes declared without one, e.g. `var C = class {};`
+const ANONYMOUS_CLASS_NAME: &str = "anonymous";
+// every lox call costs several native stack frames, each nested block inside it a few more, about
+// 13KB in all in a debug build. this depth stays well inside the 2MB a spawned thread gets by
+// default, let alone the main thread's 8MB.
+const DEFAULT_MAX_CALL_DEPTH: usize = 100;
+
+/// the native stack a thread running the interpreter should be given by hosts that raise the call
+/// depth past the default, enough for a depth of 1000 with room to spare in a debug build.
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
 pub struct Lox {
     globals: HashMap<String, LoxObject>,
-    current_scope: Rc<RefCell<Scope>>,
+    // the locals of the function being run, or of the top level's blocks.
+    frame: Frame,
+    // what the function being run captured, see `Capture`.
+    upvalues: Rc<[UpValue]>,
+    // the first slot of the innermost block with a scope of its own.
+    scope_start: usize,
+    // the text of the program being run, used to give runtime errors a line number.
+    source: Option<String>,
+    // the functions currently being executed, outermost first.
+    call_stack: Vec<CallFrame>,
+    // where the call currently being dispatched was made from.
+    call_site: usize,
+    // the whole of the latest call expression, for natives to point their errors at.
+    call_span: Span,
+    max_call_depth: usize,
+    // whether to fold constant expressions before interpreting, see `fold.rs`.
+    fold_constants: bool,
+    // whether to run the loop invariant pass before interpreting, and the values it has cached.
+    cache_invariants: bool,
+    invariant_cache: HashMap<usize, LoxObject>,
+    // how numbers are shown to the program's user, see `display`.
+    number_format: NumberFormat,
+    // when set, string literals and anything passed to `intern` are shared through this pool.
+    strings: Option<StringPool>,
+    // whether arithmetic turning finite numbers into inf or NaN is an error.
+    strict_math: bool,
+    // whether assigning to a global that was never declared declares it.
+    implicit_globals: bool,
+    // whether declaring something with the same name as a native is worth a warning.
+    warn_shadowed_natives: bool,
+    warnings: Vec<Warning>,
+    // when set, every lox function call is timed, see `profile`.
+    profiler: Option<Profiler>,
+    // where `print` and `write` send their text.
+    output: Box<dyn Write>,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Lox {
     pub fn new() -> Self {
         let mut me = Self {
             globals: HashMap::new(),
-            current_scope: Rc::new(RefCell::new(Scope::default())),
+            frame: Frame::default(),
+            upvalues: Rc::new([]),
+            scope_start: 0,
+            source: None,
+            call_stack: Vec::new(),
+            call_site: 0,
+            call_span: Span::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            fold_constants: false,
+            cache_invariants: false,
+            invariant_cache: HashMap::new(),
+            number_format: NumberFormat::default(),
+            strings: None,
+            strict_math: false,
+            implicit_globals: false,
+            warn_shadowed_natives: false,
+            warnings: Vec::new(),
+            profiler: None,
+            output: Box::new(io::stdout()),
         };
         setup_native(&mut me);
         me
     }
 
+    /// an interpreter whose programs start out with `globals` defined alongside the natives, for
+    /// hosts passing in configuration. a seeded global with a native's name replaces the native,
+    /// and programs are free to reassign or redeclare any of them.
+    pub fn new_with_globals(globals: HashMap<String, LoxObject>) -> Self {
+        let mut me = Self::new();
+        me.globals.extend(globals);
+        me
+    }
+
     pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), RuntimeError> {
+        self.interpret_value(statements).map(|_| ())
+    }
+
+    /// like `interpret`, but hands back the value of the program's final statement when it is an
+    /// expression statement, and nil otherwise.
+    pub fn interpret_value(
+        &mut self,
+        mut statements: Vec<Stmt>,
+    ) -> Result<LoxObject, RuntimeError> {
+        if self.fold_constants {
+            fold_constants(&mut statements);
+        }
+        if self.cache_invariants {
+            cache_loop_invariants(&mut statements);
+        }
+        let mut last = LoxObject::new_nil();
         for stmt in statements {
-            let _ = stmt.accept(self)?;
+            let eval = stmt.accept(self).map_err(|e| self.locate(e))?;
+            last = match (stmt, eval) {
+                (Stmt::Expression { .. }, Eval::Object(obj)) => obj,
+                _ => LoxObject::new_nil(),
+            };
         }
-        Ok(())
+        Ok(last)
+    }
+
+    /// run a single line of input the way a REPL would. errors are handed back to be reported, but
+    /// the session stays usable: globals defined before the error are kept and any scopes or calls
+    /// the failed statement left open are unwound. a line ending in an expression gives back its value,
+    /// and that expression doesn't need a semicolon.
+    pub fn run_line(&mut self, line: &str) -> Result<LoxObject, Vec<LoxDiagnostic>> {
+        let (locals, scope_start) = (self.frame.len(), self.scope_start);
+        let result = self.run(line, true);
+        if result.is_err() {
+            self.frame.truncate(locals);
+            self.scope_start = scope_start;
+            self.call_stack.clear();
+        }
+        result
+    }
+
+    /// parse, resolve and run a whole program, handing back the value of its final statement like
+    /// `interpret_value`. every syntax error is reported at once, otherwise the first error stops it.
+    /// the parser's warnings are collected with the rest, see `warnings`.
+    pub fn run_source(&mut self, src: &str) -> Result<LoxObject, Vec<LoxDiagnostic>> {
+        self.run(src, false)
     }
 
-    fn declare(&mut self, name: &str) -> usize {
-        self.current_scope.borrow_mut().declare(name)
+    fn run(&mut self, src: &str, bare_expression: bool) -> Result<LoxObject, Vec<LoxDiagnostic>> {
+        let mut parser = Parser::new(src)
+            .with_bare_expression(bare_expression)
+            .with_printed_errors(false);
+        parser.parse();
+        if parser.had_errors() {
+            return Err(parser.take_errors().into_iter().map(Into::into).collect());
+        }
+        for warning in parser.warnings() {
+            self.warn(warning.into());
+        }
+        let stmts = parser.take_statements();
+        Resolver::new()
+            .resolve(&stmts)
+            .map_err(|e| vec![e.into()])?;
+        self.set_source(src);
+        self.interpret_value(stmts).map_err(|e| vec![e.into()])
     }
 
-    fn define(&mut self, name: &str, value: LoxObject) {
-        self.current_scope.borrow_mut().define(name, value);
+    /// limit how many lox calls can be active at once before a StackOverflow error is raised.
+    pub fn with_max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
     }
 
-    fn bind(&mut self, ident: &Identifier, value: LoxObject) {
-        // 2. If resolver gave us a (depth,slot), it's a local…
-        if let Some(_) = ident.depth_slot() {
-            self.declare(ident.name_str());
-            self.define(ident.name_str(), value)
-        } else {
-            // …otherwise it's a global
-            self.set_global(ident.name_str(), value);
+    /// work out operations on literals, like `60 * 60`, once before the program runs rather than
+    /// every time they're evaluated. see `fold.rs` for what gets folded. off by default.
+    pub fn with_constant_folding(mut self, enabled: bool) -> Self {
+        self.fold_constants = enabled;
+        self
+    }
+
+    /// cache the values of pure expressions that can't change while a loop runs, see `invariant.rs`
+    /// for what counts. off by default.
+    pub fn with_invariant_caching(mut self, enabled: bool) -> Self {
+        self.cache_invariants = enabled;
+        self
+    }
+
+    /// share one allocation between equal string literals, and any string a program passes to the
+    /// `intern` native, so comparing them is a pointer check. built strings aren't interned by
+    /// themselves since hashing every intermediate result costs more than it saves. the pool is
+    /// never emptied, so this is off by default.
+    pub fn with_string_interning(mut self, enabled: bool) -> Self {
+        self.strings = enabled.then(StringPool::new);
+        self
+    }
+
+    /// the pooled copy of `value` when interning is on, otherwise `value` itself.
+    pub fn intern(&mut self, value: LoxObject) -> LoxObject {
+        match self.strings.as_mut() {
+            Some(pool) => pool.intern_object(value),
+            None => value,
+        }
+    }
+
+    /// collect a warning whenever the program declares a variable, function or class named like
+    /// one of the natives. the declaration still goes ahead, see `warnings`. off by default.
+    pub fn with_shadowed_native_warnings(mut self, enabled: bool) -> Self {
+        self.warn_shadowed_natives = enabled;
+        self
+    }
+
+    /// the warnings collected so far, each reported once.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings[..]
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// time every call to a lox function, totalled per function. natives aren't timed on their
+    /// own, their time counts towards whichever function called them. off by default.
+    pub fn with_profiler(mut self, enabled: bool) -> Self {
+        self.profiler = enabled.then(Profiler::new);
+        self
+    }
+
+    /// the timings collected by the profiler so far, slowest function first. `None` when the
+    /// profiler isn't on.
+    pub fn profile(&self) -> Option<Vec<FunctionProfile>> {
+        let src = self.source.as_deref();
+        self.profiler.as_ref().map(|profiler| profiler.report(src))
+    }
+
+    /// raise a RangeError when arithmetic on finite numbers gives inf or NaN, like
+    /// `1e308 * 1e308` or `0 / 0`, rather than carrying on with it. off by default.
+    pub fn with_strict_math(mut self, enabled: bool) -> Self {
+        self.strict_math = enabled;
+        self
+    }
+
+    /// let `x = 5;` declare `x` when there's no such global yet, instead of raising a
+    /// ReferenceError. reading a global that doesn't exist is still an error. off by default.
+    pub fn with_implicit_globals(mut self, enabled: bool) -> Self {
+        self.implicit_globals = enabled;
+        self
+    }
+
+    /// send everything the program prints to `output` instead of stdout.
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// where the program's printed text goes, for natives that write to it.
+    pub fn output(&mut self) -> &mut dyn Write {
+        self.output.as_mut()
+    }
+
+    pub fn with_number_format(mut self, format: NumberFormat) -> Self {
+        self.number_format = format;
+        self
+    }
+
+    /// the text a program sees for `value`, as written by `print` or built by `string`.
+    /// numbers follow the configured `NumberFormat`, everything else is its `Display`.
+    pub fn display(&self, value: &LoxObject) -> String {
+        match value.as_number() {
+            Some(n) => self.number_format.format(n),
+            None => value.to_string(),
         }
     }
 
-    fn set_at(&mut self, distance: usize, slot: usize, value: LoxObject) {
-        self.current_scope
-            .borrow_mut()
-            .set_at(distance, slot, value);
+    /// hand the interpreter the source text its statements were parsed from
+    /// so runtime errors can report where they happened.
+    pub fn set_source(&mut self, src: &str) {
+        self.source = Some(src.to_string());
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    fn locate(&self, err: RuntimeError) -> RuntimeError {
+        match self.source {
+            Some(ref src) => err.with_source(src),
+            None => err,
+        }
+    }
+
+    // locals get their slot before their initializer runs, as the resolver hands them out, so a
+    // function can capture itself and anything declared while initializing comes after it.
+    fn reserve(&mut self, ident: &Identifier) {
+        if let Some(Binding::Local { slot }) = ident.binding() {
+            let reserved = self.frame.declare(LoxObject::new_nil());
+            debug_assert_eq!(reserved, slot, "'{}' was reserved out of order", ident);
+        }
     }
 
-    fn get_at(&self, distance: usize, slot: usize) -> LoxObject {
-        self.current_scope.borrow().get_at(distance, slot)
+    fn bind(&mut self, ident: &Identifier, value: LoxObject) {
+        if self.warn_shadowed_natives && is_native(ident.name_str()) {
+            let msg = format!(
+                "'{}' shadows the native function of the same name",
+                ident.name_str()
+            );
+            self.warn(Warning::new(msg, ident.position()));
+        }
+        match ident.binding() {
+            // the resolver gave us the slot `reserve` set aside in the current frame…
+            Some(Binding::Local { slot }) => self.frame.set(slot, value),
+            // …otherwise it's a global. declarations are never captured from another function.
+            Some(Binding::Global) | Some(Binding::UpValue { .. }) | None => {
+                self.set_global(ident.name_str(), value)
+            }
+        }
     }
 
     pub fn get_global(&self, name: &str) -> Option<LoxObject> {
-        self.globals.get(name).map(|v| v.clone())
+        self.globals.get(name).cloned()
+    }
+
+    /// every global defined so far, natives included.
+    pub fn global_names(&self) -> impl Iterator<Item = &str> {
+        self.globals.keys().map(String::as_str)
     }
 
     pub fn set_global(&mut self, name: &str, value: LoxObject) {
         self.globals.insert(name.to_string(), value);
     }
 
+    /// make a native function available to programs as the global `name`.
+    pub fn register_native(&mut self, name: &'static str, native: NativeFn) {
+        self.set_global(name, LoxObject::Native(name, native));
+    }
+
     pub fn assign_global(
         &mut self,
         name_ident: &Identifier,
         value: LoxObject,
     ) -> Result<(), RuntimeError> {
         let key = name_ident.name_str();
-        if !self.globals.contains_key(key) {
+        if !self.implicit_globals && !self.globals.contains_key(key) {
             return Err(reference_error(name_ident));
         }
         self.set_global(key, value);
@@ -86,65 +377,268 @@ impl Lox {
     }
 
     pub fn resolve(&self, name: &Identifier) -> Option<LoxObject> {
-        if let Some((depth, slot)) = name.depth_slot() {
-            Some(self.get_at(depth, slot))
-        } else {
-            self.get_global(name.name_str())
+        match name.binding() {
+            Some(Binding::Local { slot }) => Some(self.frame.get(slot)),
+            Some(Binding::UpValue { index }) => Some(self.upvalues[index].borrow().clone()),
+            Some(Binding::Global) | None => self.get_global(name.name_str()),
         }
     }
 
-    fn create_scope(&mut self) {
-        let next = Scope::from(self.current_scope.clone());
-        self.current_scope = Rc::new(RefCell::new(next));
+    // write `value` to wherever the resolver found `ident` lives.
+    fn store(&mut self, ident: &Identifier, value: LoxObject) -> Result<(), RuntimeError> {
+        match ident.binding() {
+            Some(Binding::Local { slot }) => {
+                self.frame.set(slot, value);
+                Ok(())
+            }
+            Some(Binding::UpValue { index }) => {
+                *self.upvalues[index].borrow_mut() = value;
+                Ok(())
+            }
+            Some(Binding::Global) | None => self.assign_global(ident, value),
+        }
     }
 
-    fn shed_scope(&mut self) {
-        let parent = self.current_scope.borrow().parent();
-        if let Some(p) = parent {
-            self.current_scope = p
-        }
+    // the cells a function being created holds on to for what it captures.
+    fn capture(&mut self, captures: &[Capture]) -> Rc<[UpValue]> {
+        captures
+            .iter()
+            .map(|capture| match *capture {
+                Capture::Local(slot) => self.frame.capture(slot),
+                Capture::UpValue(index) => self.upvalues[index].clone(),
+            })
+            .collect()
     }
 
     fn call_fn(&mut self, func: &Function, args: Vec<LoxObject>) -> EvalResult {
-        // copy our current scope.
-        let original = self.current_scope.clone();
-        // setup the environment for the func's enclosing scope.
-        self.current_scope = func.closure();
-        // setup a fresh environment for the parameters to be bound to the arguments.
-        self.create_scope();
-        // setup the stack local arguments.
-        self.setup_fn_stack(func, args);
+        self.call_fn_in(func, None, args)
+    }
+
+    /// call a method with `this` bound to `target`.
+    fn call_method(
+        &mut self,
+        func: &Function,
+        target: LoxObject,
+        args: Vec<LoxObject>,
+    ) -> EvalResult {
+        self.call_fn_in(func, Some(target), args)
+    }
+
+    fn call_fn_in(
+        &mut self,
+        func: &Function,
+        this: Option<LoxObject>,
+        args: Vec<LoxObject>,
+    ) -> EvalResult {
+        if self.call_stack.len() >= self.max_call_depth {
+            let err = RuntimeError::from(LoxError::StackOverflow(self.max_call_depth));
+            return Err(err.with_place(self.call_site).with_trace(&self.call_stack));
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.enter(func.shared_name(), func.position());
+        }
+        let call = CallFrame::new(func.shared_name(), func.position(), self.call_site);
+        self.call_stack.push(call);
+        // swap in a fresh frame and the func's upvalues, keeping the caller's to restore later.
+        let frame = self.setup_fn_frame(func, this, args);
+        let caller_frame = std::mem::replace(&mut self.frame, frame);
+        let caller_upvalues = std::mem::replace(&mut self.upvalues, func.upvalues());
         // call the function
         let eval = func.body().accept(self);
-        // peel off the parameter's scope
-        self.shed_scope();
-        //println!("scope after calling func \n{:#?}", self.current_scope);
-        // return to our original state.
-        self.current_scope = original;
+        // return to our original state before looking at the result, the body may have errored part way through.
+        self.frame = caller_frame;
+        self.upvalues = caller_upvalues;
+        let eval = eval.map_err(|e| self.trace_error(e));
+        self.call_stack.pop();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.exit();
+        }
         eval
     }
 
-    // it is the responsibliity of the caller to have properly set up the state
-    // for local variables.
-    fn setup_fn_stack(&mut self, func: &Function, args: Vec<LoxObject>) {
-        let params = func.params();
-        if params.len() == 0 {
-            return;
+    fn execute_block(&mut self, statments: &[Stmt]) -> EvalResult {
+        for stmt in statments {
+            let v = stmt.accept(self)?;
+            if v.is_control() {
+                return Ok(v);
+            }
+        }
+        Ok(Eval::new_nil())
+    }
+
+    /// snapshot the call stack onto an error leaving the innermost active call.
+    fn trace_error(&self, err: RuntimeError) -> RuntimeError {
+        // errors without a place of their own are attributed to the call they escaped from.
+        let call_site = self.call_stack.last().map_or(0, |frame| frame.call_site());
+        err.with_place(call_site).with_trace(&self.call_stack)
+    }
+
+    /// the call expression that invoked the native being run, e.g. all of `assert(x, "msg")`.
+    /// natives that call back into lox should read it first, the calls made there replace it.
+    pub fn current_call_span(&self) -> Span {
+        self.call_span
+    }
+
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack[..]
+    }
+
+    // a method's frame starts with `this`, then come the parameters, as the resolver laid it out.
+    fn setup_fn_frame(
+        &self,
+        func: &Function,
+        this: Option<LoxObject>,
+        args: Vec<LoxObject>,
+    ) -> Frame {
+        let mut frame = Frame::with_capacity(func.arity() + 1);
+        if let Some(this) = this {
+            frame.declare(this);
+        }
+        // parameters without a matching argument are nil, extra arguments are dropped.
+        let mut args = args.into_iter();
+        for _ in func.params() {
+            frame.declare(args.next().unwrap_or_else(LoxObject::new_nil));
+        }
+        frame
+    }
+
+    /// call any callable lox value with already evaluated arguments. this is the entry point
+    /// for native functions that need to call back into lox code.
+    pub fn call_object(&mut self, callee: LoxObject, args: Vec<LoxObject>) -> EvalResult {
+        match callee {
+            LoxObject::Native(_, f) => f(self, args),
+            LoxObject::Function(f) => self
+                .call_fn(f.as_ref(), args)
+                .map(|v| v.into_return_value().into()),
+            LoxObject::BoundMethod(ci, f) => self
+                .call_method(f.as_ref(), LoxObject::ClassInstance(ci), args)
+                .map(|v| v.into_return_value().into()),
+            LoxObject::Class(c) => self.instantiate_class(c, args),
+            _ => Err(not_callable(&callee)),
+        }
+    }
+
+    fn instantiate_class(&mut self, class: Rc<Class>, args: Vec<LoxObject>) -> EvalResult {
+        let obj = LoxObject::from(ClassInstance::new(class.clone()));
+        self.init_instance_fields(&class, &obj)?;
+        if let Some(init) = class.init() {
+            let _ = self.call_method(&init, obj.clone(), args)?;
+        }
+        Ok(obj.into())
+    }
+
+    fn init_instance_fields(&mut self, class: &Class, obj: &LoxObject) -> Result<(), RuntimeError> {
+        // inherited fields come first so the subclass can override or build on them.
+        if let Some(superclass) = class.superclass() {
+            self.init_instance_fields(superclass, obj)?;
+        }
+        if class.fields().is_empty() {
+            return Ok(());
+        }
+        // initializers run like the body of a method would: a frame holding `this` along with
+        // whatever they captured.
+        let mut frame = Frame::with_capacity(1);
+        frame.declare(obj.clone());
+        let caller_frame = std::mem::replace(&mut self.frame, frame);
+        let caller_upvalues = std::mem::replace(&mut self.upvalues, class.field_upvalues());
+        let result = self.eval_instance_fields(class, obj);
+        self.frame = caller_frame;
+        self.upvalues = caller_upvalues;
+        result
+    }
+
+    fn eval_instance_fields(&mut self, class: &Class, obj: &LoxObject) -> Result<(), RuntimeError> {
+        let LoxObject::ClassInstance(ci) = obj else {
+            return Ok(());
+        };
+        for (name, value) in class.fields() {
+            let eval = value.accept(self)?;
+            let value = unwrap_to_object(eval)?;
+            ci.borrow_mut().set(name, value);
+        }
+        Ok(())
+    }
+
+    fn resolve_superclass(&self, value: &ast::Class) -> Result<Option<Rc<Class>>, RuntimeError> {
+        let Some(ident) = value.superclass() else {
+            return Ok(None);
+        };
+        match self.resolve(ident) {
+            Some(LoxObject::Class(class)) => Ok(Some(class)),
+            Some(other) => {
+                Err(type_error("class", &other.type_name()).with_place(ident.position()))
+            }
+            None => Err(reference_error(ident)),
+        }
+    }
+
+    fn collect_class_methods(
+        &mut self,
+        name: &str,
+        value: &ast::Class,
+        superclass: Option<Rc<Class>>,
+    ) -> Class {
+        // methods of a subclass capture `super` from a slot declared just for them, matching the
+        // resolver.
+        let locals = self.frame.len();
+        if let Some(ref sup) = superclass {
+            self.frame.declare(LoxObject::Class(sup.clone()));
         }
-        for param in params {
-            self.declare(param);
+        let methods = value.methods();
+        let mut class_methods = HashMap::with_capacity(methods.len());
+        let mut static_methods = HashMap::with_capacity(methods.len());
+        let mut init = None;
+        for method in methods {
+            // the parser should have already confirmed that this is safe.
+            let name = method.name().unwrap().name_str().to_string();
+            let upvalues = self.capture(&method.captures());
+            let func = Function::new(upvalues, method.param_strings(), method.body())
+                .with_name(Some(name.clone()), method.position())
+                .with_getter(method.is_getter());
+
+            // todo: parser should ensure that there are no "static" init functions.
+            if name == "init" {
+                init.replace(LoxObject::from(func));
+            } else if method.is_static() {
+                static_methods.insert(name, LoxObject::from(func));
+            } else {
+                class_methods.insert(name, LoxObject::from(func));
+            }
         }
-        let pairs = params.iter().zip(args.into_iter());
-        for (name, value) in pairs {
-            self.define(name, value);
+        let fields = value
+            .instance_fields()
+            .map(|field| (field.name().name_str().to_string(), field.value()))
+            .collect::<Vec<_>>();
+        let field_upvalues = if fields.is_empty() {
+            Rc::new([])
+        } else {
+            self.capture(&value.field_captures())
+        };
+        self.frame.truncate(locals);
+        Class::new(name.to_string(), class_methods, static_methods, init)
+            .with_fields(field_upvalues, fields)
+            .with_superclass(superclass)
+    }
+
+    // static fields are evaluated once the class exists so their initializers can refer to it.
+    fn init_static_fields(
+        &mut self,
+        class: &Class,
+        value: &ast::Class,
+    ) -> Result<(), RuntimeError> {
+        for field in value.static_fields() {
+            let eval = field.value().accept(self)?;
+            let obj = unwrap_to_object(eval).map_err(|e| e.with_place(field.name().position()))?;
+            class.set_static(field.name().name_str(), obj);
         }
+        Ok(())
     }
 
     fn handle_object_get(&mut self, obj: LoxObject, property: &Identifier) -> EvalResult {
         match obj {
             LoxObject::ClassInstance(ci) => self.handle_class_instance_get(ci, property),
             LoxObject::Class(c) => self.handle_class_get(c, property),
-            _ => Err(reference_error(property)),
+            _ => Err(ref_error_prop_not_obj(&obj, property)),
         }
     }
 
@@ -153,37 +647,83 @@ impl Lox {
         ci: Rc<RefCell<ClassInstance>>,
         property: &Identifier,
     ) -> EvalResult {
-        if let Some(v) = ci.borrow().get(property.name_str()) {
-            match v {
-                LoxObject::Function(func) => {
-                    let obj = LoxObject::ClassInstance(ci.clone());
-                    let bound_func = func.bind(obj);
-                    Ok(LoxObject::from(bound_func).into())
-                }
-                _ => Ok(v.clone().into()),
-            }
-        } else {
-            Err(ref_error_prop_access(property))
+        // release the borrow before running a getter, its body is free to modify the instance.
+        let value = ci.borrow().get(property.name_str()).cloned();
+        match value {
+            Some(LoxObject::Function(func)) => self.bind_method(ci, func, property),
+            Some(v) => Ok(v.into()),
+            None => Err(ref_error_prop_access(property)),
+        }
+    }
+
+    // `super.name` looks methods up starting from the superclass, bound to the current `this`.
+    // fields live on the instance rather than the class, so there is only ever one `name` field
+    // per instance. `super.name` reads that same value as `this.name`, even when the subclass
+    // declares its own `name` field (whose initializer runs after, and so replaces, the
+    // superclass's); it only has to be a field the superclass declares.
+    fn handle_super_get(
+        &mut self,
+        keyword: &Identifier,
+        this: &Identifier,
+        property: &Identifier,
+    ) -> EvalResult {
+        let (Some(superclass), Some(this)) = (self.resolve(keyword), self.resolve(this)) else {
+            return Err(reference_error(keyword));
+        };
+        let (LoxObject::Class(superclass), LoxObject::ClassInstance(ci)) = (superclass, this)
+        else {
+            return Err(reference_error(keyword));
+        };
+        let name = property.name_str();
+        if let Some(LoxObject::Function(method)) = superclass.get_method(name) {
+            return self.bind_method(ci, method.clone(), property);
+        }
+        if superclass.declares_field(name) {
+            let value = ci.borrow().properties().get(name).cloned();
+            return Ok(value.unwrap_or_else(LoxObject::new_nil).into());
+        }
+        Err(ref_error_prop_access(property))
+    }
+
+    // getters run straight away, any other method is handed back bound to the instance.
+    fn bind_method(
+        &mut self,
+        ci: Rc<RefCell<ClassInstance>>,
+        func: Rc<Function>,
+        property: &Identifier,
+    ) -> EvalResult {
+        if !func.is_getter() {
+            return Ok(LoxObject::BoundMethod(ci, func).into());
         }
+        self.call_site = property.position();
+        self.call_method(&func, LoxObject::ClassInstance(ci), Vec::new())
+            .map(|v| v.into_return_value().into())
+            .map_err(|e| e.with_place(property.position()))
     }
 
     fn handle_class_get(&mut self, class: Rc<Class>, property: &Identifier) -> EvalResult {
-        if let Some(v) = class.get_static(property.name_str()) {
-            match v {
-                LoxObject::Function(func) => Ok(LoxObject::from(func.clone()).into()),
-                _ => Ok(v.clone().into()),
+        match class.get_static(property.name_str()) {
+            Some(LoxObject::Function(func)) if func.is_getter() => {
+                self.call_getter(&func, property)
             }
-        } else {
-            Err(ref_error_prop_access(property))
+            Some(v) => Ok(v.into()),
+            None => Err(ref_error_prop_access(property)),
         }
     }
+
+    fn call_getter(&mut self, getter: &Function, property: &Identifier) -> EvalResult {
+        self.call_site = property.position();
+        self.call_fn(getter, Vec::new())
+            .map(|v| v.into_return_value().into())
+            .map_err(|e| e.with_place(property.position()))
+    }
 }
 
 impl Visitor<EvalResult, Expr, Stmt> for Lox {
     fn visit_binary(&mut self, left: &Expr, op: BinaryOperator, right: &Expr) -> EvalResult {
         let l = unwrap_to_object(left.accept(self)?).map_err(|e| e.with_place(op.position()))?;
         let r = unwrap_to_object(right.accept(self)?).map_err(|e| e.with_place(op.position()))?;
-        match binary_op(&l, &r, op) {
+        match binary_op(&l, &r, op, self.strict_math) {
             Ok(v) => Ok(v.into()),
             Err(err_type) => Err(binary_op_error(&l, &r, op, err_type)),
         }
@@ -211,7 +751,7 @@ impl Visitor<EvalResult, Expr, Stmt> for Lox {
     }
 
     fn visit_literal(&mut self, value: &Literal) -> EvalResult {
-        Ok(LoxObject::from(value).into())
+        Ok(self.intern(LoxObject::from(value)).into())
     }
 
     fn visit_unary(&mut self, prefix: UnaryPrefix, expr: &Expr) -> EvalResult {
@@ -224,75 +764,58 @@ impl Visitor<EvalResult, Expr, Stmt> for Lox {
     }
 
     fn visit_variable(&mut self, ident: &Identifier) -> EvalResult {
-        let v = if let Some((depth, slot)) = ident.depth_slot() {
-            self.get_at(depth, slot).into()
-        } else {
-            self.get_global(ident.name_str())
-                .ok_or_else(|| reference_error(ident))?
-        };
+        let v = self.resolve(ident).ok_or_else(|| reference_error(ident))?;
         Ok(v.into())
     }
 
     fn visit_assignment(&mut self, ident: &Identifier, value: &Expr) -> EvalResult {
         let eval = value.accept(self)?;
         let value = unwrap_to_object(eval).map_err(|e| e.with_place(ident.position()))?;
-        // println!("ident is {:#?}", ident);
-        if let Some((depth, slot)) = ident.depth_slot() {
-            self.set_at(depth, slot, value.clone());
-            return Ok(value.into());
-        } else {
-            return self
-                .assign_global(ident, value.clone())
-                .map(|_| Eval::from(value));
+        self.store(ident, value.clone())?;
+        Ok(value.into())
+    }
+
+    fn visit_postfix(&mut self, ident: &Identifier, op: PostfixOperator) -> EvalResult {
+        let old = self.resolve(ident).ok_or_else(|| reference_error(ident))?;
+        let Some(n) = old.as_number() else {
+            let msg = format!("invalid type {} for postfix {}", old.type_name(), op);
+            return Err(RuntimeError::from(LoxError::TypeError(msg)).with_place(op.position()));
         };
+        self.store(ident, LoxObject::from(n + op.step()))?;
+        Ok(old.into())
     }
 
     fn visit_call(&mut self, callee: &Callee, args: &[Expr]) -> EvalResult {
         let eval = callee.expr.accept(self)?;
-        let call_obj = unwrap_to_object(eval).map_err(|e| e.with_place(callee.position()))?;
+        let call_obj = unwrap_to_object(eval).map_err(|e| e.with_place(callee.place()))?;
+        if !call_obj.is_callable() {
+            return Err(not_callable(&call_obj).with_place(callee.place()));
+        }
         let mut rt_args = Vec::with_capacity(args.len());
         for arg in args {
             let eval = arg.accept(self)?;
             let obj = unwrap_to_object(eval).map_err(|e| e.with_place(callee.position()))?;
             rt_args.push(obj)
         }
-        match call_obj {
-            LoxObject::Native(f) => f(self, rt_args).map_err(|e| e.with_place(callee.position())),
-            LoxObject::Function(f) => self
-                .call_fn(f.as_ref(), rt_args)
-                .map(|v| v.unwrap_return())
-                .map_err(|e| e.with_place(callee.position())),
-            LoxObject::Class(c) => {
-                let instance = ClassInstance::new(c);
-                if let Some(init) = instance.init() {
-                    let obj = LoxObject::from(instance);
-                    let _ = self
-                        .call_fn(&init.bind(obj.clone()), rt_args)
-                        .map_err(|e| e.with_place(callee.position()))?;
-                    Ok(obj.into())
-                } else {
-                    Ok(LoxObject::from(instance).into())
-                }
-            }
-            _ => Err(
-                RuntimeError::from(type_error("function", call_obj.type_str()))
-                    .with_place(callee.position()),
-            ),
-        }
+        self.call_site = callee.position();
+        self.call_span = callee.span();
+        self.call_object(call_obj, rt_args)
+            .map_err(|e| e.with_place(callee.position()))
     }
 
     fn visit_function(&mut self, value: &ast::Function) -> EvalResult {
-        Ok(LoxObject::from(Function::new(
-            self.current_scope.clone(),
-            value
-                .params()
-                .iter()
-                .map(|p| p.name_str().to_string())
-                .collect(),
+        let func = Function::new(
+            self.capture(&value.captures()),
+            value.param_strings(),
             value.body(),
-        ))
-        .into())
+        )
+        .with_name(
+            value.name().map(|ident| ident.name_str().to_string()),
+            value.position(),
+        );
+        Ok(LoxObject::from(func).into())
     }
+
     fn visit_get(&mut self, object: &Expr, property: &Identifier) -> EvalResult {
         let obj = object.accept(self)?;
         match obj {
@@ -308,13 +831,29 @@ impl Visitor<EvalResult, Expr, Stmt> for Lox {
                 let eval = value.accept(self)?;
                 let value =
                     unwrap_to_object(eval).map_err(|e| e.with_place(property.position()))?;
-                ci.borrow_mut().set(property.name_str(), value);
-                Ok(Eval::new_nil())
+                ci.borrow_mut().set(property.name_str(), value.clone());
+                Ok(value.into())
+            }
+            Eval::Object(LoxObject::Class(class)) => {
+                let eval = value.accept(self)?;
+                let value =
+                    unwrap_to_object(eval).map_err(|e| e.with_place(property.position()))?;
+                class.set_static(property.name_str(), value.clone());
+                Ok(value.into())
             }
             _ => Err(type_error("class instance", obj.type_str())),
         }
     }
 
+    fn visit_super(
+        &mut self,
+        keyword: &Identifier,
+        this: &Identifier,
+        property: &Identifier,
+    ) -> EvalResult {
+        self.handle_super_get(keyword, this, property)
+    }
+
     fn visit_this(&mut self, ident: &Identifier) -> EvalResult {
         match self.resolve(ident) {
             Some(v) => Ok(Eval::from(v)),
@@ -327,7 +866,7 @@ impl Visitor<EvalResult, Expr, Stmt> for Lox {
     }
 
     fn visit_continue_statment(&mut self) -> EvalResult {
-        Ok(Eval::new_continue().into())
+        Ok(Eval::new_continue())
     }
 
     fn visit_return_statment(&mut self, value: Option<&Expr>) -> EvalResult {
@@ -343,10 +882,22 @@ impl Visitor<EvalResult, Expr, Stmt> for Lox {
         expr.accept(self)
     }
 
-    fn visit_print_statement(&mut self, expr: &Expr) -> EvalResult {
-        let v = expr.accept(self)?;
-        v.with_object(|obj| println!("{}", obj));
-        Ok(v)
+    // evaluates to the last value printed, nil for a bare `print;`.
+    fn visit_print_statement(&mut self, exprs: &[Expr], span: Span) -> EvalResult {
+        let mut line = String::new();
+        let mut last = LoxObject::new_nil();
+        for (i, expr) in exprs.iter().enumerate() {
+            last = unwrap_to_object(expr.accept(self)?)?;
+            if i > 0 {
+                line.push(' ');
+            }
+            line.push_str(&self.display(&last));
+        }
+        if writeln!(self.output, "{line}").is_err() {
+            let err = NativeError::SystemError("print failed to write its output".to_string());
+            return Err(RuntimeError::from(LoxError::from(err)).with_place(span.start));
+        }
+        Ok(last.into())
     }
 
     fn visit_var_statement(
@@ -354,6 +905,7 @@ impl Visitor<EvalResult, Expr, Stmt> for Lox {
         ident: &Identifier,
         initializer: Option<&Expr>,
     ) -> EvalResult {
+        self.reserve(ident);
         // 1. Evaluate the initializer (or nil)
         let value = if let Some(expr) = initializer {
             unwrap_to_object(expr.accept(self)?)?
@@ -364,20 +916,46 @@ impl Visitor<EvalResult, Expr, Stmt> for Lox {
         Ok(Eval::new_nil())
     }
 
+    fn visit_destructure_statement(
+        &mut self,
+        names: &[Identifier],
+        initializer: &Expr,
+        position: usize,
+    ) -> EvalResult {
+        for name in names {
+            self.reserve(name);
+        }
+        let value = unwrap_to_object(initializer.accept(self)?)?;
+        let LoxObject::Array(items) = &value else {
+            return Err(type_error("array", &value.type_name()).with_place(position));
+        };
+        let items = items.borrow();
+        if items.len() != names.len() {
+            let msg = format!(
+                "cannot destructure {} values into {} names",
+                items.len(),
+                names.len()
+            );
+            return Err(RuntimeError::from(LoxError::TypeError(msg)).with_place(position));
+        }
+        for (name, item) in names.iter().zip(items.iter()) {
+            self.bind(name, item.clone());
+        }
+        Ok(Eval::new_nil())
+    }
+
     fn visit_block_statement(&mut self, statments: &[Stmt]) -> EvalResult {
-        // create a new scope
-        self.create_scope();
-        let mut ret = Eval::new_nil();
-        for stmt in statments {
-            let v = stmt.accept(self)?;
-            if v.is_control() {
-                ret = v;
-                break;
-            }
+        // blocks that declare nothing run in the enclosing scope, see `Stmt::needs_scope`.
+        if !statments.iter().any(Stmt::needs_scope) {
+            return self.execute_block(statments);
         }
-        // get rid of the temporary scope we created.
-        self.shed_scope();
-        Ok(ret)
+        // forget the block's locals even if a statement errors part way through.
+        let start = self.frame.len();
+        let enclosing = std::mem::replace(&mut self.scope_start, start);
+        let ret = self.execute_block(statments);
+        self.frame.truncate(start);
+        self.scope_start = enclosing;
+        ret
     }
 
     fn visit_if_statement(
@@ -404,65 +982,167 @@ impl Visitor<EvalResult, Expr, Stmt> for Lox {
             if v.is_return() {
                 return Ok(v);
             }
+            // `continue` has already skipped the rest of the body, all that's left is to go back
+            // around and test the condition again.
+            if v.is_continue() {
+                continue;
+            }
         }
         Ok(LoxObject::new_nil().into())
     }
 
-    // todo: should this just be desugared into a var statement?
-    // I want to wait to see if this is the exact same logic or not.
-    fn visit_class_statement(
+    fn visit_for_statement(
         &mut self,
-        name: &Identifier,
-        methods: &[ast::Function],
+        condition: &Expr,
+        increment: Option<&Expr>,
+        body: &Stmt,
     ) -> EvalResult {
-        let mut class_methods = HashMap::with_capacity(methods.len());
-        let mut static_methods = HashMap::with_capacity(methods.len());
-        let mut init = None;
-        for method in methods {
-            // the parser should have already confirmed that this is safe.
-            let name = method.name().unwrap().name_str().to_string();
-            let func = Function::new(
-                self.current_scope.clone(),
-                method.param_strings(),
-                method.body(),
-            );
-
-            // todo: parser should ensure that there are no "static" init functions.
-            if name == "init" {
-                init.replace(LoxObject::from(func));
-            } else if method.is_static() {
-                static_methods.insert(name, LoxObject::from(func));
-            } else {
-                class_methods.insert(name, LoxObject::from(func));
+        // the loop variables are the locals of the block the loop was desugared into.
+        let loop_vars = self.scope_start;
+        while condition.accept(self)?.truthy() {
+            let v = body.accept(self)?;
+            if v.is_break() {
+                break;
+            }
+            if v.is_return() {
+                return Ok(v);
+            }
+            // closures from this iteration hold on to the loop variables they captured, so the
+            // next iteration gets its own copy of them to update.
+            self.frame.renew(loop_vars);
+            if let Some(increment) = increment {
+                increment.accept(self)?;
             }
         }
-        let class_name = String::from(name.name_str());
-        let class = LoxObject::from(Class::new(class_name, class_methods, static_methods, init));
-        self.bind(name, class.clone());
-        Ok(Eval::Object(class))
+        Ok(LoxObject::new_nil().into())
     }
-}
 
-fn unary_op(value: &LoxObject, op: UnaryPrefix) -> Result<LoxObject, BinaryError> {
-    match op {
-        UnaryPrefix::Bang { .. } => Ok(value.truthy().into()),
-        UnaryPrefix::Minus { .. } => apply_math_op(value, &(-1.0).into(), |a, b| a * b),
+    // todo: should this just be desugared into a var statement?
+    // I want to wait to see if this is the exact same logic or not.
+    fn visit_class_statement(&mut self, value: &ast::Class) -> EvalResult {
+        // the parser should have already confirmed that this is safe.
+        let name = value.name().unwrap();
+        self.reserve(name);
+        let superclass = self.resolve_superclass(value)?;
+        let class = Rc::new(self.collect_class_methods(name.name_str(), value, superclass));
+        self.bind(name, LoxObject::Class(class.clone()));
+        self.init_static_fields(&class, value)?;
+        Ok(Eval::Object(LoxObject::Class(class)))
     }
-}
 
-fn binary_op(l: &LoxObject, r: &LoxObject, op: BinaryOperator) -> Result<LoxObject, BinaryError> {
-    match op {
-        // addition is a special case where we need to handle string concatenation.
-        BinaryOperator::Plus { .. } => {
-            if l.is_number() && r.is_number() {
-                apply_math_op(l, r, |a, b| a + b)
-            } else {
-                concat_strings(l, r)
-            }
-        }
-        BinaryOperator::Minus { .. } => apply_math_op(l, r, |a, b| a - b),
-        BinaryOperator::Slash { .. } => apply_math_op(l, r, |a, b| a / b),
-        BinaryOperator::Star { .. } => apply_math_op(l, r, |a, b| a * b),
+    fn visit_class(&mut self, value: &ast::Class) -> EvalResult {
+        let name = value
+            .name()
+            .map(|ident| ident.name_str())
+            .unwrap_or(ANONYMOUS_CLASS_NAME);
+        let superclass = self.resolve_superclass(value)?;
+        let class = Rc::new(self.collect_class_methods(name, value, superclass));
+        self.init_static_fields(&class, value)?;
+        Ok(LoxObject::Class(class).into())
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], position: usize) -> EvalResult {
+        let mut map = LoxMap::with_capacity(entries.len());
+        for (key, value) in entries {
+            let key = unwrap_to_object(key.accept(self)?)?;
+            let key = MapKey::try_from(&key).map_err(|e| e.with_place(position))?;
+            let value = unwrap_to_object(value.accept(self)?)?;
+            map.insert(key, value);
+        }
+        Ok(LoxObject::from(map).into())
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr, position: usize) -> EvalResult {
+        let obj = unwrap_to_object(object.accept(self)?).map_err(|e| e.with_place(position))?;
+        let idx = unwrap_to_object(index.accept(self)?).map_err(|e| e.with_place(position))?;
+        let value = match obj {
+            LoxObject::Map(map) => {
+                let key = MapKey::try_from(&idx)?;
+                map.borrow().get(&key).cloned().ok_or_else(|| {
+                    RuntimeError::from(LoxError::IndexError(format!("key {} not found", key)))
+                })
+            }
+            LoxObject::Array(items) => {
+                let items = items.borrow();
+                array_index(&idx, items.len()).map(|i| items[i].clone())
+            }
+            _ => match obj.as_string() {
+                Some(s) => string_index(s, &idx),
+                None => Err(type_error("map, array or string", &obj.type_name())),
+            },
+        };
+        value.map(Eval::from).map_err(|e| e.with_place(position))
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        value: &Expr,
+        position: usize,
+    ) -> EvalResult {
+        let obj = unwrap_to_object(object.accept(self)?).map_err(|e| e.with_place(position))?;
+        let idx = unwrap_to_object(index.accept(self)?).map_err(|e| e.with_place(position))?;
+        let value = unwrap_to_object(value.accept(self)?).map_err(|e| e.with_place(position))?;
+        let result = match obj {
+            LoxObject::Map(map) => MapKey::try_from(&idx).map(|key| {
+                map.borrow_mut().insert(key, value.clone());
+            }),
+            LoxObject::Array(items) => {
+                let mut items = items.borrow_mut();
+                array_index(&idx, items.len()).map(|i| items[i] = value.clone())
+            }
+            _ => Err(type_error("map or array", &obj.type_name())),
+        };
+        result
+            .map(|_| value.into())
+            .map_err(|e| e.with_place(position))
+    }
+
+    fn visit_cached(&mut self, expr: &Expr, slot: usize) -> EvalResult {
+        if let Some(value) = self.invariant_cache.get(&slot) {
+            return Ok(value.clone().into());
+        }
+        let value = unwrap_to_object(expr.accept(self)?)?;
+        self.invariant_cache.insert(slot, value.clone());
+        Ok(value.into())
+    }
+
+    fn visit_cached_loop(&mut self, slots: &[usize], body: &Stmt) -> EvalResult {
+        // whatever was cached the last time this loop ran may be stale by now.
+        for slot in slots {
+            self.invariant_cache.remove(slot);
+        }
+        body.accept(self)
+    }
+}
+
+fn unary_op(value: &LoxObject, op: UnaryPrefix) -> Result<LoxObject, BinaryError> {
+    match op {
+        UnaryPrefix::Bang { .. } => Ok(value.truthy().into()),
+        UnaryPrefix::Minus { .. } => apply_math_op(value, &(-1.0).into(), false, |a, b| a * b),
+    }
+}
+
+fn binary_op(
+    l: &LoxObject,
+    r: &LoxObject,
+    op: BinaryOperator,
+    strict: bool,
+) -> Result<LoxObject, BinaryError> {
+    match op {
+        // addition is a special case where we need to handle string concatenation.
+        BinaryOperator::Plus { .. } => {
+            if l.is_number() && r.is_number() {
+                apply_math_op(l, r, strict, |a, b| a + b)
+            } else {
+                concat_strings(l, r)
+            }
+        }
+        BinaryOperator::Minus { .. } => apply_math_op(l, r, strict, |a, b| a - b),
+        BinaryOperator::Slash { .. } => apply_math_op(l, r, strict, |a, b| a / b),
+        BinaryOperator::Star { .. } => apply_math_op(l, r, strict, |a, b| a * b),
+        BinaryOperator::Power { .. } => apply_math_op(l, r, strict, |a, b| a.powf(b)),
         BinaryOperator::Greater { .. } => apply_comparison(l, r, |a, b| a > b),
         BinaryOperator::GreaterEqual { .. } => apply_comparison(l, r, |a, b| a >= b),
         BinaryOperator::Less { .. } => apply_comparison(l, r, |a, b| a < b),
@@ -483,16 +1163,29 @@ fn concat_strings(l: &LoxObject, r: &LoxObject) -> Result<LoxObject, BinaryError
     }
 }
 
-fn apply_math_op<F>(l: &LoxObject, r: &LoxObject, f: F) -> Result<LoxObject, BinaryError>
+// when `strict` is set, finite operands have to give a finite result. once a program has an inf
+// or NaN, which it can only get from a native or a non strict run, it is left to carry on with it.
+fn apply_math_op<F>(
+    l: &LoxObject,
+    r: &LoxObject,
+    strict: bool,
+    f: F,
+) -> Result<LoxObject, BinaryError>
 where
     F: FnOnce(f64, f64) -> f64,
 {
     let l_as_num = l.as_number();
     let r_as_num = r.as_number();
     match (l_as_num, r_as_num) {
-        (Some(a), Some(b)) => Ok(LoxObject::from(f(a, b))),
+        (Some(a), Some(b)) => {
+            let result = f(a, b);
+            if strict && !result.is_finite() && a.is_finite() && b.is_finite() {
+                return Err(BinaryError::NotFinite(result));
+            }
+            Ok(LoxObject::from(result))
+        }
         _ => {
-            if !l_as_num.is_some() {
+            if l_as_num.is_none() {
                 Err(BinaryError::LeftSide)
             } else {
                 Err(BinaryError::RightSide)
@@ -510,7 +1203,7 @@ where
     match (l_as_num, r_as_num) {
         (Some(a), Some(b)) => Ok(LoxObject::from(f(a, b))),
         _ => {
-            if !l_as_num.is_some() {
+            if l_as_num.is_none() {
                 Err(BinaryError::LeftSide)
             } else {
                 Err(BinaryError::RightSide)
@@ -528,23 +1221,27 @@ fn binary_op_error(
     let msg = match err_type {
         BinaryError::LeftSide => format!(
             "lefthand side incorrect type '{}' for op {}",
-            l.type_str(),
+            l.type_name(),
             op
         ),
         BinaryError::RightSide => format!(
             "righthand side incorrect type '{}' for op {}",
-            r.type_str(),
+            r.type_name(),
             op
         ),
         BinaryError::InvalidOperator => format!("invalid binary operator {}", op),
-        _ => format!("cannot add '{}' + {}'", l.type_str(), r.type_str()),
+        BinaryError::NotFinite(result) => {
+            let msg = format!("{} of two finite numbers gave {}", op, result);
+            return RuntimeError::from(LoxError::RangeError(msg)).with_place(op.position());
+        }
+        _ => format!("cannot add '{}' + {}'", l.type_name(), r.type_name()),
     };
 
     RuntimeError::from(LoxError::TypeError(msg)).with_place(op.position())
 }
 
 fn unary_prefix_error(l: &LoxObject, prefix: UnaryPrefix) -> RuntimeError {
-    let msg = format!("invalid type {} for prefix {}", l.type_str(), prefix);
+    let msg = format!("invalid type {} for prefix {}", l.type_name(), prefix);
     RuntimeError::from(LoxError::TypeError(msg)).with_place(prefix.position())
 }
 
@@ -558,6 +1255,20 @@ fn ref_error_prop_access(ident: &Identifier) -> RuntimeError {
     RuntimeError::from(LoxError::ReferenceError(msg)).with_place(ident.position())
 }
 
+// reading a property off something that can't have any, most often nil.
+fn ref_error_prop_not_obj(value: &LoxObject, property: &Identifier) -> RuntimeError {
+    let msg = format!(
+        "cannot read property '{}' of {}",
+        property.name_str(),
+        value.type_str()
+    );
+    RuntimeError::from(LoxError::ReferenceError(msg)).with_place(property.position())
+}
+
+fn not_callable(value: &LoxObject) -> RuntimeError {
+    type_error("function or class", &value.type_name())
+}
+
 fn type_error(expected: &str, recieved: &str) -> RuntimeError {
     LoxError::TypeError(format!(
         "expected type '{}' but recieved {}",
@@ -566,9 +1277,2371 @@ fn type_error(expected: &str, recieved: &str) -> RuntimeError {
     .into()
 }
 
+// arrays and strings are indexed by whole numbers, negative ones counting back from the end so
+// `-1` is the last item. `kind` names what is being indexed for the error messages.
+fn sequence_index(index: &LoxObject, len: usize, kind: &str) -> Result<usize, RuntimeError> {
+    let Some(n) = index.as_number() else {
+        return Err(type_error("number", &index.type_name()));
+    };
+    if n.fract() != 0.0 {
+        let msg = format!("{} index must be a whole number but got {}", kind, n);
+        return Err(LoxError::IndexError(msg).into());
+    }
+    let at = if n < 0.0 { n + len as f64 } else { n };
+    if at < 0.0 || at >= len as f64 {
+        let msg = format!("index {} out of bounds for {} of length {}", n, kind, len);
+        return Err(LoxError::IndexError(msg).into());
+    }
+    Ok(at as usize)
+}
+
+fn array_index(index: &LoxObject, len: usize) -> Result<usize, RuntimeError> {
+    sequence_index(index, len, "array")
+}
+
+// strings are indexed by character rather than byte.
+fn string_index(s: &str, index: &LoxObject) -> Result<LoxObject, RuntimeError> {
+    let at = sequence_index(index, s.chars().count(), "string")?;
+    let c = s.chars().nth(at).unwrap_or_default();
+    Ok(LoxObject::from(c.to_string()))
+}
+
 fn unwrap_to_object(eval: Eval) -> Result<LoxObject, RuntimeError> {
     match eval {
         Eval::Object(obj) => Ok(obj),
         _ => Err(type_error("object", eval.type_str())),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::tree::error::ParseError;
+    use crate::lang::tree::parser::Parser;
+    use crate::lang::tree::resolver::Resolver;
+
+    fn parse_and_resolve(src: &str) -> Vec<Stmt> {
+        let mut parser = Parser::new(src);
+        parser.parse();
+        assert!(!parser.had_errors(), "failed to parse {src}");
+        let stmts = parser.take_statements();
+        let mut resolver = Resolver::new();
+        for stmt in &stmts {
+            stmt.accept(&mut resolver).unwrap();
+        }
+        stmts
+    }
+
+    fn run(lox: &mut Lox, src: &str) -> Result<(), RuntimeError> {
+        lox.interpret(parse_and_resolve(src))
+    }
+
+    // somewhere for a test to hand the interpreter's output and read it back from afterwards.
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Captured {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn global_number(lox: &Lox, name: &str) -> f64 {
+        lox.get_global(name)
+            .and_then(|v| v.as_number())
+            .unwrap_or_else(|| panic!("global '{name}' is not a number"))
+    }
+
+    #[test]
+    fn test_function_return_values() {
+        let mut lox = Lox::new();
+        let src = r#"
+            fun bare() { return; }
+            fun value(x) { return x * 2; }
+            fun falls_off() { var unused = 1; unused + 1; }
+            fun empty() {}
+            var a = bare();
+            var b = value(21);
+            var c = falls_off();
+            var d = empty();
+        "#;
+        run(&mut lox, src).unwrap();
+        assert!(lox.get_global("a").unwrap().is_nil());
+        assert_eq!(global_number(&lox, "b"), 42.0);
+        assert!(lox.get_global("c").unwrap().is_nil());
+        assert!(lox.get_global("d").unwrap().is_nil());
+    }
+
+    #[test]
+    fn test_getters_run_on_access() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Circle {
+                init(r) { this.r = r; }
+                area { return 3 * this.r * this.r; }
+                grow { this.r = this.r + 1; return this.r; }
+                scale(n) { return this.r * n; }
+                static unit { return Circle(1); }
+            }
+            var c = Circle(2);
+            var area = c.area;
+            var grown = c.grow;
+            var scale = c.scale;
+            var scaled = scale(10);
+            var unit = Circle.unit.area;
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "area"), 12.0);
+        assert_eq!(global_number(&lox, "grown"), 3.0);
+        assert!(matches!(
+            lox.get_global("scale"),
+            Some(LoxObject::BoundMethod(..))
+        ));
+        assert_eq!(global_number(&lox, "scaled"), 30.0);
+        assert_eq!(global_number(&lox, "unit"), 3.0);
+    }
+
+    #[test]
+    fn test_run_line_recovers_from_runtime_errors() {
+        let mut lox = Lox::new();
+        lox.run_line("var a = 1;").unwrap();
+        let err = lox
+            .run_line("fun f() { { var b = 2; return b - nil; } } f();")
+            .unwrap_err();
+        let [LoxDiagnostic::Runtime(err)] = &err[..] else {
+            panic!("expected a single runtime error");
+        };
+        assert!(matches!(err.reason(), LoxError::TypeError(_)));
+        assert!(lox.frame.is_empty());
+        assert!(lox.call_stack().is_empty());
+        lox.run_line("var c = a + 1;").unwrap();
+        assert_eq!(global_number(&lox, "c"), 2.0);
+    }
+
+    #[test]
+    fn test_static_fields() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Math {
+                static PI = 3;
+                static TAU = Math.PI * 2;
+                static area(r) { return Math.PI * r * r; }
+            }
+            var pi = Math.PI;
+            var tau = Math.TAU;
+            Math.PI = 4;
+            var area = Math.area(1);
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "pi"), 3.0);
+        assert_eq!(global_number(&lox, "tau"), 6.0);
+        assert_eq!(global_number(&lox, "area"), 4.0);
+    }
+
+    #[test]
+    fn test_block_error_restores_scope() {
+        let mut lox = Lox::new();
+        let err = run(&mut lox, "{ var a = 1; { var b = a; b - nil; } }");
+        assert!(err.is_err());
+        assert!(lox.frame.is_empty());
+    }
+
+    #[test]
+    fn test_instance_field_initializers() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var start = 10;
+            class Point {
+                x = start;
+                y = this.x + 1;
+                init(z) { this.z = this.y + z; }
+            }
+            class Origin { x = 0; y = 0; }
+            var p = Point(5);
+            var a = Point(0);
+            a.x = 99;
+            var b = Point(0);
+            var x = p.x;
+            var y = p.y;
+            var z = p.z;
+            var bx = b.x;
+            var ox = Origin().x;
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "x"), 10.0);
+        assert_eq!(global_number(&lox, "y"), 11.0);
+        assert_eq!(global_number(&lox, "z"), 16.0);
+        assert_eq!(global_number(&lox, "bx"), 10.0);
+        assert_eq!(global_number(&lox, "ox"), 0.0);
+    }
+
+    #[test]
+    fn test_var_field_declarations() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Counter {
+                var count = 0;
+                var label;
+                static var made = 0;
+                init() { Counter.made = Counter.made + 1; }
+                bump() { this.count = this.count + 1; }
+            }
+            var c = Counter();
+            c.bump();
+            c.bump();
+            var fresh = Counter().count;
+            var count = c.count;
+            var label = c.label;
+            var made = Counter.made;
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "fresh"), 0.0);
+        assert_eq!(global_number(&lox, "count"), 2.0);
+        assert!(lox.get_global("label").unwrap().is_nil());
+        assert_eq!(global_number(&lox, "made"), 2.0);
+
+        for src in ["class A { var = 1; }", "class A { var x = 1 }"] {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            assert!(parser.had_errors(), "{src} should fail to parse");
+        }
+    }
+
+    #[test]
+    fn test_string_interning_option() {
+        let src = r#"
+            var a = intern("ab" + "c");
+            var b = intern("a" + "bc");
+            var c = "abc";
+            var d = "ab" + "c";
+            var same = a == b;
+        "#;
+        let shared = |lox: &Lox, x: &str, y: &str| {
+            let x = lox.get_global(x).unwrap();
+            let y = lox.get_global(y).unwrap();
+            std::ptr::eq(x.as_string().unwrap(), y.as_string().unwrap())
+        };
+
+        let mut lox = Lox::new();
+        run(&mut lox, src).unwrap();
+        assert!(!shared(&lox, "a", "b"));
+        assert!(lox.get_global("same").unwrap().truthy());
+
+        let mut lox = Lox::new().with_string_interning(true);
+        run(&mut lox, src).unwrap();
+        assert!(shared(&lox, "a", "b"));
+        assert!(shared(&lox, "a", "c"));
+        assert!(!shared(&lox, "a", "d"));
+        assert!(lox.get_global("same").unwrap().truthy());
+        assert!(run(&mut lox, "intern(1);").is_err());
+    }
+
+    #[test]
+    fn test_super_access() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Animal {
+                var legs = 4;
+                var sound = "...";
+                speak() { return this.sound; }
+                kind { return "animal"; }
+            }
+            class Bird < Animal {
+                var sound = "tweet";
+                init() { this.legs = 2; }
+                speak() { return "the " + super.kind + " says " + super.speak(); }
+                inherited() { return super.legs; }
+                shadowed() { return super.sound; }
+            }
+            class Parrot < Bird {
+                speak() { return super.speak() + "!"; }
+            }
+            var speech = Parrot().speak();
+            var legs = Bird().inherited();
+            var sound = Bird().shadowed();
+        "#;
+        run(&mut lox, src).unwrap();
+        let global = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(global("speech"), "the animal says tweet!");
+        assert_eq!(global_number(&lox, "legs"), 2.0);
+        assert_eq!(global("sound"), "tweet");
+
+        let err = run(
+            &mut lox,
+            "class A < Animal { m() { return super.missing; } } A().m();",
+        );
+        assert!(err.is_err());
+        let mut resolver = Resolver::new();
+        for src in ["super.x;", "class A { m() { return super.m(); } }"] {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            let stmts = parser.take_statements();
+            assert!(
+                resolver.resolve(&stmts).is_err(),
+                "{src} should not resolve"
+            );
+        }
+    }
+
+    #[test]
+    fn test_function_error_restores_caller_scope() {
+        let mut lox = Lox::new();
+        run(&mut lox, "fun boom(x) { var y = x; { return y - nil; } }").unwrap();
+        assert!(run(&mut lox, "{ var a = 1; boom(a); }").is_err());
+        assert!(lox.frame.is_empty());
+        assert!(lox.call_stack().is_empty());
+        run(&mut lox, "var b = 1; { var c = b + 1; b = c; }").unwrap();
+        assert_eq!(global_number(&lox, "b"), 2.0);
+    }
+
+    #[test]
+    fn test_inheritance_and_is_instance() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Animal {
+                legs = 4;
+                init(name) { this.name = name; }
+                speak() { return 1; }
+                describe() { return this.speak() + this.legs; }
+            }
+            class Dog < Animal {
+                speak() { return 10; }
+            }
+            class Rock {}
+            var d = Dog("rex");
+            var described = d.describe();
+            var dog_is_animal = is_instance(d, Animal);
+            var dog_is_dog = is_instance(d, Dog);
+            var animal_is_dog = is_instance(Animal("cat"), Dog);
+            var dog_is_rock = is_instance(d, Rock);
+            var number_is_rock = is_instance(1, Rock);
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "described"), 14.0);
+        let truthy = |name: &str| lox.get_global(name).unwrap().truthy();
+        assert!(truthy("dog_is_animal"));
+        assert!(truthy("dog_is_dog"));
+        assert!(!truthy("animal_is_dog"));
+        assert!(!truthy("dog_is_rock"));
+        assert!(!truthy("number_is_rock"));
+
+        let err = run(&mut lox, "is_instance(d, 1);").unwrap_err();
+        assert!(matches!(err.reason(), LoxError::TypeError(_)));
+        let err = run(&mut lox, "var NotClass = 1; class Bad < NotClass {}").unwrap_err();
+        assert!(matches!(err.reason(), LoxError::TypeError(_)));
+    }
+
+    #[test]
+    fn test_map_literals_and_indexing() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var m = {"a": 1, "b": 2, 3: "three", true: nil,};
+            var a = m["a"];
+            m["a"] = 10;
+            m["c"] = m["a"] + m["b"];
+            var c = m["c"];
+            var keys = keys({"x": 1, "y": 2});
+            var first_key = keys[0];
+            var empty = {};
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "a"), 1.0);
+        assert_eq!(global_number(&lox, "c"), 12.0);
+        let first = lox.get_global("first_key").unwrap();
+        assert_eq!(first.as_string().map(|s| s.as_str()), Some("x"));
+        assert_eq!(
+            lox.get_global("keys").unwrap().to_string(),
+            "[\"x\", \"y\"]"
+        );
+        assert_eq!(lox.get_global("empty").unwrap().to_string(), "{}");
+        assert_eq!(
+            lox.get_global("m").unwrap().to_string(),
+            "{ \"a\": 10, \"b\": 2, \"c\": 12, 3: \"three\", true: nil }"
+        );
+
+        let err = run(&mut lox, "m[clock] = 1;").unwrap_err();
+        assert!(matches!(err.reason(), LoxError::TypeError(_)));
+        let err = run(&mut lox, "m[\"missing\"];").unwrap_err();
+        assert!(matches!(err.reason(), LoxError::IndexError(_)));
+        let err = run(&mut lox, "keys[2];").unwrap_err();
+        assert!(matches!(err.reason(), LoxError::IndexError(_)));
+    }
+
+    #[test]
+    fn test_power_operator() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var right_assoc = 2 ** 3 ** 2;
+            var negative_exponent = 2 ** -2;
+            var negated = -2 ** 2;
+            var precedence = 3 * 2 ** 2;
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "right_assoc"), 512.0);
+        assert_eq!(global_number(&lox, "negative_exponent"), 0.25);
+        assert_eq!(global_number(&lox, "negated"), -4.0);
+        assert_eq!(global_number(&lox, "precedence"), 12.0);
+    }
+
+    #[test]
+    fn test_deep_eq() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class P { init(x) { this.x = x; } }
+            class Q { init(x) { this.x = x; } }
+            var nested = deep_eq({"a": {"b": keys({1: 0, 2: 0})}}, {"a": {"b": keys({1: 0, 2: 0})}});
+            var differ = deep_eq({"a": {"b": keys({1: 0})}}, {"a": {"b": keys({2: 0})}});
+            var extra_key = deep_eq({"a": 1}, {"a": 1, "b": 2});
+            var instances = deep_eq(P({"v": 1}), P({"v": 1}));
+            var identity = P(1) == P(1);
+            var other_class = deep_eq(P(1), Q(1));
+            var a = {}; a["self"] = a;
+            var b = {}; b["self"] = b;
+            var cyclic = deep_eq(a, b);
+            var c = {"n": 1}; c["self"] = c;
+            var d = {"n": 2}; d["self"] = d;
+            var cyclic_differ = deep_eq(c, d);
+        "#;
+        run(&mut lox, src).unwrap();
+        let truthy = |name: &str| lox.get_global(name).unwrap().truthy();
+        assert!(truthy("nested"));
+        assert!(!truthy("differ"));
+        assert!(!truthy("extra_key"));
+        assert!(truthy("instances"));
+        assert!(!truthy("identity"));
+        assert!(!truthy("other_class"));
+        assert!(truthy("cyclic"));
+        assert!(!truthy("cyclic_differ"));
+    }
+
+    #[test]
+    fn test_invariant_caching_matches_plain_run() {
+        let src = "
+            var a = 3;
+            var total = 0;
+            var i = 0;
+            while (i < 10) { total = total + a * 2 + (1 + 1); i = i + 1; }
+            var b = 1;
+            fun bump() { b = b + 1; return 0; }
+            var other = 0;
+            var j = 0;
+            while (j < 5) { other = other + b * 10 + bump(); j = j + 1; }
+            var k = 0;
+            var nested = 0;
+            while (k < 3) {
+                var m = 0;
+                while (m < 3) { nested = nested + k * a; m = m + 1; }
+                k = k + 1;
+            }
+            // field initializers run in the loop too, static ones as the class is declared.
+            var si = 0;
+            var sn = 0;
+            while (si < 3 and sn < 10) { class A { static s = si = si + 1; } sn = sn + 1; }
+            var ei = 0;
+            var en = 0;
+            while (ei < 3 and en < 10) { var E = class { static s = ei = ei + 1; }; en = en + 1; }
+        ";
+        let mut plain = Lox::new();
+        run(&mut plain, src).unwrap();
+        let mut cached = Lox::new().with_invariant_caching(true);
+        run(&mut cached, src).unwrap();
+        for name in ["total", "other", "nested", "si", "sn", "ei", "en"] {
+            assert_eq!(
+                global_number(&plain, name),
+                global_number(&cached, name),
+                "{name}"
+            );
+        }
+        assert_eq!(global_number(&cached, "sn"), 3.0);
+        assert_eq!(global_number(&cached, "en"), 3.0);
+        assert_eq!(global_number(&cached, "total"), 80.0);
+        assert_eq!(global_number(&cached, "other"), 150.0);
+        assert_eq!(global_number(&cached, "nested"), 27.0);
+    }
+
+    #[test]
+    fn test_postfix_increment_yields_old_value() {
+        let mut lox = Lox::new();
+        run(
+            &mut lox,
+            "var i = 5; var before = i++; var j = 5; var down = j--; var n = 0; for (var k = 0; k < 4; k++) { n++; }",
+        )
+        .unwrap();
+        assert_eq!(global_number(&lox, "before"), 5.0);
+        assert_eq!(global_number(&lox, "i"), 6.0);
+        assert_eq!(global_number(&lox, "down"), 5.0);
+        assert_eq!(global_number(&lox, "j"), 4.0);
+        assert_eq!(global_number(&lox, "n"), 4.0);
+    }
+
+    #[test]
+    fn test_postfix_increment_is_exact() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var x = 0.1; var old_x = x++;
+            var big = 9007199254740993; var old_big = big--;
+            var s = "a";
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "old_x"), 0.1);
+        assert_eq!(global_number(&lox, "x"), 1.1);
+        assert_eq!(global_number(&lox, "old_big"), 9007199254740992.0);
+        assert_eq!(global_number(&lox, "big"), 9007199254740991.0);
+        // a string is rejected before anything is stored.
+        let err = run(&mut lox, "s++;").unwrap_err();
+        assert_eq!(
+            err.reason().to_string(),
+            "TypeError: invalid type string for postfix '++'"
+        );
+        assert_eq!(lox.get_global("s").unwrap().to_string(), "a");
+    }
+
+    #[test]
+    fn test_minus_minus_before_an_operand_is_subtraction() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var a = 5; var b = 2;
+            var lit = 1--1;
+            var vars = a--b;
+            var neg = --a;
+            var called = a--(b);
+            var dec = a-- - b;
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "lit"), 2.0);
+        assert_eq!(global_number(&lox, "vars"), 7.0);
+        assert_eq!(global_number(&lox, "neg"), 5.0);
+        assert_eq!(global_number(&lox, "called"), 7.0);
+        // followed by a `-`, `--` decrements like it would in C.
+        assert_eq!(global_number(&lox, "dec"), 3.0);
+        assert_eq!(global_number(&lox, "a"), 4.0);
+    }
+
+    #[test]
+    fn test_postfix_increment_needs_a_variable() {
+        for src in ["1++;", "(a)--;", "a.b++;", "f()++;"] {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            assert!(
+                matches!(
+                    parser.errors().first(),
+                    Some(ParseError::UnexpectedAssignment { .. })
+                ),
+                "{src} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn test_interpret_value_returns_last_expression() {
+        let mut lox = Lox::new();
+        let value = lox
+            .interpret_value(parse_and_resolve("var a = 1; 1 + 2;"))
+            .unwrap();
+        assert_eq!(value.as_number(), Some(3.0));
+        let value = lox
+            .interpret_value(parse_and_resolve("1 + 2; var b = 3;"))
+            .unwrap();
+        assert!(value.is_nil());
+    }
+
+    #[test]
+    fn test_call_the_result_of_any_expression() {
+        let mut lox = Lox::new();
+        run(
+            &mut lox,
+            "
+            fun one() { return 1; }
+            fun get() { return one; }
+            class Holder { init() { this.f = one; } }
+            var grouped = (get())();
+            var property = Holder().f();
+            var mapped = {\"f\": one}[\"f\"]();
+            var curried = get()();
+            ",
+        )
+        .unwrap();
+        for name in ["grouped", "property", "mapped", "curried"] {
+            assert_eq!(global_number(&lox, name), 1.0, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_uncallable_callee_error_points_at_callee() {
+        let cases = [
+            ("var a = 1; a();", "a()"),
+            ("var m = {\"f\": 1}; m[\"f\"]();", "[\"f\"]()"),
+            (
+                "class A { init() { this.f = 1; } } var x = A(); (x.f)();",
+                "f)()",
+            ),
+        ];
+        for (src, at) in cases {
+            let mut lox = Lox::new();
+            let err = run(&mut lox, src).unwrap_err();
+            assert_eq!(err.place(), src.rfind(at), "{src}");
+        }
+    }
+
+    #[test]
+    fn test_lambda_shorthand() {
+        let mut lox = Lox::new();
+        run(
+            &mut lox,
+            "
+            fun apply(f, x) { return f(x); }
+            var base = 10;
+            var add = \\(a, b) => a + b + base;
+            var sum = add(1, 2);
+            var applied = apply(\\(x) => x * 2, 4);
+            var constant = (\\() => 7)();
+            var curried = (\\(a) => \\(b) => a - b)(5)(3);
+            ",
+        )
+        .unwrap();
+        assert_eq!(global_number(&lox, "sum"), 13.0);
+        assert_eq!(global_number(&lox, "applied"), 8.0);
+        assert_eq!(global_number(&lox, "constant"), 7.0);
+        assert_eq!(global_number(&lox, "curried"), 2.0);
+    }
+
+    #[test]
+    fn test_assert_natives() {
+        let mut lox = Lox::new();
+        run(
+            &mut lox,
+            "assert(1 < 2); assert(true, \"fine\"); assert_eq(1 + 1, 2);",
+        )
+        .unwrap();
+
+        let failure = |src: &str| {
+            let err = run(&mut Lox::new(), src).unwrap_err();
+            assert!(
+                matches!(err.reason(), LoxError::AssertionError(_)),
+                "{src} raised {err}"
+            );
+            err.reason().to_string()
+        };
+        assert_eq!(
+            failure("assert(nil);"),
+            "AssertionError: assertion failed, nil is falsey"
+        );
+        assert_eq!(
+            failure("assert(1 > 2, \"math is broken\");"),
+            "AssertionError: math is broken"
+        );
+        assert_eq!(
+            failure("assert_eq(1, \"1\");"),
+            "AssertionError: 1 != \"1\""
+        );
+        assert_eq!(
+            failure("assert_eq(1, 2, \"sum\");"),
+            "AssertionError: sum: 1 != 2"
+        );
+        assert!(run(&mut Lox::new(), "assert();").is_err());
+    }
+
+    #[test]
+    fn test_format_native() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var plain = format("no placeholders");
+            var filled = format("{} + {} = {}", 1, 2, 1 + 2);
+            var braces = format("{{{}}}", "x");
+            var values = format("{} {}", nil, {"a": true});
+        "#;
+        run(&mut lox, src).unwrap();
+        let string = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(string("plain"), "no placeholders");
+        assert_eq!(string("filled"), "1 + 2 = 3");
+        assert_eq!(string("braces"), "{x}");
+        assert_eq!(string("values"), r#"nil { "a": true }"#);
+
+        for src in [
+            r#"format("{} {}", 1);"#,
+            r#"format("{}", 1, 2);"#,
+            r#"format("{", 1);"#,
+            "format(1);",
+            "write(1, 2);",
+        ] {
+            let err = run(&mut Lox::new(), src).unwrap_err();
+            assert!(
+                matches!(
+                    err.reason(),
+                    LoxError::NativeError(NativeError::InvalidArguments(_))
+                ),
+                "{src} raised {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_calling_a_number_points_at_the_callee() {
+        let src = "var x = 5;\nx();";
+        let mut lox = Lox::new();
+        let err = run(&mut lox, src).unwrap_err();
+        assert_eq!(
+            err.reason().to_string(),
+            "TypeError: expected type 'function or class' but recieved number"
+        );
+        assert_eq!(err.place(), src.find("x()"));
+        let block = err.code_block(src).unwrap();
+        assert!(block.ends_with("2 | x();\n  | ^"), "{block}");
+
+        let err = run(&mut lox, "5();").unwrap_err();
+        assert!(err.reason().to_string().ends_with("recieved number"));
+        assert_eq!(err.place(), Some(0));
+    }
+
+    #[test]
+    fn test_number_format_option() {
+        let format = NumberFormat::default().with_precision(2);
+        let mut lox = Lox::new().with_number_format(format);
+        let src = r#"
+            var third = string(1 / 3);
+            var whole = string(4);
+            var formatted = format("{} of {}", 1, "x");
+            var text = string("1.23456");
+        "#;
+        run(&mut lox, src).unwrap();
+        let string = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(string("third"), "0.33");
+        assert_eq!(string("whole"), "4.00");
+        assert_eq!(string("formatted"), "1.00 of x");
+        assert_eq!(string("text"), "1.23456");
+        assert_eq!(lox.display(&LoxObject::from(2.5)), "2.50");
+    }
+
+    #[test]
+    fn test_statements_starting_with_fun_or_class() {
+        let mut lox = Lox::new();
+        // telling a declaration from an expression statement takes looking past `fun`/`class`.
+        let src = "
+            fun named() { return 1; }
+            class Named { static one() { return 1; } }
+            var r = 0;
+            fun (x) { r = x; }(5);
+            fun () {};
+            \\(x) => x;
+            class { static set() { r = r + 1; } }.set();
+            var total = named() + Named.one() + r;
+        ";
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "total"), 8.0);
+
+        let mut parser = Parser::new("fun (x) { return x; }");
+        parser.parse();
+        assert!(
+            parser.had_errors(),
+            "anonymous function statements need a semicolon"
+        );
+    }
+
+    #[test]
+    fn test_bare_expression_option() {
+        let mut strict = Parser::new("1 + 2");
+        strict.parse();
+        assert!(strict.had_errors());
+
+        let mut parser = Parser::new("1 + 2").with_bare_expression(true);
+        parser.parse();
+        assert!(!parser.had_errors());
+        let value = Lox::new()
+            .interpret_value(parser.take_statements())
+            .unwrap();
+        assert_eq!(value.as_number(), Some(3.0));
+
+        // only a trailing expression can go without, everything else is as strict as before.
+        for src in ["1 + 2 3;", "print 1", "var a = 1"] {
+            let mut parser = Parser::new(src).with_bare_expression(true);
+            parser.parse();
+            assert!(parser.had_errors(), "{src} should not parse");
+        }
+        let mut lox = Lox::new();
+        lox.run_line("var a = 2;").unwrap();
+        assert_eq!(lox.run_line("a * 4").unwrap().as_number(), Some(8.0));
+    }
+
+    #[test]
+    fn test_sets() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var s = set(1, 2, 2, "two", true, 1);
+            var initial = size(s);
+            var added = add(s, 3);
+            var again = add(s, 3);
+            var has_two = has(s, "two");
+            var has_fn = has(s, clock);
+            var removed = remove(s, 1);
+            var missing = remove(s, 1);
+            var final = size(s);
+            var same = deep_eq(set(1, 2), set(2, 1));
+            var shown = string(set("b", "a"));
+        "#;
+        run(&mut lox, src).unwrap();
+        let truthy = |name: &str| lox.get_global(name).unwrap().truthy();
+        assert_eq!(global_number(&lox, "initial"), 4.0);
+        assert!(truthy("added"));
+        assert!(!truthy("again"));
+        assert!(truthy("has_two"));
+        assert!(!truthy("has_fn"));
+        assert!(truthy("removed"));
+        assert!(!truthy("missing"));
+        assert_eq!(global_number(&lox, "final"), 4.0);
+        assert!(truthy("same"));
+        assert_eq!(
+            lox.get_global("shown").unwrap().to_string(),
+            r#"set("a", "b")"#
+        );
+
+        for src in ["set(clock);", "add(set(), chars(\"a\"));", "add({}, 1);"] {
+            assert!(run(&mut Lox::new(), src).is_err(), "{src} should fail");
+        }
+    }
+
+    #[test]
+    fn test_for_loop_binding_per_iteration() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var fns = {};
+            for (var i = 0; i < 3; i = i + 1) {
+                fns[i] = fun() { return i; };
+            }
+            var first = fns[0]();
+            var second = fns[1]();
+            var third = fns[2]();
+
+            // closures created in the same iteration still share it.
+            var get;
+            var bump;
+            for (var j = 0; j < 1; j = j + 1) {
+                get = fun() { return j; };
+                bump = fun() { j = j + 10; };
+            }
+            bump();
+            var shared = get();
+
+            var total = 0;
+            for (var k = 0; k < 5; k = k + 1) {
+                if (k == 3) break;
+                total = total + k;
+            }
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "first"), 0.0);
+        assert_eq!(global_number(&lox, "second"), 1.0);
+        assert_eq!(global_number(&lox, "third"), 2.0);
+        assert_eq!(global_number(&lox, "shared"), 10.0);
+        assert_eq!(global_number(&lox, "total"), 3.0);
+    }
+
+    #[test]
+    fn test_arity_native() {
+        let mut lox = Lox::new();
+        let src = r#"
+            fun add(a, b) { return a + b; }
+            var none = arity(fun() {});
+            var two = arity(add);
+            var lambda = arity(\(x) => x);
+            var native = arity(deep_eq);
+            var variadic = arity(format);
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "none"), 0.0);
+        assert_eq!(global_number(&lox, "two"), 2.0);
+        assert_eq!(global_number(&lox, "lambda"), 1.0);
+        assert_eq!(global_number(&lox, "native"), 2.0);
+        assert!(lox.get_global("variadic").unwrap().is_nil());
+
+        for src in ["arity(5);", "class A {} arity(A());", "arity();"] {
+            assert!(run(&mut Lox::new(), src).is_err(), "{src} should fail");
+        }
+    }
+
+    #[test]
+    fn test_is_callable_native() {
+        let mut lox = Lox::new();
+        let src = r#"
+            fun f() {}
+            class A { method() {} }
+            var a = A();
+            var function = is_callable(f);
+            var lambda = is_callable(\() => 1);
+            var native = is_callable(clock);
+            var constructor = is_callable(A);
+            var method = is_callable(a.method);
+            var number = is_callable(5);
+            var text = is_callable("f");
+            var instance = is_callable(a);
+            var nothing = is_callable(nil);
+        "#;
+        run(&mut lox, src).unwrap();
+        let truthy = |name: &str| lox.get_global(name).unwrap().truthy();
+        for name in ["function", "lambda", "native", "constructor", "method"] {
+            assert!(truthy(name), "{name} should be callable");
+        }
+        for name in ["number", "text", "instance", "nothing"] {
+            assert!(!truthy(name), "{name} should not be callable");
+        }
+        assert!(run(&mut Lox::new(), "is_callable();").is_err());
+    }
+
+    #[test]
+    fn test_string_indexing() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var s = "héllo";
+            var first = s[0];
+            var accent = s[1];
+            var last = s[-1];
+            var from_end = s[-5];
+            var emoji = "a🦀b"[1];
+        "#;
+        run(&mut lox, src).unwrap();
+        let global = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(global("first"), "h");
+        assert_eq!(global("accent"), "é");
+        assert_eq!(global("last"), "o");
+        assert_eq!(global("from_end"), "h");
+        assert_eq!(global("emoji"), "🦀");
+
+        for src in [
+            r#""abc"[3];"#,
+            r#""abc"[-4];"#,
+            r#""abc"[1.5];"#,
+            r#""abc"["a"];"#,
+        ] {
+            assert!(run(&mut lox, src).is_err(), "{src} should fail");
+        }
+        let err = run(&mut lox, r#""abc"[5];"#).unwrap_err();
+        assert!(matches!(err.reason(), LoxError::IndexError(_)));
+        assert!(run(&mut lox, r#"var t = "abc"; t[0] = "z";"#).is_err());
+    }
+
+    #[test]
+    fn test_bound_methods_keep_their_receiver() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Counter {
+                init() { this.count = 0; }
+                bump() { this.count = this.count + 1; return this.count; }
+            }
+            var a = Counter();
+            var b = Counter();
+            var bump = a.bump;
+            bump();
+            bump();
+            var count = a.count;
+            var untouched = b.count;
+            var same = a.bump == a.bump;
+            var other = a.bump == b.bump;
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "count"), 2.0);
+        assert_eq!(global_number(&lox, "untouched"), 0.0);
+        assert!(lox.get_global("same").unwrap().truthy());
+        assert!(!lox.get_global("other").unwrap().truthy());
+    }
+
+    #[test]
+    fn test_method_bound_to_each_instance() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Named {
+                init(name) { this.name = name; }
+                get() { return this.name; }
+            }
+            var a = Named("a");
+            var b = Named("b");
+            var from_a = a.get();
+            var from_b = b.get();
+            // a method bound to `b` stays bound to `b` when read back off `a`.
+            a.borrowed = b.get;
+            var borrowed = a.borrowed();
+            var again = a.get();
+        "#;
+        run(&mut lox, src).unwrap();
+        let global = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(global("from_a"), "a");
+        assert_eq!(global("from_b"), "b");
+        assert_eq!(global("borrowed"), "b");
+        assert_eq!(global("again"), "a");
+    }
+
+    #[test]
+    fn test_chars_native() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var ascii = chars("abc");
+            var mixed = chars("né🦀");
+            var empty = chars("");
+        "#;
+        run(&mut lox, src).unwrap();
+        let global = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(global("ascii"), r#"["a", "b", "c"]"#);
+        assert_eq!(global("mixed"), r#"["n", "é", "🦀"]"#);
+        assert_eq!(global("empty"), "[]");
+
+        for src in ["chars(1);", "chars();", r#"chars("a", "b");"#] {
+            assert!(run(&mut lox, src).is_err(), "{src} should fail");
+        }
+    }
+
+    #[test]
+    fn test_constant_folding_matches_unfolded() {
+        let src = r#"
+            var seconds = 2 * 60 * 60;
+            var mixed = (1 + 2) * 3 - 4 / 8 ** 2;
+            var negated = -(2 + 3);
+            var joined = "con" + "cat" + "enate";
+            var compared = 1 + 1 < 3 == true;
+            var different = nil != false;
+            var inf = 1 / 0;
+            fun area(r) { return 3 * 2 * r; }
+            var in_function = area(2);
+        "#;
+        let names = [
+            "seconds",
+            "mixed",
+            "negated",
+            "joined",
+            "compared",
+            "different",
+            "inf",
+            "in_function",
+        ];
+        let mut plain = Lox::new();
+        run(&mut plain, src).unwrap();
+        let mut folded = Lox::new().with_constant_folding(true);
+        run(&mut folded, src).unwrap();
+        for name in names {
+            assert_eq!(
+                plain.get_global(name),
+                folded.get_global(name),
+                "{name} differs once folded"
+            );
+        }
+
+        // type errors are left to happen at runtime, in the same place.
+        let src = r#"var x = 1;
+            var y = "a" + 1;"#;
+        let plain = run(&mut Lox::new(), src).unwrap_err();
+        let folded = run(&mut Lox::new().with_constant_folding(true), src).unwrap_err();
+        assert_eq!(plain.to_string(), folded.to_string());
+    }
+
+    #[test]
+    fn test_shadowed_native_warnings() {
+        let src = r#"
+            fun clock() { return 42; }
+            var now = clock();
+            fun f() {
+                var string = "local";
+                return string;
+            }
+            f();
+            f();
+            var fine = 1;
+        "#;
+        let mut lox = Lox::new();
+        run(&mut lox, src).unwrap();
+        assert!(lox.warnings().is_empty());
+
+        let mut lox = Lox::new().with_shadowed_native_warnings(true);
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "now"), 42.0);
+        let warnings = lox.take_warnings();
+        let messages: Vec<_> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "warning: 'clock' shadows the native function of the same name",
+                "warning: 'string' shadows the native function of the same name",
+            ]
+        );
+        assert_eq!(warnings[0].place(), src.find("clock").unwrap());
+        assert!(lox.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_run_source_collects_parse_warnings() {
+        let src = "var x; var y; if (x = 1) y = x;";
+        let mut lox = Lox::new();
+        lox.run_source(src).unwrap();
+        let warnings = lox.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].place(), src.find("x =").unwrap());
+        assert!(
+            warnings[0]
+                .to_string()
+                .starts_with("warning: assignment used as a condition")
+        );
+        assert!(warnings[0].code_block(src).ends_with("^"));
+
+        lox.run_line("while ((x = false)) {}").unwrap();
+        assert!(lox.warnings().is_empty());
+        lox.run_line("while (x = false) {}").unwrap();
+        assert_eq!(lox.warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_field_existence_and_deletion() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Point {
+                init(x) { this.x = x; }
+                norm() { return this.x; }
+            }
+            var p = Point(3);
+            var before = has_field(p, "x");
+            var missing = has_field(p, "y");
+            var method = has_field(p, "norm");
+            var deleted = delete_field(p, "x");
+            var after = has_field(p, "x");
+            var again = delete_field(p, "x");
+            var kept = delete_field(p, "norm");
+            p.x = 5;
+            var restored = has_field(p, "x");
+            var still_callable = p.norm();
+        "#;
+        run(&mut lox, src).unwrap();
+        let truthy = |name: &str| lox.get_global(name).unwrap().truthy();
+        assert!(truthy("before"));
+        assert!(!truthy("missing"));
+        assert!(!truthy("method"));
+        assert!(truthy("deleted"));
+        assert!(!truthy("after"));
+        assert!(!truthy("again"));
+        assert!(!truthy("kept"));
+        assert!(truthy("restored"));
+        assert_eq!(global_number(&lox, "still_callable"), 5.0);
+
+        let err = run(&mut lox, r#"has_field(nil, "x");"#).unwrap_err();
+        assert!(matches!(err.reason(), LoxError::TypeError(_)));
+        for src in [
+            r#"delete_field(1, "x");"#,
+            "has_field(p, 1);",
+            "has_field(p);",
+        ] {
+            assert!(run(&mut lox, src).is_err(), "{src} should fail");
+        }
+    }
+
+    #[test]
+    fn test_return_escapes_nested_blocks_and_loops() {
+        let src = r#"
+            fun f() { { { return 1; } } }
+            fun from_while() {
+                var i = 0;
+                while (true) {
+                    { if (i == 3) { { return i; } } }
+                    i = i + 1;
+                }
+                return -1;
+            }
+            fun from_for() {
+                for (var i = 0; i < 10; i = i + 1) {
+                    for (var j = 0; j < 10; j = j + 1) {
+                        { var k = i * 10 + j; if (k == 42) return k; }
+                    }
+                }
+                return -1;
+            }
+            fun after_break() {
+                var n = 0;
+                while (true) { { n = n + 1; break; } }
+                { return n + 10; }
+            }
+            var nested = f();
+            var while_value = from_while();
+            var for_value = from_for();
+            var break_value = after_break();
+        "#;
+        // cached loops wrap the loop they came from, they have to pass control through too.
+        for mut lox in [Lox::new(), Lox::new().with_invariant_caching(true)] {
+            run(&mut lox, src).unwrap();
+            assert_eq!(global_number(&lox, "nested"), 1.0);
+            assert_eq!(global_number(&lox, "while_value"), 3.0);
+            assert_eq!(global_number(&lox, "for_value"), 42.0);
+            assert_eq!(global_number(&lox, "break_value"), 11.0);
+            // nothing should be left behind by returning through all those scopes.
+            assert!(lox.call_stack().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_profiler_counts_calls_per_function() {
+        let src = "
+            fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            var square = fun (x) { return x * x; };
+            var result = fib(10) + square(3);
+        ";
+        let mut lox = Lox::new().with_profiler(true);
+        lox.set_source(src);
+        run(&mut lox, src).unwrap();
+        let profile = lox.profile().unwrap();
+        let calls: HashMap<_, _> = profile.iter().map(|f| (f.name.as_str(), f.calls)).collect();
+        assert_eq!(calls["fib"], 177);
+        assert_eq!(calls["fn@3:26"], 1);
+        assert_eq!(profile.len(), 2);
+        // a call that errors part way through is still counted.
+        assert!(run(&mut lox, "fib(nil);").is_err());
+        let fib = lox.profile().unwrap();
+        assert_eq!(fib.iter().find(|f| f.name == "fib").unwrap().calls, 178);
+
+        // methods sharing a name on different classes are profiled apart.
+        let src = "
+            class Square { area() { return 4; } }
+            class Circle { area() { return 3; } }
+            var total = Square().area() + Circle().area() + Circle().area();
+        ";
+        let mut lox = Lox::new().with_profiler(true);
+        run(&mut lox, src).unwrap();
+        let mut areas: Vec<_> = lox
+            .profile()
+            .unwrap()
+            .into_iter()
+            .filter(|f| f.name == "area")
+            .map(|f| f.calls)
+            .collect();
+        areas.sort();
+        assert_eq!(areas, [1, 2]);
+
+        assert!(Lox::new().profile().is_none());
+    }
+
+    #[test]
+    fn test_chained_assignment() {
+        let mut lox = Lox::new();
+        run(
+            &mut lox,
+            "
+            class Box {}
+            var made = 0;
+            var box = Box();
+            fun get_box() { made = made + 1; return box; }
+            var a; var b; var c; var y;
+            a = b = c = 3;
+            get_box().x = y = 5;
+            var z = box.other = 7;
+            var inner = box.x;
+            var other = box.other;
+            ",
+        )
+        .unwrap();
+        for (name, expected) in [
+            ("a", 3.0),
+            ("b", 3.0),
+            ("c", 3.0),
+            ("y", 5.0),
+            ("inner", 5.0),
+            ("z", 7.0),
+            ("other", 7.0),
+            ("made", 1.0),
+        ] {
+            assert_eq!(global_number(&lox, name), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_property_access_on_primitives() {
+        for (src, receiver) in [
+            ("var a = nil;\nprint a.name;", "nil"),
+            ("var a = 1;\nprint a.name;", "number"),
+            ("var a = \"text\";\nprint a.name;", "string"),
+        ] {
+            let mut lox = Lox::new();
+            lox.set_source(src);
+            let err = run(&mut lox, src).unwrap_err();
+            assert_eq!(
+                err.reason().to_string(),
+                format!("ReferenceError: cannot read property 'name' of {receiver}")
+            );
+            // the error points at the property, not the receiver.
+            assert_eq!(err.place(), Some(src.rfind("name").unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_local_shadows_parameter() {
+        let mut lox = Lox::new();
+        run(
+            &mut lox,
+            "
+            fun f(x) { var x = x + 1; return x; }
+            var result = f(1);
+            ",
+        )
+        .unwrap();
+        assert_eq!(global_number(&lox, "result"), 2.0);
+    }
+
+    #[test]
+    fn test_array_indexing() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var a = chars("abc");
+            var first = a[0];
+            var last = a[-1];
+            var from_end = a[-3];
+            a[-1] = "z";
+            var replaced = a[2];
+        "#;
+        run(&mut lox, src).unwrap();
+        let global = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(global("first"), "a");
+        assert_eq!(global("last"), "c");
+        assert_eq!(global("from_end"), "a");
+        assert_eq!(global("replaced"), "z");
+
+        for (src, msg) in [
+            ("a[3];", "index 3 out of bounds for array of length 3"),
+            ("a[-4];", "index -4 out of bounds for array of length 3"),
+            ("a[1.5];", "array index must be a whole number but got 1.5"),
+            (
+                "a[-0.5] = 1;",
+                "array index must be a whole number but got -0.5",
+            ),
+        ] {
+            let err = run(&mut lox, src).unwrap_err();
+            assert_eq!(
+                err.reason().to_string(),
+                format!("IndexError: {msg}"),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_function_display() {
+        let mut lox = Lox::new();
+        lox.register_native("apply", apply);
+        let src = r#"
+            fun fib(n) { return n; }
+            class Greeter { greet(a, b) {} }
+            var named = string(fib);
+            var anonymous = string(fun () {});
+            var method = string(Greeter().greet);
+            var native = string(clock);
+            var registered = string(apply);
+        "#;
+        run(&mut lox, src).unwrap();
+        let global = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(global("named"), "<fn fib/1>");
+        assert_eq!(global("anonymous"), "<fn anonymous/0>");
+        assert_eq!(global("method"), "<fn greet/2>");
+        assert_eq!(global("native"), "<native fn clock>");
+        assert_eq!(global("registered"), "<native fn apply>");
+    }
+
+    #[test]
+    fn test_closure_captures_this() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Counter {
+                init() { this.count = 1; }
+                reader() { return fun () { return this.count; } }
+                nested() {
+                    var scale = 10;
+                    return fun () { return \() => this.count * scale }
+                }
+            }
+            var c = Counter();
+            var read = c.reader();
+            var scaled = c.nested()();
+            var before = read();
+            c.count = 5;
+            var after = read();
+            var nested = scaled();
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "before"), 1.0);
+        assert_eq!(global_number(&lox, "after"), 5.0);
+        assert_eq!(global_number(&lox, "nested"), 50.0);
+    }
+
+    #[test]
+    fn test_strict_math() {
+        let mut lox = Lox::new();
+        run(
+            &mut lox,
+            "var big = 10 ** 300 * 10 ** 300; var nan = 0 / 0;",
+        )
+        .unwrap();
+        assert_eq!(global_number(&lox, "big"), f64::INFINITY);
+        assert!(global_number(&lox, "nan").is_nan());
+
+        // folding must not hide the error by working the result out ahead of time.
+        for folding in [false, true] {
+            let mut lox = Lox::new()
+                .with_strict_math(true)
+                .with_constant_folding(folding);
+            for (src, op, result) in [
+                ("var big = 10 ** 300 * 10 ** 300;", "*", "inf"),
+                ("var nan = 0.0 / 0.0;", "/", "NaN"),
+            ] {
+                let err = run(&mut lox, src).unwrap_err();
+                assert_eq!(
+                    err.reason().to_string(),
+                    format!("RangeError: '{op}' of two finite numbers gave {result}")
+                );
+                assert_eq!(err.place(), src.find(&format!(" {op} ")).map(|i| i + 1));
+            }
+            run(&mut lox, "var fine = 10 ** 300 + 1;").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_errors_do_not_cascade() {
+        let src = "
+            fun f() {
+                var x = 1 +;
+                print x;
+            }
+            var y = ;
+            print 2;
+        ";
+        let mut parser = Parser::new(src);
+        parser.parse();
+        let starts: Vec<_> = parser
+            .errors()
+            .iter()
+            .map(|e| e.span().map(|span| span.start))
+            .collect();
+        // one error for each mistake, and not one for the `}` left behind by the first.
+        assert_eq!(
+            starts,
+            vec![
+                Some(src.find("+;").unwrap() + 1),
+                Some(src.find("= ;").unwrap() + 2)
+            ]
+        );
+
+        for src in [
+            "}",
+            "{ print 1 }",
+            "{ fun }",
+            "class { var }",
+            "if (1 { print 1; }",
+        ] {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            assert!(parser.had_errors(), "{src} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_missing_semicolon_reports_one_error() {
+        for (src, statements) in [
+            ("{ var a = 1 }", 1),
+            ("fun f() { var a = 1 } print 2;", 2),
+            ("var a = 1\nprint a;", 1),
+            ("var a = 1\nvar b = 2;", 1),
+        ] {
+            let mut parser = Parser::new(src).with_printed_errors(false);
+            parser.parse();
+            assert_eq!(parser.errors().len(), 1, "{src}: {:?}", parser.errors());
+            // the statement after the mistake is still parsed, keyword and all.
+            assert_eq!(parser.take_statements().len(), statements, "{src}");
+        }
+    }
+
+    #[test]
+    fn test_continue_skips_rest_of_body() {
+        let src = "
+            var i = 0;
+            var work = 0;
+            while (i < 10) {
+                i = i + 1;
+                if (i > 5) continue;
+                work = work + 1;
+            }
+            var late = 0;
+            for (var j = 0; j < 10; j = j + 1) {
+                if (j < 5) { continue; }
+                late = late + j;
+            }
+        ";
+        for caching in [false, true] {
+            let mut lox = Lox::new().with_invariant_caching(caching);
+            run(&mut lox, src).unwrap();
+            assert_eq!(global_number(&lox, "i"), 10.0);
+            assert_eq!(global_number(&lox, "work"), 5.0);
+            // the increment still runs after a `continue` in a for loop.
+            assert_eq!(global_number(&lox, "late"), 35.0);
+        }
+    }
+
+    #[test]
+    fn test_json_natives() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Point { init(x, y) { this.y = y; this.x = x; } }
+            var p = Point(1, "two");
+            var data = { "points": chars("ab"), "origin": p, "tags": set(true), "note": nil };
+            var text = to_json(data);
+            var back = from_json(text);
+            var again = to_json(back);
+            var origin_x = back["origin"]["x"];
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(
+            lox.get_global("text").unwrap().to_string(),
+            r#"{"note":null,"origin":{"y":"two","x":1},"points":["a","b"],"tags":[true]}"#
+        );
+        // the instance comes back as a map, whose keys are written in sorted order.
+        assert_eq!(
+            lox.get_global("again").unwrap().to_string(),
+            r#"{"note":null,"origin":{"x":1,"y":"two"},"points":["a","b"],"tags":[true]}"#
+        );
+        assert_eq!(global_number(&lox, "origin_x"), 1.0);
+
+        for src in [
+            "p.me = p; to_json(p);",
+            "to_json(clock);",
+            r#"from_json("{");"#,
+            "from_json(1);",
+        ] {
+            assert!(run(&mut lox, src).is_err(), "{src} should fail");
+        }
+    }
+
+    #[test]
+    fn test_destructuring_var() {
+        let mut lox = Lox::new();
+        let src = r#"
+            fun pair(a, b) {
+                var values = chars("..");
+                values[0] = a;
+                values[1] = b;
+                return values;
+            }
+            var q, r = pair(7, "seven");
+            fun swapped() {
+                var x, y = pair(1, 2);
+                var y2, x2 = pair(y, x);
+                return y2 * 10 + x2;
+            }
+            var s = swapped();
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "q"), 7.0);
+        assert_eq!(lox.get_global("r").unwrap().to_string(), "seven");
+        assert_eq!(global_number(&lox, "s"), 21.0);
+
+        for (src, msg) in [
+            (
+                "var a, b, c = pair(1, 2);",
+                "TypeError: cannot destructure 2 values into 3 names",
+            ),
+            (
+                "var a = 0; var b = 0; { var a, b = chars(\"abc\"); }",
+                "TypeError: cannot destructure 3 values into 2 names",
+            ),
+            (
+                "var a, b = 1;",
+                "TypeError: expected type 'array' but recieved number",
+            ),
+        ] {
+            let err = run(&mut lox, src).unwrap_err();
+            assert_eq!(err.reason().to_string(), msg);
+        }
+
+        let mut parser = Parser::new("var a, b;");
+        parser.parse();
+        assert!(parser.had_errors());
+    }
+
+    #[test]
+    fn test_implicit_return() {
+        let mut lox = Lox::new();
+        let src = r#"
+            fun double(x) { x * 2 }
+            fun early(x) {
+                if (x > 0) return "positive";
+                "not positive"
+            }
+            fun nothing() { var y = 1; }
+            class Rect {
+                init(w, h) { this.w = w; this.h = h; }
+                area { this.w * this.h }
+            }
+            var a = double(21);
+            var b = early(1);
+            var c = early(-1);
+            var d = nothing();
+            var e = Rect(2, 3).area;
+            var f = fun () { fun () { 5 } }()();
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "a"), 42.0);
+        assert_eq!(lox.get_global("b").unwrap().to_string(), "positive");
+        assert_eq!(lox.get_global("c").unwrap().to_string(), "not positive");
+        assert!(lox.get_global("d").unwrap().is_nil());
+        assert_eq!(global_number(&lox, "e"), 6.0);
+        assert_eq!(global_number(&lox, "f"), 5.0);
+
+        // only the function body's own last statement can leave off its semicolon.
+        for src in [
+            "fun f(x) { if (x) { 1 } }",
+            "fun f(x) { while (x) x }",
+            "fun f() { print 1 }",
+            "fun f() { 1 2 }",
+            "{ 1 }",
+        ] {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            assert!(parser.had_errors(), "{src} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_equality_matrix() {
+        let prelude = r#"
+            class Point { init(x) { this.x = x; } get() { this.x } }
+            var p = Point(1);
+            var q = Point(1);
+            fun f() {}
+        "#;
+        let cases = [
+            ("1", "1", true),
+            ("1", "1.0", true),
+            ("0.1 + 0.2", "0.3", false),
+            ("1", "\"1\"", false),
+            ("0", "false", false),
+            ("0", "nil", false),
+            ("\"\"", "nil", false),
+            ("\"\"", "false", false),
+            ("nil", "false", false),
+            ("nil", "nil", true),
+            ("true", "true", true),
+            ("true", "1", false),
+            ("\"ab\"", "\"a\" + \"b\"", true),
+            ("0 / 0", "0 / 0", false),
+            ("p", "p", true),
+            ("p", "q", false),
+            ("p", "Point(1)", false),
+            ("Point", "Point", true),
+            ("p.get", "p.get", true),
+            ("p.get", "q.get", false),
+            ("f", "f", true),
+            ("fun () {}", "fun () {}", false),
+            ("clock", "clock", true),
+            ("clock", "chars", false),
+            ("chars(\"a\")", "chars(\"a\")", false),
+            ("1", "p", false),
+        ];
+        // literals compared directly can be folded, those held in variables never are.
+        for folding in [false, true] {
+            let mut lox = Lox::new().with_constant_folding(folding);
+            run(&mut lox, prelude).unwrap();
+            for (left, right, equal) in cases {
+                let src = format!(
+                    "var eq = {left} == {right}; var ne = {left} != {right};
+                    var l = {left}; var r = {right}; var held = l == r; var flipped = r == l;"
+                );
+                run(&mut lox, &src).unwrap();
+                for (name, expected) in [
+                    ("eq", equal),
+                    ("ne", !equal),
+                    ("held", equal),
+                    ("flipped", equal),
+                ] {
+                    assert_eq!(
+                        lox.get_global(name).unwrap().to_string(),
+                        expected.to_string(),
+                        "{name} for {left} and {right}, folding: {folding}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_print_several_values() {
+        let output = Captured::default();
+        let mut lox = Lox::new().with_output(output.clone());
+        let src = r#"
+            var x = 2;
+            print x;
+            print "x is", x, x * 2 == 4;
+            print;
+            write("no newline");
+            print nil, "";
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(output.text(), "2\nx is 2 true\n\nno newlinenil \n");
+
+        for src in ["print 1,;", "print , 1;", "print 1 2;"] {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            assert!(parser.had_errors(), "{src} should not parse");
+        }
+
+        // a print evaluates to the last value it printed.
+        for (src, expected) in [
+            ("print 1, 2;", "2"),
+            ("print \"a\";", "a"),
+            ("print;", "nil"),
+        ] {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            let stmts = parser.take_statements();
+            let Stmt::Print { span, .. } = &stmts[0] else {
+                panic!("expected a print statement");
+            };
+            assert_eq!(*span, Span::new(0, src.len()));
+            let value = unwrap_to_object(stmts[0].accept(&mut lox).unwrap()).unwrap();
+            assert_eq!(value.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_print_write_error_points_at_print() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let src = "var a = 1;\nprint a, 2;";
+        let err = run(&mut Lox::new().with_output(Closed), src).unwrap_err();
+        assert!(matches!(
+            err.reason(),
+            LoxError::NativeError(NativeError::SystemError(_))
+        ));
+        assert_eq!(err.place(), src.find("print"));
+    }
+
+    #[test]
+    fn test_weak_links_free_cycles_across_iterations() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Node {
+                init(parent) {
+                    this.parent = parent;
+                    this.child = nil;
+                }
+            }
+            var strong = {};
+            var linked = {};
+            for (var i = 0; i < 1000; i = i + 1) {
+                var a = Node(nil);
+                a.child = Node(a);
+                strong[i] = weak(a);
+
+                var b = Node(nil);
+                b.child = Node(weak(b));
+                linked[i] = weak(b);
+            }
+            var leaked = 0;
+            var freed = 0;
+            for (var i = 0; i < 1000; i = i + 1) {
+                if (deref(strong[i]) != nil) leaked = leaked + 1;
+                if (deref(linked[i]) == nil) freed = freed + 1;
+            }
+        "#;
+        run(&mut lox, src).unwrap();
+        // every pair linked strongly is still alive, every pair linked weakly is gone.
+        assert_eq!(global_number(&lox, "leaked"), 1000.0);
+        assert_eq!(global_number(&lox, "freed"), 1000.0);
+    }
+
+    #[test]
+    fn test_weak_references_break_cycles() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Node {
+                init(parent) {
+                    this.parent = parent;
+                    this.child = nil;
+                }
+            }
+            var strong_probe;
+            var weak_probe;
+            {
+                var root = Node(nil);
+                root.child = Node(root);
+                strong_probe = weak(root);
+
+                var other = Node(nil);
+                other.child = Node(weak(other));
+                weak_probe = weak(other);
+                assert(ref_count(other) == 1);
+                assert(deref(other.child.parent) == other);
+            }
+            // the strong cycle outlives the block, the one closed by a weak reference doesn't.
+            var strong_alive = deref(strong_probe) != nil;
+            var weak_alive = deref(weak_probe) != nil;
+        "#;
+        run(&mut lox, src).unwrap();
+        for (name, expected) in [("strong_alive", true), ("weak_alive", false)] {
+            assert_eq!(
+                lox.get_global(name).unwrap().to_string(),
+                expected.to_string(),
+                "{name}"
+            );
+        }
+        assert_eq!(
+            lox.get_global("weak_probe").unwrap().to_string(),
+            "<weak, freed>"
+        );
+
+        run(
+            &mut lox,
+            "var a = chars(\"ab\"); var b = a; var n = ref_count(a);",
+        )
+        .unwrap();
+        assert_eq!(global_number(&lox, "n"), 2.0);
+        for (src, msg) in [
+            (
+                "weak(1);",
+                "TypeError: weak() expected type 'instance, map or array' but recieved number",
+            ),
+            (
+                "deref(a);",
+                "TypeError: deref() expected type 'weak reference' but recieved array",
+            ),
+            (
+                "ref_count(nil);",
+                "TypeError: ref_count() expected type 'object' but recieved nil",
+            ),
+        ] {
+            let err = run(&mut lox, src).unwrap_err();
+            assert_eq!(err.reason().to_string(), msg);
+        }
+    }
+
+    #[test]
+    fn test_dollar_signs_in_strings_are_literal() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var price = "$5";
+            var shell = "echo ${HOME} $PATH";
+            var trailing = "$";
+            var joined = "$" + "{" + "x}";
+        "#;
+        run(&mut lox, src).unwrap();
+        for (name, expected) in [
+            ("price", "$5"),
+            ("shell", "echo ${HOME} $PATH"),
+            ("trailing", "$"),
+            ("joined", "${x}"),
+        ] {
+            assert_eq!(lox.get_global(name).unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_objects_as_map_keys_by_identity() {
+        let mut lox = Lox::new();
+        let src = r#"
+            fun f() {}
+            fun g() {}
+            class Point { init(x) { this.x = x; } }
+            var p = Point(1);
+            var q = Point(1);
+
+            var handlers = {};
+            handlers[f] = 1;
+            handlers[g] = 2;
+            handlers[f] = 3;
+            var from_f = handlers[f];
+            var from_g = handlers[g];
+
+            var seen = set(p, q, p, Point);
+            var count = size(seen);
+            var has_p = has(seen, p);
+            var has_other = has(seen, Point(1));
+            var by_class = { Point: "class" }[Point];
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "from_f"), 3.0);
+        assert_eq!(global_number(&lox, "from_g"), 2.0);
+        assert_eq!(
+            lox.get_global("handlers").unwrap().to_string(),
+            "{ <fn f/0>: 3, <fn g/0>: 2 }"
+        );
+        assert_eq!(global_number(&lox, "count"), 3.0);
+        assert_eq!(lox.get_global("has_p").unwrap().to_string(), "true");
+        assert_eq!(lox.get_global("has_other").unwrap().to_string(), "false");
+        assert_eq!(lox.get_global("by_class").unwrap().to_string(), "class");
+
+        // arrays and maps still can't be keys, and object keys have no JSON form.
+        for (src, msg) in [
+            (
+                "handlers[chars(\"a\")] = 1;",
+                "TypeError: cannot use array as a map key",
+            ),
+            (
+                "to_json(handlers);",
+                "TypeError: to_json() cannot use function as an object key",
+            ),
+        ] {
+            let err = run(&mut lox, src).unwrap_err();
+            assert_eq!(err.reason().to_string(), msg);
+        }
+    }
+
+    #[test]
+    fn test_methods_calling_methods_through_this() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Base {
+                helper() { return "base helper"; }
+                describe() { return "base"; }
+                intro() { return "I am " + this.describe(); }
+                is_even(n) { if (n == 0) return true; return this.is_odd(n - 1); }
+                is_odd(n) { if (n == 0) return false; return this.is_even(n - 1); }
+            }
+            class Derived < Base {
+                // overrides describe, but still leans on the inherited helper.
+                describe() { return "derived with " + this.helper(); }
+                via_inherited() { return this.is_even(4); }
+                sibling() { return this.describe(); }
+            }
+            class Leaf < Derived {}
+            var d = Derived();
+            var overridden = d.describe();
+            var sibling = d.sibling();
+            var inherited = d.via_inherited();
+            var mutual = Base().is_odd(7);
+            var from_leaf = Leaf().sibling();
+            var base_describe = Base().describe();
+            // a method found on the superclass still calls the subclass's override.
+            var intro = d.intro();
+        "#;
+        run(&mut lox, src).unwrap();
+        for (name, expected) in [
+            ("overridden", "derived with base helper"),
+            ("sibling", "derived with base helper"),
+            ("inherited", "true"),
+            ("mutual", "true"),
+            ("from_leaf", "derived with base helper"),
+            ("base_describe", "base"),
+            ("intro", "I am derived with base helper"),
+        ] {
+            assert_eq!(
+                lox.get_global(name).unwrap().to_string(),
+                expected,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_implicit_globals() {
+        let src = "fun set_later() { later = 2; } x = 5; set_later(); var sum = x + later;";
+        let err = run(&mut Lox::new(), src).unwrap_err();
+        assert_eq!(
+            err.reason().to_string(),
+            "ReferenceError: undeclared identifier 'x'"
+        );
+
+        let mut lox = Lox::new().with_implicit_globals(true);
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "sum"), 7.0);
+        // only assignment declares, reading a missing global is still an error.
+        assert!(run(&mut lox, "print missing;").is_err());
+    }
+
+    #[test]
+    fn test_seeded_globals() {
+        let globals = HashMap::from([
+            ("env".to_string(), LoxObject::from("prod".to_string())),
+            ("retries".to_string(), LoxObject::from(3.0)),
+        ]);
+        let mut lox = Lox::new_with_globals(globals);
+        let src = r#"
+            var label = env + ":" + string(retries);
+            retries = retries + 1;
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(lox.get_global("label").unwrap().to_string(), "prod:3");
+        assert_eq!(global_number(&lox, "retries"), 4.0);
+    }
+
+    #[test]
+    fn test_assert_failure_points_at_call() {
+        let src = "var total = 3;\nassert(total == 4, \"total is off\");";
+        let mut lox = Lox::new();
+        let err = lox.run_source(src).unwrap_err();
+        let [LoxDiagnostic::Runtime(e)] = &err[..] else {
+            panic!("expected a single runtime error");
+        };
+        assert_eq!(e.reason().to_string(), "AssertionError: total is off");
+        assert_eq!(e.place(), Some(src.find("assert").unwrap()));
+        assert_eq!(
+            err[0].render(src).lines().take(2).collect::<Vec<_>>(),
+            ["AssertionError: total is off at line 2", " --> 2:1"]
+        );
+        lox.run_source("assert(total == 3, \"total is off\");")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_native_argument_errors_point_at_call() {
+        for (src, native) in [
+            ("var s = set();\nprint size(s, 1);", "size"),
+            ("print string(keys(5));", "keys"),
+            ("var c = 1;\nc = 1 + deep_eq(1);", "deep_eq"),
+        ] {
+            let err = run(&mut Lox::new(), src).unwrap_err();
+            assert!(
+                matches!(
+                    err.reason(),
+                    LoxError::NativeError(NativeError::InvalidArguments(_))
+                ),
+                "{src} raised {err}"
+            );
+            assert_eq!(err.place(), src.find(native), "{src}");
+        }
+    }
+
+    #[test]
+    fn test_static_methods_capture_enclosing_locals() {
+        let mut lox = Lox::new();
+        let src = r#"
+            fun make() {
+                var count = 3;
+                class A < Object { static get() { return count; } }
+                return A.get();
+            }
+            class Object {}
+            var got = make();
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "got"), 3.0);
+    }
+
+    #[test]
+    fn test_closures_share_captured_variables() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var inc;
+            var get;
+            fun counter() {
+                var count = 0;
+                fun i() { count = count + 1; return count; }
+                fun g() { return count; }
+                inc = i;
+                get = g;
+            }
+            counter();
+            inc();
+            inc();
+            var shared = get();
+
+            // captured through a function that never reads it itself.
+            fun outer() {
+                var x = 1;
+                fun middle() { return fun() { x = x + 1; return x; } }
+                var bump = middle();
+                bump();
+                return x;
+            }
+            var through = outer();
+
+            // writes made after the capture are seen, and the class can refer to itself.
+            class Base { one() { return 1; } }
+            var late;
+            {
+                var n = 1;
+                class Node < Base { twice() { return Node.scale * n * super.one(); } }
+                Node.scale = 2;
+                n = 5;
+                late = Node().twice();
+            }
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "shared"), 2.0);
+        assert_eq!(global_number(&lox, "through"), 2.0);
+        assert_eq!(global_number(&lox, "late"), 10.0);
+        assert!(lox.frame.is_empty());
+    }
+
+    #[test]
+    fn test_anonymous_class_expression() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var Counter = class {
+                init(start) { this.count = start; }
+                inc() { this.count = this.count + 1; return this.count; }
+            };
+            var c = Counter(5);
+            c.inc();
+            var result = c.inc();
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "result"), 7.0);
+        let class = lox.get_global("Counter").unwrap();
+        assert_eq!(class.to_string(), "[class anonymous]");
+    }
+
+    #[test]
+    fn test_named_class_expression() {
+        let mut lox = Lox::new();
+        let src = r#"
+            var make = fun () { return class Point { x() { return 3; } }; };
+            var p = make()();
+            var result = p.x();
+        "#;
+        run(&mut lox, src).unwrap();
+        assert_eq!(global_number(&lox, "result"), 3.0);
+        assert_eq!(lox.get_global("p").unwrap().to_string(), "Point {}");
+    }
+
+    fn apply(lox: &mut Lox, args: Vec<LoxObject>) -> EvalResult {
+        let mut args = args.into_iter();
+        let func = args.next().unwrap_or(LoxObject::new_nil());
+        lox.call_object(func, args.collect())
+    }
+
+    #[test]
+    fn test_method_reference_keeps_receiver() {
+        let mut lox = Lox::new();
+        lox.register_native("apply", apply);
+        let src = r#"
+            class Greeter {
+                init(name) { this.name = name; }
+                greet(greeting) { return greeting + " " + this.name; }
+            }
+            var g = Greeter("a");
+            var m = g.greet;
+            // the bound method sees mutations of the instance it was read from...
+            g.name = "b";
+            var first = m("hi");
+            // ...but not whatever the variable is pointed at afterwards.
+            g = Greeter("c");
+            var second = m("hi");
+            var third = apply(m, "hey");
+
+            class Obj {
+                init() { this.greeting = "hello"; }
+                greet() { return this.greeting; }
+            }
+            var obj = Obj();
+            var later = obj.greet;
+            fun call_later(f) { var greeting = "shadowed"; return f(); }
+            var fourth = later();
+            var fifth = call_later(later);
+        "#;
+        run(&mut lox, src).unwrap();
+        let global_str = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(global_str("first"), "hi b");
+        assert_eq!(global_str("second"), "hi b");
+        assert_eq!(global_str("third"), "hey b");
+        assert_eq!(global_str("fourth"), "hello");
+        assert_eq!(global_str("fifth"), "hello");
+    }
+
+    #[test]
+    fn test_runtime_error_reports_line() {
+        let src = "var a = 1;\nvar b = a - nil;";
+        let mut lox = Lox::new();
+        lox.set_source(src);
+        let err = run(&mut lox, src).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "TypeError: righthand side incorrect type 'nil' for op '-' at line 2"
+        );
+        let block = err.code_block(src).unwrap();
+        assert!(
+            block.ends_with("2 | var b = a - nil;\n  |           ^"),
+            "{block}"
+        );
+    }
+
+    #[test]
+    fn test_type_error_names_class() {
+        let mut lox = Lox::new();
+        let err = run(&mut lox, "class Foo {} var f = Foo(); var x = f - 1;").unwrap_err();
+        assert_eq!(
+            err.reason().to_string(),
+            "TypeError: lefthand side incorrect type 'instance of Foo' for op '-'"
+        );
+        let err = run(&mut lox, "f();").unwrap_err();
+        assert!(err.to_string().contains("instance of Foo"), "{err}");
+    }
+
+    #[test]
+    fn test_runtime_error_stack_trace() {
+        let src = r#"
+            fun inner() { return 1 - nil; }
+            fun helper() { return inner(); }
+            var outer = fun () { return helper(); };
+            outer();
+        "#;
+        let mut lox = Lox::new();
+        lox.set_source(src);
+        let err = run(&mut lox, src).unwrap_err();
+        let trace = err.stack_trace(lox.source()).unwrap();
+        assert_eq!(trace, "fn@4:25 -> helper -> inner");
+        assert_eq!(err.stack_trace(None).unwrap(), "fn@114 -> helper -> inner");
+        // the stack unwinds fully once the error has escaped.
+        assert!(lox.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_print_instance_fields() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Point { init(x, y) { this.x = x; this.y = y; } }
+            var p = Point(1, 2);
+            p.label = "origin";
+            var plain = string(p);
+            p.me = p;
+            var cyclic = string(p);
+        "#;
+        run(&mut lox, src).unwrap();
+        let global_str = |name: &str| lox.get_global(name).unwrap().to_string();
+        assert_eq!(
+            global_str("plain"),
+            r#"Point { x: 1, y: 2, label: "origin" }"#
+        );
+        let cyclic = global_str("cyclic");
+        assert!(
+            cyclic.starts_with(r#"Point { x: 1, y: 2, label: "origin", me: Point { x: 1, y: 2"#),
+            "{cyclic}"
+        );
+        assert!(cyclic.contains("me: Point { ... }"), "{cyclic}");
+    }
+
+    #[test]
+    fn test_instance_fields_keep_insertion_order() {
+        let mut lox = Lox::new();
+        let src = r#"
+            class Bag {}
+            var b = Bag();
+            b.zebra = 1;
+            b.apple = 2;
+            b.mango = 3;
+            b.zebra = 4;
+            delete_field(b, "apple");
+            b.apple = 5;
+        "#;
+        run(&mut lox, src).unwrap();
+        let Some(LoxObject::ClassInstance(bag)) = lox.get_global("b") else {
+            panic!("expected an instance");
+        };
+        let fields: Vec<_> = bag
+            .borrow()
+            .fields()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect();
+        assert_eq!(fields, ["zebra=4", "mango=3", "apple=5"]);
+        assert_eq!(
+            bag.borrow().to_string(),
+            "Bag { zebra: 4, mango: 3, apple: 5 }"
+        );
+    }
+
+    #[test]
+    fn test_runaway_recursion_overflows_cleanly() {
+        let src = "fun f(n) { return f(n + 1); }\nf(0);";
+        let mut lox = Lox::new().with_max_call_depth(50);
+        lox.set_source(src);
+        let err = run(&mut lox, src).unwrap_err();
+        assert!(matches!(err.reason(), LoxError::StackOverflow(50)));
+        assert_eq!(
+            err.to_string(),
+            "StackOverflow: maximum call depth of 50 exceeded at line 1"
+        );
+        assert_eq!(err.stack_trace(None).unwrap(), "f x50");
+        // the interpreter is still usable afterwards.
+        assert!(lox.call_stack().is_empty());
+        run(&mut lox, "var ok = f;").unwrap();
+    }
+
+    #[test]
+    fn test_default_call_depth_fits_default_thread() {
+        // a spawned thread gets the same 2MB stack by default as the test harness runs on.
+        let result = std::thread::spawn(|| {
+            let src = "
+                fun f(n) {
+                    if (n > 0) { var a = 1; { var b = a; return f(n - 1) + b; } }
+                    return 0;
+                }
+                var deepest = f(DEPTH - 1);
+            ";
+            let mut lox = Lox::new();
+            let limit = DEFAULT_MAX_CALL_DEPTH.to_string();
+            run(&mut lox, &src.replace("DEPTH", &limit)).unwrap();
+            let deepest = global_number(&lox, "deepest");
+            let err = run(&mut lox, &src.replace("DEPTH - 1", &limit)).unwrap_err();
+            (deepest, err.reason().to_string())
+        })
+        .join()
+        .unwrap();
+        assert_eq!(result.0, (DEFAULT_MAX_CALL_DEPTH - 1) as f64);
+        assert_eq!(
+            result.1,
+            "StackOverflow: maximum call depth of 100 exceeded"
+        );
+    }
+
+    #[test]
+    fn test_raised_call_depth_fits_stack_size() {
+        let deepest = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| {
+                let src = "
+                    fun f(n) {
+                        if (n > 0) { var a = 1; { var b = a; return f(n - 1) + b; } }
+                        return 0;
+                    }
+                    var deepest = f(999);
+                ";
+                let mut lox = Lox::new().with_max_call_depth(1_000);
+                run(&mut lox, src).unwrap();
+                global_number(&lox, "deepest")
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(deepest, 999.0);
+    }
+
+    #[test]
+    fn test_global_reference_uses_globals_map() {
+        let stmts = parse_and_resolve("var b = a + 1;");
+        let Stmt::Var {
+            initializer: Some(Expr::Binary { left, .. }),
+            ..
+        } = &stmts[0]
+        else {
+            panic!("expected a var declaration");
+        };
+        let Expr::Variable { value } = left.as_ref() else {
+            panic!("expected a variable");
+        };
+        assert_eq!(value.binding(), Some(Binding::Global));
+
+        let mut lox = Lox::new();
+        lox.set_global("a", LoxObject::from(41.0));
+        lox.interpret(stmts).unwrap();
+        assert_eq!(global_number(&lox, "b"), 42.0);
+    }
+
+    #[test]
+    fn test_global_assignment_from_local_scope() {
+        let mut lox = Lox::new();
+        run(&mut lox, "var a = 1; { var b = 2; a = a + b; }").unwrap();
+        assert_eq!(global_number(&lox, "a"), 3.0);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod diagnostic;
 pub mod lox;
 pub mod print; // this is essentially a test visitor to verify visitor logic in the future.
 pub mod runtime;
@@ -1,5 +1,7 @@
+use super::frame::UpValue;
 use super::function::Function;
-use super::object::LoxObject;
+use super::object::{LoxObject, MAX_DISPLAY_DEPTH};
+use crate::lang::tree::ast::Expr;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
@@ -10,9 +12,15 @@ const DEFAULT_PROPERTY_HASH_SIZE: usize = 16;
 #[derive(Debug)]
 pub struct Class {
     name: String,
+    superclass: Option<Rc<Class>>,
     methods: HashMap<String, LoxObject>,
-    statics: HashMap<String, LoxObject>,
+    // static methods and fields, fields can be reassigned after the class is defined.
+    statics: RefCell<HashMap<String, LoxObject>>,
     init: Option<LoxObject>,
+    // field initializers run against every new instance, in declaration order.
+    fields: Vec<(String, Rc<Expr>)>,
+    // what the field initializers captured from where the class was declared.
+    field_upvalues: Rc<[UpValue]>,
 }
 
 impl Class {
@@ -22,27 +30,85 @@ impl Class {
         statics: HashMap<String, LoxObject>,
         init: Option<LoxObject>,
     ) -> Self {
-        return Self {
+        Self {
             name,
+            superclass: None,
             methods,
-            statics,
+            statics: RefCell::new(statics),
             init,
-        };
+            fields: Vec::new(),
+            field_upvalues: Rc::new([]),
+        }
+    }
+
+    pub fn with_fields(mut self, upvalues: Rc<[UpValue]>, fields: Vec<(String, Rc<Expr>)>) -> Self {
+        self.field_upvalues = upvalues;
+        self.fields = fields;
+        self
+    }
+
+    pub fn fields(&self) -> &[(String, Rc<Expr>)] {
+        &self.fields[..]
     }
 
+    pub fn field_upvalues(&self) -> Rc<[UpValue]> {
+        self.field_upvalues.clone()
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn with_superclass(mut self, superclass: Option<Rc<Class>>) -> Self {
+        self.superclass = superclass;
+        self
+    }
+
+    pub fn superclass(&self) -> Option<&Rc<Class>> {
+        self.superclass.as_ref()
+    }
+
+    /// whether this is `class` itself or inherits from it somewhere up the chain.
+    pub fn is_or_inherits(&self, class: &Rc<Class>) -> bool {
+        std::ptr::eq(self, class.as_ref())
+            || self
+                .superclass
+                .as_ref()
+                .is_some_and(|sup| sup.is_or_inherits(class))
+    }
+
+    /// look up a method on this class, falling back to the superclass chain.
     pub fn get_method(&self, name: &str) -> Option<&LoxObject> {
-        self.methods.get(name)
+        self.methods.get(name).or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|sup| sup.get_method(name))
+        })
+    }
+
+    /// whether this class or one it inherits from declares an instance field called `name`.
+    pub fn declares_field(&self, name: &str) -> bool {
+        self.fields.iter().any(|(field, _)| field == name)
+            || self
+                .superclass
+                .as_ref()
+                .is_some_and(|sup| sup.declares_field(name))
+    }
+
+    pub fn get_static(&self, name: &str) -> Option<LoxObject> {
+        self.statics.borrow().get(name).cloned()
     }
 
-    pub fn get_static(&self, name: &str) -> Option<&LoxObject> {
-        self.statics.get(name)
+    pub fn set_static(&self, name: &str, value: LoxObject) -> Option<LoxObject> {
+        self.statics.borrow_mut().insert(name.to_string(), value)
     }
 
+    /// the class's initializer, subclasses without one of their own inherit it.
     pub fn init(&self) -> Option<Rc<Function>> {
         if let Some(LoxObject::Function(ref init)) = self.init {
             return Some(init.clone());
         }
-        None
+        self.superclass.as_ref().and_then(|sup| sup.init())
     }
 }
 
@@ -56,14 +122,17 @@ impl fmt::Display for Class {
 pub struct ClassInstance {
     constructor: Rc<Class>,
     properties: HashMap<String, LoxObject>,
+    // the field names in the order they were first set, so instances always show the same way.
+    order: Vec<String>,
 }
 
 impl ClassInstance {
     pub fn new(constructor: Rc<Class>) -> Self {
-        return Self {
+        Self {
             constructor,
             properties: HashMap::with_capacity(DEFAULT_PROPERTY_HASH_SIZE),
-        };
+            order: Vec::with_capacity(DEFAULT_PROPERTY_HASH_SIZE),
+        }
     }
 
     pub fn new_lox_object(constructor: Rc<Class>) -> LoxObject {
@@ -77,16 +146,69 @@ impl ClassInstance {
     }
 
     pub fn set(&mut self, prop: &str, value: LoxObject) -> Option<LoxObject> {
+        if let Some(existing) = self.properties.get_mut(prop) {
+            return Some(std::mem::replace(existing, value));
+        }
+        self.order.push(prop.to_string());
         self.properties.insert(prop.to_string(), value)
     }
 
+    /// whether the instance has a field called `prop`, methods don't count.
+    pub fn contains(&self, prop: &str) -> bool {
+        self.properties.contains_key(prop)
+    }
+
+    /// take the field `prop` off the instance, methods are left alone.
+    pub fn remove(&mut self, prop: &str) -> Option<LoxObject> {
+        let removed = self.properties.remove(prop)?;
+        self.order.retain(|name| name != prop);
+        Some(removed)
+    }
+
     pub fn init(&self) -> Option<Rc<Function>> {
         self.constructor.init()
     }
+
+    pub fn constructor(&self) -> &Rc<Class> {
+        &self.constructor
+    }
+
+    pub fn properties(&self) -> &HashMap<String, LoxObject> {
+        &self.properties
+    }
+
+    /// the instance's fields in the order they were first set. setting a field again keeps its
+    /// place, removing one and setting it later moves it to the end.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &LoxObject)> {
+        self.order
+            .iter()
+            .map(|name| (name.as_str(), &self.properties[name]))
+    }
+}
+
+impl ClassInstance {
+    pub(crate) fn fmt_nested(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let name = self.constructor.name();
+        if self.properties.is_empty() {
+            return write!(f, "{} {{}}", name);
+        }
+        if depth >= MAX_DISPLAY_DEPTH {
+            return write!(f, "{} {{ ... }}", name);
+        }
+        write!(f, "{} {{ ", name)?;
+        for (idx, (key, value)) in self.fields().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: ", key)?;
+            value.fmt_nested(f, depth + 1)?;
+        }
+        write!(f, " }}")
+    }
 }
 
 impl fmt::Display for ClassInstance {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {{}}", self.constructor.name)
+        self.fmt_nested(f, 0)
     }
 }
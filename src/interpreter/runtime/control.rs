@@ -23,24 +23,15 @@ impl Control {
     }
 
     pub fn is_return(&self) -> bool {
-        match self {
-            Self::Return(_) => true,
-            _ => false,
-        }
+        matches!(self, Self::Return(_))
     }
 
     pub fn is_break(&self) -> bool {
-        match self {
-            Self::Break => true,
-            _ => false,
-        }
+        matches!(self, Self::Break)
     }
 
     pub fn is_continue(&self) -> bool {
-        match self {
-            Self::Continue => true,
-            _ => false,
-        }
+        matches!(self, Self::Continue)
     }
 }
 
@@ -1,12 +1,19 @@
+use super::trace::{CallFrame, format_trace};
+use crate::lang::tokenizer::span::{View, code_block, word_span};
+use crate::lang::tree::error::ParseWarning;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum RuntimeError {
-    #[error("{reason}")]
+    #[error("{reason}{}", .line.map(|l| format!(" at line {l}")).unwrap_or_default())]
     WithLocation {
         #[source]
         reason: LoxError,
         place: usize,
+        // only known once the error has been matched up with the source it came from.
+        line: Option<usize>,
+        // the calls that were active when the error was raised, outermost first.
+        trace: Vec<CallFrame>,
     },
     #[error("{reason}")]
     Without {
@@ -20,7 +27,90 @@ impl RuntimeError {
     pub fn with_place(self, place: usize) -> Self {
         match self {
             Self::WithLocation { .. } => self, // you cannot mutate the location originally attached to it.
-            Self::Without { reason } => Self::WithLocation { reason, place },
+            Self::Without { reason } => Self::WithLocation {
+                reason,
+                place,
+                line: None,
+                trace: Vec::new(),
+            },
+        }
+    }
+
+    /// attach the call stack active when the error was raised. like the place, the first trace
+    /// attached wins since that is the deepest point of the stack the error passed through.
+    pub fn with_trace(self, frames: &[CallFrame]) -> Self {
+        match self {
+            Self::WithLocation {
+                reason,
+                place,
+                line,
+                trace,
+            } if trace.is_empty() => Self::WithLocation {
+                reason,
+                place,
+                line,
+                trace: frames.to_vec(),
+            },
+            _ => self,
+        }
+    }
+
+    pub fn trace(&self) -> &[CallFrame] {
+        match self {
+            Self::WithLocation { trace, .. } => &trace[..],
+            Self::Without { .. } => &[],
+        }
+    }
+
+    /// the chain of calls that led to the error, e.g. `fib -> helper`, if it happened inside a function.
+    pub fn stack_trace(&self, src: Option<&str>) -> Option<String> {
+        let trace = self.trace();
+        if trace.is_empty() {
+            return None;
+        }
+        Some(format_trace(trace, src))
+    }
+
+    pub fn place(&self) -> Option<usize> {
+        match self {
+            Self::WithLocation { place, .. } => Some(*place),
+            Self::Without { .. } => None,
+        }
+    }
+
+    pub fn reason(&self) -> &LoxError {
+        match self {
+            Self::WithLocation { reason, .. } | Self::Without { reason } => reason,
+        }
+    }
+
+    /// fill in the line the error occured on from the source the program was parsed from.
+    pub fn with_source(self, src: &str) -> Self {
+        match self {
+            Self::WithLocation {
+                reason,
+                place,
+                line: None,
+                trace,
+            } => Self::WithLocation {
+                reason,
+                place,
+                line: Some(View::at(src, place).line),
+                trace,
+            },
+            _ => self,
+        }
+    }
+
+    /// render the offending line of `src` with the token the error points to underlined.
+    pub fn code_block(&self, src: &str) -> Option<String> {
+        self.place()
+            .map(|place| code_block(src, word_span(src, place)))
+    }
+
+    pub fn print_code_block(&self, src: &str) {
+        if let Some(block) = self.code_block(src) {
+            println!("{}", block);
         }
     }
 }
@@ -39,6 +129,14 @@ pub enum LoxError {
     EvalUnwrapError(String),
     #[error("Uncaught SyntaxError: {0}")]
     UncaughtSyntaxError(String),
+    #[error("IndexError: {0}")]
+    IndexError(String),
+    #[error("StackOverflow: maximum call depth of {0} exceeded")]
+    StackOverflow(usize),
+    #[error("AssertionError: {0}")]
+    AssertionError(String),
+    #[error("RangeError: {0}")]
+    RangeError(String),
 }
 
 #[derive(Error, Debug, Clone)]
@@ -49,6 +147,41 @@ pub enum NativeError {
     InvalidArguments(String),
 }
 
+/// something questionable about a program that doesn't stop it from running, collected on the
+/// interpreter for the host to report.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("warning: {message}")]
+pub struct Warning {
+    message: String,
+    place: usize,
+}
+
+impl Warning {
+    pub fn new(message: String, place: usize) -> Self {
+        Self { message, place }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn place(&self) -> usize {
+        self.place
+    }
+
+    /// render the line of `src` the warning points to, like `RuntimeError::code_block`.
+    pub fn code_block(&self, src: &str) -> String {
+        code_block(src, word_span(src, self.place))
+    }
+}
+
+// the parser's warnings are collected on the interpreter along with its own, see `Lox::run_source`.
+impl From<&ParseWarning> for Warning {
+    fn from(warning: &ParseWarning) -> Self {
+        Self::new(warning.message().to_string(), warning.span().start)
+    }
+}
+
 // this is purly for routing logic to understand why something failed.
 // It is not intended to be printed directly.
 #[derive(Debug, Clone)]
@@ -57,4 +190,6 @@ pub enum BinaryError {
     RightSide,
     InvalidOperator,
     InvalidTypes,
+    // finite operands gave inf or NaN while strict math is on.
+    NotFinite(f64),
 }
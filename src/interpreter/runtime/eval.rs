@@ -61,10 +61,7 @@ impl Eval {
     }
 
     pub fn is_control(&self) -> bool {
-        match self {
-            Self::Ctrl(_) => true,
-            _ => false,
-        }
+        matches!(self, Self::Ctrl(_))
     }
 
     pub fn truthy(&self) -> bool {
@@ -97,10 +94,12 @@ impl Eval {
         }
     }
 
-    pub fn unwrap_return(self) -> Self {
+    /// the value a function call evaluates to once its body has run. anything other than an
+    /// explicit return means the body fell off the end, which yields nil.
+    pub fn into_return_value(self) -> LoxObject {
         match self {
-            Self::Ctrl(Control::Return(v)) => Self::Object(v),
-            _ => self,
+            Self::Ctrl(Control::Return(v)) => v,
+            _ => LoxObject::new_nil(),
         }
     }
 
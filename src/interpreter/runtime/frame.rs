@@ -0,0 +1,83 @@
+use crate::interpreter::runtime::object::LoxObject;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// a variable shared between the frame that declared it and the closures that captured it.
+pub type UpValue = Rc<RefCell<LoxObject>>;
+
+#[derive(Debug, Clone)]
+enum Slot {
+    Value(LoxObject),
+    // once a closure captures a slot the value moves into a cell both of them hold.
+    Captured(UpValue),
+}
+
+/// the locals of one call, or of the top level's blocks. slots are handed out in the order things
+/// are declared, which is the order the resolver numbered them in, so a read is a single index.
+#[derive(Debug, Default)]
+pub struct Frame {
+    slots: Vec<Slot>,
+}
+
+impl Frame {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Declare the next slot holding `value`, returning its index.
+    pub fn declare(&mut self, value: LoxObject) -> usize {
+        self.slots.push(Slot::Value(value));
+        self.slots.len() - 1
+    }
+
+    pub fn get(&self, slot: usize) -> LoxObject {
+        match &self.slots[slot] {
+            Slot::Value(value) => value.clone(),
+            Slot::Captured(cell) => cell.borrow().clone(),
+        }
+    }
+
+    pub fn set(&mut self, slot: usize, value: LoxObject) {
+        match &mut self.slots[slot] {
+            Slot::Value(current) => *current = value,
+            Slot::Captured(cell) => *cell.borrow_mut() = value,
+        }
+    }
+
+    /// the cell holding `slot`, moving the value into one the first time it is captured.
+    pub fn capture(&mut self, slot: usize) -> UpValue {
+        let entry = &mut self.slots[slot];
+        let cell = match entry {
+            Slot::Captured(cell) => return cell.clone(),
+            Slot::Value(value) => Rc::new(RefCell::new(value.clone())),
+        };
+        *entry = Slot::Captured(cell.clone());
+        cell
+    }
+
+    /// give every captured slot from `start` on a cell of its own, so the closures that captured
+    /// the old cells keep the values they saw while the frame carries on with copies.
+    pub fn renew(&mut self, start: usize) {
+        for entry in &mut self.slots[start..] {
+            if let Slot::Captured(cell) = entry {
+                let value = cell.borrow().clone();
+                *entry = Slot::Captured(Rc::new(RefCell::new(value)));
+            }
+        }
+    }
+
+    /// forget every slot from `len` on, at the end of the block that declared them.
+    pub fn truncate(&mut self, len: usize) {
+        self.slots.truncate(len);
+    }
+}
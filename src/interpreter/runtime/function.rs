@@ -1,27 +1,61 @@
-use super::class::ClassInstance;
-use super::object::LoxObject;
-use super::scope::Scope;
+use super::frame::UpValue;
 use crate::lang::tree::ast::Stmt;
-use std::cell::RefCell;
 use std::fmt;
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct Function {
-    closure: Rc<RefCell<Scope>>,
+    // the variables captured from enclosing functions when this one was created, see `Capture`.
+    upvalues: Rc<[UpValue]>,
     params: Vec<String>,
     body: Rc<Stmt>,
+    // shared with every call frame made for this function, so calls don't copy it.
+    name: Option<Rc<str>>,
+    // where the function was declared, used to label anonymous functions in stack traces.
+    position: usize,
+    // getters are invoked as soon as they are accessed on an instance rather than being bound.
+    getter: bool,
 }
 
 impl Function {
-    pub fn new(closure: Rc<RefCell<Scope>>, params: Vec<String>, body: Rc<Stmt>) -> Self {
+    pub fn new(upvalues: Rc<[UpValue]>, params: Vec<String>, body: Rc<Stmt>) -> Self {
         Self {
-            closure,
+            upvalues,
             params,
             body,
+            name: None,
+            position: 0,
+            getter: false,
         }
     }
 
+    pub fn with_name(mut self, name: Option<impl Into<Rc<str>>>, position: usize) -> Self {
+        self.name = name.map(Into::into);
+        self.position = position;
+        self
+    }
+
+    pub fn with_getter(mut self, getter: bool) -> Self {
+        self.getter = getter;
+        self
+    }
+
+    pub fn is_getter(&self) -> bool {
+        self.getter
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn shared_name(&self) -> Option<Rc<str>> {
+        self.name.clone()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
     pub fn body(&self) -> &Stmt {
         self.body.as_ref()
     }
@@ -34,37 +68,15 @@ impl Function {
         &self.params[..]
     }
 
-    pub fn closure(&self) -> Rc<RefCell<Scope>> {
-        self.closure.clone()
-    }
-
-    pub fn bind(&self, target: LoxObject) -> Self {
-        let mut env = Scope::from(self.closure.clone());
-        env.declare("this");
-        env.define("this", target);
-        Self::new(
-            Rc::new(RefCell::new(env)),
-            self.params.clone(),
-            self.body.clone(),
-        )
+    pub fn upvalues(&self) -> Rc<[UpValue]> {
+        self.upvalues.clone()
     }
 }
 
+// shown as `<fn name/arity>`, e.g. `<fn fib/1>`.
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "function(")?;
-        let max_len = self.params.len();
-        if max_len == 0 {
-            return write!(f, ") {{}}");
-        }
-        write!(f, "{}", self.params()[0])?;
-        for param in &self.params()[1..max_len.min(3)] {
-            write!(f, ", {}", param)?;
-        }
-        if max_len > 3 {
-            return write!(f, ", ...) {{}}");
-        } else {
-            return write!(f, ") {{}}");
-        }
+        let name = self.name().unwrap_or("anonymous");
+        write!(f, "<fn {}/{}>", name, self.arity())
     }
 }
@@ -0,0 +1,67 @@
+use super::object::LoxObject;
+use super::primitive::Primitive;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// A pool that hands out one shared `Rc<String>` for every distinct string contents.
+///
+/// Interned strings that are equal are also the same allocation, so comparing them stops at the
+/// pointer check instead of walking both strings. Nothing is ever evicted, the pool keeps every
+/// string it has seen alive for as long as the interpreter is around.
+#[derive(Debug, Default)]
+pub struct StringPool {
+    strings: HashSet<Rc<String>>,
+}
+
+impl StringPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the pooled copy of `value`, which becomes the pooled copy if there wasn't one yet.
+    pub fn intern(&mut self, value: &Rc<String>) -> Rc<String> {
+        if let Some(existing) = self.strings.get(value.as_ref()) {
+            return existing.clone();
+        }
+        self.strings.insert(value.clone());
+        value.clone()
+    }
+
+    /// interns `value` if it is a string, anything else is handed back untouched.
+    pub fn intern_object(&mut self, value: LoxObject) -> LoxObject {
+        match value {
+            LoxObject::Primitive(Primitive::String(s)) => {
+                LoxObject::Primitive(Primitive::String(self.intern(&s)))
+            }
+            other => other,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_shares_equal_strings() {
+        let mut pool = StringPool::new();
+        let a = pool.intern(&Rc::new("hello".to_string()));
+        let b = pool.intern(&Rc::new("hello".to_string()));
+        let c = pool.intern(&Rc::new("world".to_string()));
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &c));
+        assert_eq!(pool.len(), 2);
+
+        let number = pool.intern_object(LoxObject::from(1.0));
+        assert_eq!(number, LoxObject::from(1.0));
+        assert_eq!(pool.len(), 2);
+    }
+}
@@ -0,0 +1,481 @@
+//! Converting lox values to and from JSON text, for the `to_json` and `from_json` natives.
+//!
+//! Numbers, strings, booleans and nil map onto their JSON counterparts, arrays and sets become
+//! arrays, and maps and class instances become objects. Map keys are written as strings, so a
+//! map with number keys comes back from `from_json` keyed by strings. Instances keep their field
+//! order, map keys are sorted. Functions and classes have no JSON form and are an error, as are
+//! map keys that aren't primitives, two keys that are written the same, and a value that contains
+//! itself.
+use super::error::LoxError;
+use super::map::{LoxMap, LoxSet, MapKey, sorted_keys};
+use super::object::LoxObject;
+use super::primitive::Primitive;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+// how deeply `from_json` and `to_json` will nest arrays and objects before giving up, so hostile
+// input or a deeply linked value can't run the interpreter out of stack.
+const MAX_JSON_DEPTH: usize = 512;
+
+/// the JSON text for `value`.
+pub fn to_json(value: &LoxObject) -> Result<String, LoxError> {
+    let mut out = String::new();
+    write_value(value, &mut out, &mut HashSet::new())?;
+    Ok(out)
+}
+
+// `path` holds the containers being written further up, meeting one again means a cycle.
+fn write_value(
+    value: &LoxObject,
+    out: &mut String,
+    path: &mut HashSet<usize>,
+) -> Result<(), LoxError> {
+    let container = match value {
+        LoxObject::Array(a) => a.as_ptr() as usize,
+        LoxObject::Map(m) => m.as_ptr() as usize,
+        LoxObject::ClassInstance(i) => i.as_ptr() as usize,
+        // a set can't hold a container directly, only instances that are checked on their own.
+        LoxObject::Set(s) => return write_set(&s.borrow(), out, path),
+        _ => return write_flat(value, out),
+    };
+    if path.len() >= MAX_JSON_DEPTH {
+        let msg = format!("to_json() cannot serialize values nested over {MAX_JSON_DEPTH} deep");
+        return Err(LoxError::TypeError(msg));
+    }
+    if !path.insert(container) {
+        let msg = "to_json() cannot serialize a value that contains itself".to_string();
+        return Err(LoxError::TypeError(msg));
+    }
+    match value {
+        LoxObject::Array(a) => {
+            out.push('[');
+            for (idx, item) in a.borrow().iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_value(item, out, path)?;
+            }
+            out.push(']');
+        }
+        LoxObject::Map(m) => {
+            let map = m.borrow();
+            let entries = sorted_keys(&map)
+                .into_iter()
+                .map(|key| key_string(key).map(|key_str| (key_str, &map[key])))
+                .collect::<Result<Vec<_>, _>>()?;
+            // `1` and `"1"` are different keys in a map but would be the same one in the object.
+            let mut seen = HashSet::new();
+            if let Some((key, _)) = entries.iter().find(|(key, _)| !seen.insert(key)) {
+                let msg = format!("to_json() found more than one map key written as \"{key}\"");
+                return Err(LoxError::TypeError(msg));
+            }
+            write_object(entries.into_iter(), out, path)?;
+        }
+        LoxObject::ClassInstance(i) => {
+            let instance = i.borrow();
+            let entries = instance
+                .fields()
+                .map(|(name, value)| (name.to_string(), value));
+            write_object(entries, out, path)?;
+        }
+        _ => unreachable!("only containers make it past the first match"),
+    }
+    path.remove(&container);
+    Ok(())
+}
+
+fn write_object<'v>(
+    entries: impl Iterator<Item = (String, &'v LoxObject)>,
+    out: &mut String,
+    path: &mut HashSet<usize>,
+) -> Result<(), LoxError> {
+    out.push('{');
+    for (idx, (key, value)) in entries.enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+        write_string(&key, out);
+        out.push(':');
+        write_value(value, out, path)?;
+    }
+    out.push('}');
+    Ok(())
+}
+
+// values that can't hold other values.
+fn write_flat(value: &LoxObject, out: &mut String) -> Result<(), LoxError> {
+    match value {
+        LoxObject::Primitive(Primitive::Number(n)) if n.is_finite() => {
+            let _ = write!(out, "{}", n);
+        }
+        LoxObject::Primitive(Primitive::Number(n)) => {
+            let msg = format!("to_json() cannot serialize the number {}", n);
+            return Err(LoxError::TypeError(msg));
+        }
+        LoxObject::Primitive(Primitive::String(s)) => write_string(s, out),
+        LoxObject::Primitive(Primitive::Boolean(b)) => {
+            let _ = write!(out, "{}", b);
+        }
+        LoxObject::Primitive(Primitive::Nil) => out.push_str("null"),
+        _ => {
+            let msg = format!("to_json() cannot serialize type '{}'", value.type_name());
+            return Err(LoxError::TypeError(msg));
+        }
+    }
+    Ok(())
+}
+
+fn write_set(set: &LoxSet, out: &mut String, path: &mut HashSet<usize>) -> Result<(), LoxError> {
+    let mut items: Vec<_> = set.iter().collect();
+    items.sort_by_cached_key(|key| key.to_string());
+    out.push('[');
+    for (idx, item) in items.into_iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+        write_value(&LoxObject::from(item), out, path)?;
+    }
+    out.push(']');
+    Ok(())
+}
+
+fn key_string(key: &MapKey) -> Result<String, LoxError> {
+    match key {
+        MapKey::String(s) => Ok(s.to_string()),
+        MapKey::Number(bits) => Ok(f64::from_bits(*bits).to_string()),
+        MapKey::Boolean(b) => Ok(b.to_string()),
+        MapKey::Object(_) => {
+            let msg = format!(
+                "to_json() cannot use {} as an object key",
+                LoxObject::from(key).type_name()
+            );
+            Err(LoxError::TypeError(msg))
+        }
+    }
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// the value described by the JSON text `src`, objects becoming maps.
+pub fn from_json(src: &str) -> Result<LoxObject, LoxError> {
+    let mut reader = JsonReader {
+        src,
+        chars: src.char_indices().peekable(),
+    };
+    let value = reader.value(0)?;
+    reader.skip_whitespace();
+    match reader.chars.next() {
+        None => Ok(value),
+        Some((at, c)) => Err(json_error(
+            at,
+            &format!("unexpected '{}' after the value", c),
+        )),
+    }
+}
+
+fn json_error(at: usize, msg: &str) -> LoxError {
+    LoxError::UncaughtSyntaxError(format!("from_json() {} at offset {}", msg, at))
+}
+
+struct JsonReader<'a> {
+    src: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl JsonReader<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|(_, c)| matches!(c, ' ' | '\t' | '\n' | '\r'))
+            .is_some()
+        {}
+    }
+
+    fn next(&mut self) -> Result<(usize, char), LoxError> {
+        self.chars
+            .next()
+            .ok_or_else(|| json_error(self.src.len(), "unexpected end of input"))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), LoxError> {
+        self.skip_whitespace();
+        match self.next()? {
+            (_, c) if c == expected => Ok(()),
+            (at, c) => Err(json_error(
+                at,
+                &format!("expected '{}' but found '{}'", expected, c),
+            )),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<LoxObject, LoxError> {
+        self.skip_whitespace();
+        let Some(&(at, c)) = self.chars.peek() else {
+            return Err(json_error(self.src.len(), "unexpected end of input"));
+        };
+        if depth >= MAX_JSON_DEPTH && matches!(c, '[' | '{') {
+            return Err(json_error(at, "too deeply nested"));
+        }
+        match c {
+            '[' => self.array(depth),
+            '{' => self.object(depth),
+            '"' => Ok(LoxObject::from(self.string()?)),
+            't' => self.word("true", LoxObject::from(true)),
+            'f' => self.word("false", LoxObject::from(false)),
+            'n' => self.word("null", LoxObject::new_nil()),
+            '-' | '0'..='9' => self.number(),
+            c => Err(json_error(at, &format!("unexpected '{}'", c))),
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<LoxObject, LoxError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|(_, c)| *c == ']').is_some() {
+            return Ok(LoxObject::from(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.next()? {
+                (_, ',') => continue,
+                (_, ']') => return Ok(LoxObject::from(items)),
+                (at, c) => {
+                    return Err(json_error(
+                        at,
+                        &format!("expected ',' or ']' but found '{}'", c),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<LoxObject, LoxError> {
+        self.expect('{')?;
+        let mut map = LoxMap::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|(_, c)| *c == '}').is_some() {
+            return Ok(LoxObject::from(map));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = LoxObject::from(self.string()?);
+            self.expect(':')?;
+            let value = self.value(depth + 1)?;
+            // string keys always make valid map keys.
+            if let Ok(key) = MapKey::try_from(&key) {
+                map.insert(key, value);
+            }
+            self.skip_whitespace();
+            match self.next()? {
+                (_, ',') => continue,
+                (_, '}') => return Ok(LoxObject::from(map)),
+                (at, c) => {
+                    return Err(json_error(
+                        at,
+                        &format!("expected ',' or '}}' but found '{}'", c),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, LoxError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                (_, '"') => return Ok(s),
+                (_, '\\') => s.push(self.escape()?),
+                (at, c) if c.is_control() => {
+                    return Err(json_error(at, "unescaped control character in string"));
+                }
+                (_, c) => s.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, LoxError> {
+        let (at, c) = self.next()?;
+        Ok(match c {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let high = self.hex4()?;
+                // characters outside the basic plane are written as a surrogate pair.
+                if (0xd800..0xdc00).contains(&high) {
+                    self.expect('\\')?;
+                    self.expect('u')?;
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(json_error(at, "invalid surrogate pair"));
+                    }
+                    let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                    char::from_u32(code).ok_or_else(|| json_error(at, "invalid surrogate pair"))?
+                } else {
+                    char::from_u32(high).ok_or_else(|| json_error(at, "invalid unicode escape"))?
+                }
+            }
+            c => return Err(json_error(at, &format!("invalid escape '\\{}'", c))),
+        })
+    }
+
+    fn hex4(&mut self) -> Result<u32, LoxError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let (at, c) = self.next()?;
+            let digit = c
+                .to_digit(16)
+                .ok_or_else(|| json_error(at, &format!("invalid hex digit '{}'", c)))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn word(&mut self, word: &str, value: LoxObject) -> Result<LoxObject, LoxError> {
+        for expected in word.chars() {
+            let (at, c) = self.next()?;
+            if c != expected {
+                return Err(json_error(at, &format!("unexpected '{}'", c)));
+            }
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<LoxObject, LoxError> {
+        let start = self.chars.peek().map_or(self.src.len(), |(at, _)| *at);
+        // number → "-"? ( "0" | [1-9] [0-9]* ) ( "." [0-9]+ )? ( [eE] [+-]? [0-9]+ )?
+        self.chars.next_if(|(_, c)| *c == '-');
+        if self.chars.next_if(|(_, c)| *c == '0').is_none() {
+            self.digits(start)?;
+        }
+        if self.chars.next_if(|(_, c)| *c == '.').is_some() {
+            self.digits(start)?;
+        }
+        if self
+            .chars
+            .next_if(|(_, c)| matches!(c, 'e' | 'E'))
+            .is_some()
+        {
+            self.chars.next_if(|(_, c)| matches!(c, '+' | '-'));
+            self.digits(start)?;
+        }
+        let end = self.chars.peek().map_or(self.src.len(), |(at, _)| *at);
+        let text = &self.src[start..end];
+        match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(LoxObject::from(n)),
+            _ => Err(json_error(start, &format!("invalid number '{}'", text))),
+        }
+    }
+
+    // one or more digits, as each part of a number needs.
+    fn digits(&mut self, start: usize) -> Result<(), LoxError> {
+        if self.chars.next_if(|(_, c)| c.is_ascii_digit()).is_none() {
+            let end = self.chars.peek().map_or(self.src.len(), |(at, _)| *at);
+            let text = &self.src[start..end];
+            return Err(json_error(start, &format!("invalid number '{}'", text)));
+        }
+        while self.chars.next_if(|(_, c)| c.is_ascii_digit()).is_some() {}
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_nested() {
+        let src = r#"{"list":[1,2.5,-3,[true,false,null]],"name":"a \"quoted\"\nline","nested":{"empty":{},"none":[]}}"#;
+        let value = from_json(src).unwrap();
+        assert_eq!(to_json(&value).unwrap(), src);
+        // whitespace and escapes are accepted on the way in but not written back out.
+        let spaced = from_json(" [ \"\\u00e9\\ud83e\\udd80\" , 1e3 ] ").unwrap();
+        assert_eq!(to_json(&spaced).unwrap(), r#"["é🦀",1000]"#);
+    }
+
+    #[test]
+    fn test_rejects_invalid_json() {
+        for src in [
+            "",
+            "[1,]",
+            "{\"a\" 1}",
+            "tru",
+            "\"open",
+            "[1] 2",
+            "{1: 2}",
+            "01x",
+            "01",
+            "1.",
+            "1e400",
+            "-",
+            ".5",
+            "1e",
+        ] {
+            assert!(from_json(src).is_err(), "{src} should not parse");
+        }
+        let deep = "[".repeat(MAX_JSON_DEPTH + 1) + &"]".repeat(MAX_JSON_DEPTH + 1);
+        assert!(from_json(&deep).is_err());
+        assert!(to_json(&LoxObject::from(f64::NAN)).is_err());
+        // every part the grammar allows is still accepted.
+        let numbers = from_json("[0, -0.5, 10, 1.25e2, 2E-1, 3e+0]").unwrap();
+        assert_eq!(to_json(&numbers).unwrap(), "[0,-0.5,10,125,0.2,3]");
+    }
+
+    #[test]
+    fn test_to_json_rejects_colliding_keys() {
+        let mut map = LoxMap::new();
+        for (key, value) in [
+            (LoxObject::from(true), 1.0),
+            (LoxObject::from("true"), 2.0),
+            (LoxObject::from(1.0), 3.0),
+            (LoxObject::from("1"), 4.0),
+        ] {
+            map.insert(MapKey::try_from(&key).unwrap(), LoxObject::from(value));
+        }
+        let err = to_json(&LoxObject::from(map)).unwrap_err();
+        assert!(err.to_string().contains("more than one map key"), "{err}");
+    }
+
+    #[test]
+    fn test_to_json_caps_nesting() {
+        let nest = |depth: usize| {
+            (0..depth).fold(LoxObject::from(vec![]), |inner, _| {
+                LoxObject::from(vec![inner])
+            })
+        };
+        // the innermost array counts too, so this is exactly as deep as allowed.
+        let json = to_json(&nest(MAX_JSON_DEPTH - 1)).unwrap();
+        assert_eq!(
+            json,
+            "[".repeat(MAX_JSON_DEPTH) + &"]".repeat(MAX_JSON_DEPTH)
+        );
+        let err = to_json(&nest(MAX_JSON_DEPTH * 2)).unwrap_err();
+        assert!(err.to_string().contains("nested over 512 deep"), "{err}");
+    }
+}
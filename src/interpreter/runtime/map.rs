@@ -0,0 +1,213 @@
+use super::error::{LoxError, RuntimeError};
+use super::object::{LoxObject, MAX_DISPLAY_DEPTH};
+use super::primitive::Primitive;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+pub type LoxMap = HashMap<MapKey, LoxObject>;
+pub type LoxSet = HashSet<MapKey>;
+
+/// MapKey is the hashable subset of lox values that can be used to index a map or go in a set.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    String(Rc<String>),
+    // numbers are stored by their bits, with -0 folded into 0 so they hash the same way they compare.
+    Number(u64),
+    Boolean(bool),
+    Object(ObjectKey),
+}
+
+/// a function, class or instance used as a key. like `==` on them, two keys are only the same if
+/// they are the very same object, so they hash by address.
+#[derive(Debug, Clone)]
+pub struct ObjectKey(LoxObject);
+
+impl ObjectKey {
+    fn new(value: &LoxObject) -> Option<Self> {
+        match value {
+            LoxObject::Function(_) | LoxObject::Class(_) | LoxObject::ClassInstance(_) => {
+                Some(Self(value.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn address(&self) -> usize {
+        match &self.0 {
+            LoxObject::Function(f) => Rc::as_ptr(f) as usize,
+            LoxObject::Class(c) => Rc::as_ptr(c) as usize,
+            LoxObject::ClassInstance(i) => Rc::as_ptr(i) as usize,
+            _ => unreachable!("only functions, classes and instances make object keys"),
+        }
+    }
+}
+
+impl PartialEq for ObjectKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for ObjectKey {}
+
+impl Hash for ObjectKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state);
+    }
+}
+
+impl MapKey {
+    fn from_number(n: f64) -> Self {
+        let n = if n == 0.0 { 0.0 } else { n };
+        Self::Number(n.to_bits())
+    }
+}
+
+impl TryFrom<&LoxObject> for MapKey {
+    type Error = RuntimeError;
+    fn try_from(value: &LoxObject) -> Result<Self, Self::Error> {
+        match value {
+            LoxObject::Primitive(Primitive::String(s)) => Ok(Self::String(s.clone())),
+            LoxObject::Primitive(Primitive::Boolean(b)) => Ok(Self::Boolean(*b)),
+            LoxObject::Primitive(Primitive::Number(n)) if !n.is_nan() => Ok(Self::from_number(*n)),
+            _ if let Some(key) = ObjectKey::new(value) => Ok(Self::Object(key)),
+            _ => {
+                let msg = format!("cannot use {} as a map key", value.type_name());
+                Err(LoxError::TypeError(msg).into())
+            }
+        }
+    }
+}
+
+impl From<&MapKey> for LoxObject {
+    fn from(value: &MapKey) -> Self {
+        match value {
+            MapKey::String(s) => LoxObject::Primitive(Primitive::String(s.clone())),
+            MapKey::Number(bits) => f64::from_bits(*bits).into(),
+            MapKey::Boolean(b) => (*b).into(),
+            MapKey::Object(key) => key.0.clone(),
+        }
+    }
+}
+
+impl MapKey {
+    // like `Display`, but an instance key only shows its fields up to `MAX_DISPLAY_DEPTH`.
+    fn fmt_nested(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        match self {
+            MapKey::String(s) => write!(f, "\"{}\"", s),
+            MapKey::Number(bits) => write!(f, "{}", f64::from_bits(*bits)),
+            MapKey::Boolean(b) => write!(f, "{}", b),
+            MapKey::Object(key) => key.0.fmt_nested(f, depth),
+        }
+    }
+}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_nested(f, 0)
+    }
+}
+
+/// the keys of `map` in a stable order, hash maps don't keep one of their own.
+pub fn sorted_keys(map: &LoxMap) -> Vec<&MapKey> {
+    let mut keys: Vec<_> = map.keys().collect();
+    keys.sort_by_cached_key(|key| key.to_string());
+    keys
+}
+
+pub(crate) fn fmt_map(map: &LoxMap, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+    if map.is_empty() {
+        return write!(f, "{{}}");
+    }
+    if depth >= MAX_DISPLAY_DEPTH {
+        return write!(f, "{{ ... }}");
+    }
+    write!(f, "{{ ")?;
+    for (idx, key) in sorted_keys(map).into_iter().enumerate() {
+        if idx > 0 {
+            write!(f, ", ")?;
+        }
+        key.fmt_nested(f, depth + 1)?;
+        write!(f, ": ")?;
+        map[key].fmt_nested(f, depth + 1)?;
+    }
+    write!(f, " }}")
+}
+
+pub(crate) fn fmt_set(set: &LoxSet, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+    if !set.is_empty() && depth >= MAX_DISPLAY_DEPTH {
+        return write!(f, "set(...)");
+    }
+    let mut elements: Vec<_> = set.iter().collect();
+    elements.sort_by_cached_key(|element| element.to_string());
+    write!(f, "set(")?;
+    for (idx, element) in elements.into_iter().enumerate() {
+        if idx > 0 {
+            write!(f, ", ")?;
+        }
+        element.fmt_nested(f, depth + 1)?;
+    }
+    write!(f, ")")
+}
+
+pub(crate) fn fmt_array(
+    items: &[LoxObject],
+    f: &mut fmt::Formatter<'_>,
+    depth: usize,
+) -> fmt::Result {
+    if items.is_empty() {
+        return write!(f, "[]");
+    }
+    if depth >= MAX_DISPLAY_DEPTH {
+        return write!(f, "[...]");
+    }
+    write!(f, "[")?;
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            write!(f, ", ")?;
+        }
+        item.fmt_nested(f, depth + 1)?;
+    }
+    write!(f, "]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::runtime::class::{Class, ClassInstance};
+
+    #[test]
+    fn test_number_keys_fold_negative_zero() {
+        let zero = MapKey::try_from(&LoxObject::from(0.0)).unwrap();
+        let neg_zero = MapKey::try_from(&LoxObject::from(-0.0)).unwrap();
+        assert_eq!(zero, neg_zero);
+        assert!(MapKey::try_from(&LoxObject::from(f64::NAN)).is_err());
+        assert!(MapKey::try_from(&LoxObject::new_nil()).is_err());
+    }
+
+    #[test]
+    fn test_object_keys_hash_by_identity() {
+        use std::collections::hash_map::DefaultHasher;
+        let hash = |key: &MapKey| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+        let class = Rc::new(Class::new(
+            "A".to_string(),
+            HashMap::new(),
+            HashMap::new(),
+            None,
+        ));
+        let a = LoxObject::from(ClassInstance::new(class.clone()));
+        let b = LoxObject::from(ClassInstance::new(class));
+        let key = MapKey::try_from(&a).unwrap();
+        let same = MapKey::try_from(&a.clone()).unwrap();
+        assert_eq!(key, same);
+        assert_eq!(hash(&key), hash(&same));
+        assert_ne!(key, MapKey::try_from(&b).unwrap());
+        assert_eq!(LoxObject::from(&key), a);
+    }
+}
@@ -2,8 +2,15 @@ pub mod class;
 pub mod control;
 pub mod error;
 pub mod eval;
+pub mod frame;
 pub mod function;
+pub mod intern;
+pub mod json;
+pub mod map;
 pub mod native;
+pub mod number;
 pub mod object;
 pub mod primitive;
-pub mod scope;
+pub mod profile;
+pub mod trace;
+pub mod weak;
@@ -1,16 +1,71 @@
+use super::class::ClassInstance;
 use super::eval::Eval;
+use super::json;
+use super::map::{LoxSet, MapKey, sorted_keys};
 use super::object::LoxObject;
+use super::weak::WeakRef;
 use crate::interpreter::lox::Lox;
 use crate::interpreter::runtime::error::LoxError;
 use crate::interpreter::runtime::error::NativeError;
 use crate::interpreter::runtime::error::RuntimeError;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub type NativeFn = fn(&mut Lox, Vec<LoxObject>) -> Result<Eval, RuntimeError>;
 
+// every native global along with how many arguments it takes, `None` if it accepts a varying number.
+const NATIVES: &[(&str, NativeFn, Option<usize>)] = &[
+    ("clock", clock, Some(0)),
+    ("string", to_string, Some(1)),
+    ("is_instance", is_instance, Some(2)),
+    ("keys", keys, Some(1)),
+    ("deep_eq", deep_eq, Some(2)),
+    ("assert", assert, None),
+    ("assert_eq", assert_eq, None),
+    ("write", write, Some(1)),
+    ("format", format, None),
+    ("set", set, None),
+    ("add", add, Some(2)),
+    ("has", has, Some(2)),
+    ("remove", remove, Some(2)),
+    ("size", size, Some(1)),
+    ("arity", arity, Some(1)),
+    ("is_callable", is_callable, Some(1)),
+    ("intern", intern, Some(1)),
+    ("chars", chars, Some(1)),
+    ("has_field", has_field, Some(2)),
+    ("delete_field", delete_field, Some(2)),
+    ("to_json", to_json, Some(1)),
+    ("from_json", from_json, Some(1)),
+    ("weak", weak, Some(1)),
+    ("deref", deref, Some(1)),
+    ("ref_count", ref_count, Some(1)),
+];
+
 pub fn setup_native(runtime: &mut Lox) {
-    runtime.set_global("clock", LoxObject::Native(clock));
-    runtime.set_global("string", LoxObject::Native(to_string));
+    for (name, native, _) in NATIVES {
+        runtime.register_native(name, *native);
+    }
+}
+
+/// whether `name` is one of the natives every program starts with.
+pub fn is_native(name: &str) -> bool {
+    NATIVES.iter().any(|(native, _, _)| *native == name)
+}
+
+/// the number of arguments a native takes, `None` for natives that accept a varying number.
+pub fn native_arity(native: NativeFn) -> Option<usize> {
+    NATIVES
+        .iter()
+        .find(|(_, f, _)| std::ptr::fn_addr_eq(*f, native))
+        .and_then(|(_, _, arity)| *arity)
+}
+
+// an InvalidArguments error pointing at the call the arguments were passed to.
+fn invalid_arguments(lox: &Lox, msg: impl Into<String>) -> RuntimeError {
+    let err = LoxError::from(NativeError::InvalidArguments(msg.into()));
+    RuntimeError::from(err).with_place(lox.current_call_span().start)
 }
 
 pub fn clock(_lox: &mut Lox, _args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
@@ -24,10 +79,411 @@ pub fn clock(_lox: &mut Lox, _args: Vec<LoxObject>) -> Result<Eval, RuntimeError
     }
 }
 
-pub fn to_string(_lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+pub fn to_string(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
     if args.len() != 1 {
-        let err = NativeError::InvalidArguments("to_string() takes only one argument".to_string());
+        return Err(invalid_arguments(
+            lox,
+            "to_string() takes only one argument",
+        ));
+    }
+    Ok(Eval::Object(LoxObject::from(lox.display(&args[0]))))
+}
+
+/// is_instance(obj, class) checks whether `obj` was created by `class` or one of its subclasses.
+pub fn is_instance(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [obj, class] = &args[..] else {
+        return Err(invalid_arguments(lox, "is_instance() takes two arguments"));
+    };
+    let LoxObject::Class(class) = class else {
+        let msg = format!(
+            "is_instance() expected type 'class' but recieved {}",
+            class.type_name()
+        );
+        return Err(LoxError::TypeError(msg).into());
+    };
+    let result = match obj {
+        LoxObject::ClassInstance(ci) => ci.borrow().constructor().is_or_inherits(class),
+        _ => false,
+    };
+    Ok(LoxObject::from(result).into())
+}
+
+/// keys(map) returns an array of the map's keys in a stable order.
+pub fn keys(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [LoxObject::Map(map)] = &args[..] else {
+        return Err(invalid_arguments(lox, "keys() takes a single map"));
+    };
+    let map = map.borrow();
+    let keys: Vec<LoxObject> = sorted_keys(&map).into_iter().map(LoxObject::from).collect();
+    Ok(LoxObject::from(keys).into())
+}
+
+/// deep_eq(a, b) compares arrays, maps and instances by their contents rather than identity.
+pub fn deep_eq(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [a, b] = &args[..] else {
+        return Err(invalid_arguments(lox, "deep_eq() takes two arguments"));
+    };
+    Ok(LoxObject::from(a.deep_eq(b)).into())
+}
+
+/// arity(fn) is how many parameters `fn` declares, or nil for natives that take any number.
+pub fn arity(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [callable] = &args[..] else {
+        return Err(invalid_arguments(lox, "arity() takes only one argument"));
+    };
+    let arity = match callable {
+        LoxObject::Function(f) | LoxObject::BoundMethod(_, f) => Some(f.arity()),
+        LoxObject::Native(_, f) => native_arity(*f),
+        _ => {
+            let msg = format!(
+                "arity() expected type 'function' but recieved {}",
+                callable.type_name()
+            );
+            return Err(LoxError::TypeError(msg).into());
+        }
+    };
+    Ok(arity
+        .map(|n| LoxObject::from(n as f64))
+        .unwrap_or_else(LoxObject::new_nil)
+        .into())
+}
+
+/// is_callable(value) checks whether `value` is a function, native or class.
+pub fn is_callable(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [value] = &args[..] else {
+        return Err(invalid_arguments(
+            lox,
+            "is_callable() takes only one argument",
+        ));
+    };
+    Ok(LoxObject::from(value.is_callable()).into())
+}
+
+/// intern(string) hands back the shared copy of `string` when the interpreter is interning strings,
+/// so later comparisons against other interned copies are quick.
+pub fn intern(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [value] = &args[..] else {
+        return Err(invalid_arguments(lox, "intern() takes only one argument"));
+    };
+    if value.as_string().is_none() {
+        let msg = format!(
+            "intern() expected type 'string' but recieved {}",
+            value.type_name()
+        );
+        return Err(LoxError::TypeError(msg).into());
+    }
+    Ok(lox.intern(value.clone()).into())
+}
+
+/// chars(string) splits `string` into an array of its characters, each a one character string.
+pub fn chars(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [value] = &args[..] else {
+        return Err(invalid_arguments(lox, "chars() takes only one argument"));
+    };
+    let Some(s) = value.as_string() else {
+        let msg = format!(
+            "chars() expected type 'string' but recieved {}",
+            value.type_name()
+        );
+        return Err(LoxError::TypeError(msg).into());
+    };
+    let chars: Vec<LoxObject> = s.chars().map(|c| LoxObject::from(c.to_string())).collect();
+    Ok(LoxObject::from(chars).into())
+}
+
+/// weak(value) refers to the instance, map or array `value` without keeping it alive, so it can
+/// close a cycle that would otherwise never be freed. see `weak.rs`.
+pub fn weak(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [value] = &args[..] else {
+        return Err(invalid_arguments(lox, "weak() takes only one argument"));
+    };
+    let Some(weak) = WeakRef::new(value) else {
+        let msg = format!(
+            "weak() expected type 'instance, map or array' but recieved {}",
+            value.type_name()
+        );
+        return Err(LoxError::TypeError(msg).into());
+    };
+    Ok(LoxObject::Weak(weak).into())
+}
+
+/// deref(ref) is the value the weak reference `ref` points at, or nil once it has been freed.
+pub fn deref(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [value] = &args[..] else {
+        return Err(invalid_arguments(lox, "deref() takes only one argument"));
+    };
+    let LoxObject::Weak(weak) = value else {
+        let msg = format!(
+            "deref() expected type 'weak reference' but recieved {}",
+            value.type_name()
+        );
+        return Err(LoxError::TypeError(msg).into());
+    };
+    Ok(weak.upgrade().unwrap_or_else(LoxObject::new_nil).into())
+}
+
+/// ref_count(value) is how many references keep `value` alive, not counting the one passed to
+/// `ref_count` itself. weak references don't count.
+pub fn ref_count(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [value] = &args[..] else {
+        return Err(invalid_arguments(
+            lox,
+            "ref_count() takes only one argument",
+        ));
+    };
+    let count = match value {
+        LoxObject::Class(c) => Rc::strong_count(c),
+        LoxObject::ClassInstance(i) => Rc::strong_count(i),
+        LoxObject::Function(f) => Rc::strong_count(f),
+        LoxObject::Map(m) => Rc::strong_count(m),
+        LoxObject::Array(a) => Rc::strong_count(a),
+        LoxObject::Set(s) => Rc::strong_count(s),
+        _ => {
+            let msg = format!(
+                "ref_count() expected type 'object' but recieved {}",
+                value.type_name()
+            );
+            return Err(LoxError::TypeError(msg).into());
+        }
+    };
+    Ok(LoxObject::from((count - 1) as f64).into())
+}
+
+/// has_field(obj, name) checks whether the instance `obj` has a field called `name`. methods
+/// don't count as fields.
+pub fn has_field(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let (instance, name) = instance_and_field(lox, "has_field", &args)?;
+    Ok(LoxObject::from(instance.borrow().contains(name)).into())
+}
+
+/// delete_field(obj, name) removes the field `name` from the instance `obj`, returning whether it
+/// was there. methods belong to the class, so they can't be deleted this way.
+pub fn delete_field(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let (instance, name) = instance_and_field(lox, "delete_field", &args)?;
+    let removed = instance.borrow_mut().remove(name).is_some();
+    Ok(LoxObject::from(removed).into())
+}
+
+fn instance_and_field<'a>(
+    lox: &Lox,
+    native: &str,
+    args: &'a [LoxObject],
+) -> Result<(&'a Rc<RefCell<ClassInstance>>, &'a str), RuntimeError> {
+    let [obj, name] = args else {
+        let msg = format!("{}() takes an instance and a field name", native);
+        return Err(invalid_arguments(lox, msg));
+    };
+    let LoxObject::ClassInstance(instance) = obj else {
+        let msg = format!(
+            "{}() expected type 'class instance' but recieved {}",
+            native,
+            obj.type_name()
+        );
+        return Err(LoxError::TypeError(msg).into());
+    };
+    let Some(name) = name.as_string() else {
+        let msg = format!(
+            "{}() expected a string field name but recieved {}",
+            native,
+            name.type_name()
+        );
+        return Err(LoxError::TypeError(msg).into());
+    };
+    Ok((instance, name))
+}
+
+/// write(value) prints `value` like `print` does, but without the trailing newline.
+pub fn write(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [value] = &args[..] else {
+        return Err(invalid_arguments(lox, "write() takes only one argument"));
+    };
+    let text = lox.display(value);
+    let out = lox.output();
+    if write!(out, "{}", text).and_then(|_| out.flush()).is_err() {
+        let err = NativeError::SystemError("write() failed to write its output".to_string());
         return Err(LoxError::from(err).into());
     }
-    Ok(Eval::Object(LoxObject::from(args[0].to_string())))
+    Ok(LoxObject::new_nil().into())
+}
+
+/// format(fmt, ...args) fills each `{}` in `fmt` with the next argument, `{{` and `}}` are literal braces.
+pub fn format(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let invalid = |msg: String| Err(invalid_arguments(lox, msg));
+    let Some(fmt) = args.first().and_then(|f| f.as_string()) else {
+        return invalid("format() takes a format string followed by its arguments".to_string());
+    };
+    let mut values = args[1..].iter();
+    let mut out = String::with_capacity(fmt.len());
+    let mut chars = fmt.chars().peekable();
+    let mut placeholders = 0;
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                out.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                placeholders += 1;
+                if let Some(value) = values.next() {
+                    out.push_str(&lox.display(value));
+                }
+            }
+            ('{', _) | ('}', _) => {
+                return invalid(format!(
+                    "format() found an unmatched '{}', use '{}{}' for a literal brace",
+                    c, c, c
+                ));
+            }
+            _ => out.push(c),
+        }
+    }
+    let given = args.len() - 1;
+    if placeholders != given {
+        return invalid(format!(
+            "format() string has {} placeholders but was given {} arguments",
+            placeholders, given
+        ));
+    }
+    Ok(LoxObject::from(out).into())
+}
+
+/// assert(condition, message?) raises an AssertionError when `condition` is falsey.
+pub fn assert(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let (condition, message) = match &args[..] {
+        [condition] => (condition, None),
+        [condition, message] => (condition, Some(message)),
+        _ => {
+            let msg = "assert() takes a condition and an optional message".to_string();
+            return Err(invalid_arguments(lox, msg));
+        }
+    };
+    if condition.truthy() {
+        return Ok(LoxObject::new_nil().into());
+    }
+    let msg = match message {
+        Some(message) => message.to_string(),
+        None => format!("assertion failed, {} is falsey", repr(condition)),
+    };
+    Err(assertion_error(lox, msg))
+}
+
+/// assert_eq(left, right, message?) raises an AssertionError unless `left == right`.
+pub fn assert_eq(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let (left, right, message) = match &args[..] {
+        [left, right] => (left, right, None),
+        [left, right, message] => (left, right, Some(message)),
+        _ => {
+            let msg = "assert_eq() takes two values and an optional message".to_string();
+            return Err(invalid_arguments(lox, msg));
+        }
+    };
+    if left == right {
+        return Ok(LoxObject::new_nil().into());
+    }
+    let mut msg = format!("{} != {}", repr(left), repr(right));
+    if let Some(message) = message {
+        msg = format!("{}: {}", message, msg);
+    }
+    Err(assertion_error(lox, msg))
+}
+
+// failures point at the start of the assert itself rather than its arguments' paren.
+fn assertion_error(lox: &Lox, msg: String) -> RuntimeError {
+    RuntimeError::from(LoxError::AssertionError(msg)).with_place(lox.current_call_span().start)
+}
+
+// strings are quoted so `1` and `"1"` can be told apart in a failure.
+fn repr(value: &LoxObject) -> String {
+    if value.is_string() {
+        format!("\"{}\"", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// set(...values) creates a set holding each distinct value.
+pub fn set(_lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let elements = args
+        .iter()
+        .map(set_element)
+        .collect::<Result<LoxSet, _>>()?;
+    Ok(LoxObject::from(elements).into())
+}
+
+/// add(set, value) puts `value` in the set, returning whether it wasn't already there.
+pub fn add(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let (set, value) = set_and_value(lox, "add", &args)?;
+    let added = set.borrow_mut().insert(set_element(value)?);
+    Ok(LoxObject::from(added).into())
+}
+
+/// has(set, value) checks whether `value` is in the set.
+pub fn has(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let (set, value) = set_and_value(lox, "has", &args)?;
+    // nothing unhashable can be in the set to begin with.
+    let found = MapKey::try_from(value).is_ok_and(|key| set.borrow().contains(&key));
+    Ok(LoxObject::from(found).into())
+}
+
+/// remove(set, value) takes `value` out of the set, returning whether it was there.
+pub fn remove(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let (set, value) = set_and_value(lox, "remove", &args)?;
+    let removed = MapKey::try_from(value).is_ok_and(|key| set.borrow_mut().remove(&key));
+    Ok(LoxObject::from(removed).into())
+}
+
+/// size(set) is the number of values in the set.
+pub fn size(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [LoxObject::Set(set)] = &args[..] else {
+        return Err(invalid_arguments(lox, "size() takes a single set"));
+    };
+    Ok(LoxObject::from(set.borrow().len() as f64).into())
+}
+
+fn set_and_value<'a>(
+    lox: &Lox,
+    name: &str,
+    args: &'a [LoxObject],
+) -> Result<(&'a Rc<RefCell<LoxSet>>, &'a LoxObject), RuntimeError> {
+    match args {
+        [LoxObject::Set(set), value] => Ok((set, value)),
+        _ => {
+            let msg = format!("{}() takes a set and a value", name);
+            Err(invalid_arguments(lox, msg))
+        }
+    }
+}
+
+fn set_element(value: &LoxObject) -> Result<MapKey, RuntimeError> {
+    MapKey::try_from(value).map_err(|_| {
+        let msg = format!("cannot add {} to a set", value.type_name());
+        LoxError::TypeError(msg).into()
+    })
+}
+
+/// to_json(value) is the JSON text for `value`, see `json.rs` for how each type is written.
+pub fn to_json(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [value] = &args[..] else {
+        return Err(invalid_arguments(lox, "to_json() takes only one argument"));
+    };
+    Ok(LoxObject::from(json::to_json(value)?).into())
+}
+
+/// from_json(string) builds the value `string` describes, with JSON objects becoming maps.
+pub fn from_json(lox: &mut Lox, args: Vec<LoxObject>) -> Result<Eval, RuntimeError> {
+    let [value] = &args[..] else {
+        return Err(invalid_arguments(
+            lox,
+            "from_json() takes only one argument",
+        ));
+    };
+    let Some(s) = value.as_string() else {
+        let msg = format!(
+            "from_json() expected type 'string' but recieved {}",
+            value.type_name()
+        );
+        return Err(LoxError::TypeError(msg).into());
+    };
+    Ok(json::from_json(s)?.into())
 }
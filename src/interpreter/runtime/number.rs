@@ -0,0 +1,77 @@
+/// how numbers are written out when a program prints or stringifies them.
+/// the default matches `Display`, the shortest text that reads back as the same number.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NumberFormat {
+    // digits after the decimal point, `None` for as many as the number needs.
+    precision: Option<usize>,
+    thousands_separator: Option<char>,
+}
+
+impl NumberFormat {
+    pub fn with_precision(mut self, digits: usize) -> Self {
+        self.precision = Some(digits);
+        self
+    }
+
+    pub fn with_thousands_separator(mut self, separator: char) -> Self {
+        self.thousands_separator = Some(separator);
+        self
+    }
+
+    pub fn format(&self, n: f64) -> String {
+        let text = match self.precision {
+            Some(digits) => format!("{:.*}", digits, n),
+            None => n.to_string(),
+        };
+        match self.thousands_separator {
+            Some(separator) if n.is_finite() => group_thousands(&text, separator),
+            _ => text,
+        }
+    }
+}
+
+fn group_thousands(text: &str, separator: char) -> String {
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text),
+    };
+    let split = unsigned.find('.').unwrap_or(unsigned.len());
+    let (whole, fraction) = unsigned.split_at(split);
+    let mut out = String::with_capacity(text.len() + whole.len() / 3);
+    out.push_str(sign);
+    for (idx, digit) in whole.chars().enumerate() {
+        if idx > 0 && (whole.len() - idx) % 3 == 0 {
+            out.push(separator);
+        }
+        out.push(digit);
+    }
+    out.push_str(fraction);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_matches_display() {
+        let format = NumberFormat::default();
+        for n in [0.0, -1.5, 1e21, 1.0 / 3.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(format.format(n), n.to_string());
+        }
+    }
+
+    #[test]
+    fn test_precision_and_separators() {
+        let format = NumberFormat::default()
+            .with_precision(2)
+            .with_thousands_separator(',');
+        assert_eq!(format.format(1234567.891), "1,234,567.89");
+        assert_eq!(format.format(-1234.5), "-1,234.50");
+        assert_eq!(format.format(123.0), "123.00");
+        assert_eq!(format.format(f64::NEG_INFINITY), "-inf");
+        let grouped = NumberFormat::default().with_thousands_separator('_');
+        assert_eq!(grouped.format(1000000.0), "1_000_000");
+        assert_eq!(grouped.format(100.25), "100.25");
+    }
+}
@@ -1,19 +1,34 @@
 use super::class::{Class, ClassInstance};
 use super::function::Function;
+use super::map::{LoxMap, LoxSet, fmt_array, fmt_map, fmt_set};
 use super::native::NativeFn;
 use super::primitive::Primitive;
+use super::weak::WeakRef;
 use crate::lang::tree::ast;
 use std::cell::RefCell;
 use std::fmt;
 use std::rc::Rc;
 
+// how many containers deep we will print before eliding their contents, this keeps
+// self-referential values from recursing forever.
+pub(crate) const MAX_DISPLAY_DEPTH: usize = 3;
+
 #[derive(Debug, Clone)]
 pub enum LoxObject {
     Primitive(Primitive),
     Class(Rc<Class>),
     ClassInstance(Rc<RefCell<ClassInstance>>),
     Function(Rc<Function>),
-    Native(NativeFn),
+    // a method read off an instance. `this` is only put in scope once it is called, so reading a
+    // method doesn't have to allocate anything.
+    BoundMethod(Rc<RefCell<ClassInstance>>, Rc<Function>),
+    // natives carry the name they were registered under so they can be shown by it.
+    Native(&'static str, NativeFn),
+    Map(Rc<RefCell<LoxMap>>),
+    Array(Rc<RefCell<Vec<LoxObject>>>),
+    Set(Rc<RefCell<LoxSet>>),
+    // made by `weak(value)`, see `weak.rs`.
+    Weak(WeakRef),
 }
 
 impl From<ast::Literal> for LoxObject {
@@ -91,39 +106,67 @@ impl From<ClassInstance> for LoxObject {
     }
 }
 
+impl From<LoxMap> for LoxObject {
+    fn from(value: LoxMap) -> Self {
+        LoxObject::Map(Rc::new(RefCell::new(value)))
+    }
+}
+
+impl From<LoxSet> for LoxObject {
+    fn from(value: LoxSet) -> Self {
+        LoxObject::Set(Rc::new(RefCell::new(value)))
+    }
+}
+
+impl From<Vec<LoxObject>> for LoxObject {
+    fn from(value: Vec<LoxObject>) -> Self {
+        LoxObject::Array(Rc::new(RefCell::new(value)))
+    }
+}
+
 impl fmt::Display for LoxObject {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             LoxObject::Primitive(prim) => write!(f, "{}", prim),
-            LoxObject::Function(func) => write!(f, "{}", func),
-            LoxObject::Native(_) => write!(f, "[native]()"),
+            LoxObject::Function(func) | LoxObject::BoundMethod(_, func) => write!(f, "{}", func),
+            LoxObject::Native(name, _) => write!(f, "<native fn {}>", name),
             LoxObject::Class(c) => write!(f, "{}", c),
             LoxObject::ClassInstance(i) => write!(f, "{}", i.borrow()),
+            LoxObject::Map(m) => fmt_map(&m.borrow(), f, 0),
+            LoxObject::Array(a) => fmt_array(&a.borrow(), f, 0),
+            LoxObject::Set(set) => fmt_set(&set.borrow(), f, 0),
+            LoxObject::Weak(w) => write!(f, "{}", w),
         }
     }
 }
 
+/// `==` in lox. values of different types are never equal, so `1 == "1"` and `nil == false` are
+/// both false. numbers, strings, booleans and nil compare by value (with `0 / 0` not equal to
+/// itself, as usual for floats), everything else is only equal to the very same object.
 impl PartialEq for LoxObject {
     fn eq(&self, other: &LoxObject) -> bool {
         match (self, other) {
             (LoxObject::Primitive(a), LoxObject::Primitive(b)) => a.eq(b),
             (LoxObject::Function(f1), LoxObject::Function(f2)) => Rc::ptr_eq(f1, f2),
+            (LoxObject::BoundMethod(i1, f1), LoxObject::BoundMethod(i2, f2)) => {
+                Rc::ptr_eq(i1, i2) && Rc::ptr_eq(f1, f2)
+            }
             (LoxObject::Class(c1), LoxObject::Class(c2)) => Rc::ptr_eq(c1, c2),
             (LoxObject::ClassInstance(c1), LoxObject::ClassInstance(c2)) => Rc::ptr_eq(c1, c2),
+            (LoxObject::Map(m1), LoxObject::Map(m2)) => Rc::ptr_eq(m1, m2),
+            (LoxObject::Array(a1), LoxObject::Array(a2)) => Rc::ptr_eq(a1, a2),
+            (LoxObject::Set(s1), LoxObject::Set(s2)) => Rc::ptr_eq(s1, s2),
+            (LoxObject::Weak(w1), LoxObject::Weak(w2)) => w1.ptr_eq(w2),
             // function pointers are not guarranteed to have a consistent memory address
             // see: https://doc.rust-lang.org/nightly/core/ptr/fn.fn_addr_eq.html
             //
             // However, I think that because of the way we have implemented native functions as a
             // function pointer that is created - and bound - only once on runtime startup,
             // we are always copying that address by value if we assign some expression to it.
-            (LoxObject::Native(f1), LoxObject::Native(f2)) => std::ptr::fn_addr_eq(*f1, *f2),
+            (LoxObject::Native(_, f1), LoxObject::Native(_, f2)) => std::ptr::fn_addr_eq(*f1, *f2),
             _ => false,
         }
     }
-
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
-    }
 }
 
 impl LoxObject {
@@ -132,38 +175,34 @@ impl LoxObject {
     }
 
     pub fn is_number(&self) -> bool {
-        match self {
-            LoxObject::Primitive(Primitive::Number(_)) => true,
-            _ => false,
-        }
+        matches!(self, LoxObject::Primitive(Primitive::Number(_)))
     }
 
     pub fn is_string(&self) -> bool {
-        match self {
-            LoxObject::Primitive(Primitive::String(_)) => true,
-            _ => false,
-        }
+        matches!(self, LoxObject::Primitive(Primitive::String(_)))
     }
 
     pub fn is_boolean(&self) -> bool {
-        match self {
-            LoxObject::Primitive(Primitive::Boolean(_)) => true,
-            _ => false,
-        }
+        matches!(self, LoxObject::Primitive(Primitive::Boolean(_)))
     }
 
     pub fn is_nil(&self) -> bool {
-        match self {
-            LoxObject::Primitive(Primitive::Nil) => true,
-            _ => false,
-        }
+        matches!(self, LoxObject::Primitive(Primitive::Nil))
     }
 
     pub fn is_function(&self) -> bool {
-        match self {
-            LoxObject::Function { .. } => true,
-            _ => false,
-        }
+        matches!(self, LoxObject::Function(_) | LoxObject::BoundMethod(..))
+    }
+
+    /// functions, natives and classes (which construct an instance) can all be called.
+    pub fn is_callable(&self) -> bool {
+        matches!(
+            self,
+            LoxObject::Function(_)
+                | LoxObject::BoundMethod(..)
+                | LoxObject::Native(..)
+                | LoxObject::Class(_)
+        )
     }
 
     pub fn as_number(&self) -> Option<f64> {
@@ -205,13 +244,91 @@ impl LoxObject {
         }
     }
 
+    /// like `type_str` but specific enough for error messages, i.e. instances report their class.
+    pub fn type_name(&self) -> String {
+        match self {
+            LoxObject::ClassInstance(i) => {
+                format!("instance of {}", i.borrow().constructor().name())
+            }
+            _ => self.type_str().to_string(),
+        }
+    }
+
     pub fn type_str(&self) -> &str {
         match self {
             LoxObject::Primitive(p) => p.type_str(),
-            LoxObject::Function(_) => "function",
-            LoxObject::Native(_) => "native function",
+            LoxObject::Function(_) | LoxObject::BoundMethod(..) => "function",
+            LoxObject::Native(..) => "native function",
             LoxObject::Class(_) => "class",
             LoxObject::ClassInstance(_) => "class instance",
+            LoxObject::Map(_) => "map",
+            LoxObject::Array(_) => "array",
+            LoxObject::Set(_) => "set",
+            LoxObject::Weak(_) => "weak reference",
+        }
+    }
+
+    /// structural equality: arrays compare element-wise, maps key/value-wise and instances of
+    /// the same class field-wise. `==` stays identity based for all of these.
+    pub fn deep_eq(&self, other: &LoxObject) -> bool {
+        self.deep_eq_seen(other, &mut Vec::new())
+    }
+
+    // `seen` holds the pairs of containers already being compared further up, running into one
+    // again means we've gone around a cycle and found no difference along the way.
+    fn deep_eq_seen(&self, other: &LoxObject, seen: &mut Vec<(usize, usize)>) -> bool {
+        let pair = match (self, other) {
+            (LoxObject::Array(a), LoxObject::Array(b)) => {
+                (a.as_ptr() as usize, b.as_ptr() as usize)
+            }
+            (LoxObject::Map(a), LoxObject::Map(b)) => (a.as_ptr() as usize, b.as_ptr() as usize),
+            (LoxObject::ClassInstance(a), LoxObject::ClassInstance(b)) => {
+                (a.as_ptr() as usize, b.as_ptr() as usize)
+            }
+            // set elements are compared by value or identity, never by their contents, so this
+            // can't go around a cycle.
+            (LoxObject::Set(a), LoxObject::Set(b)) => return *a.borrow() == *b.borrow(),
+            _ => return self == other,
+        };
+        if pair.0 == pair.1 || seen.contains(&pair) {
+            return true;
+        }
+        seen.push(pair);
+        let equal = match (self, other) {
+            (LoxObject::Array(a), LoxObject::Array(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.deep_eq_seen(y, seen))
+            }
+            (LoxObject::Map(a), LoxObject::Map(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(key, x)| b.get(key).is_some_and(|y| x.deep_eq_seen(y, seen)))
+            }
+            (LoxObject::ClassInstance(a), LoxObject::ClassInstance(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                let (x, y) = (a.properties(), b.properties());
+                Rc::ptr_eq(a.constructor(), b.constructor())
+                    && x.len() == y.len()
+                    && x.iter()
+                        .all(|(key, v)| y.get(key).is_some_and(|w| v.deep_eq_seen(w, seen)))
+            }
+            _ => unreachable!("only containers make it past the first match"),
+        };
+        seen.pop();
+        equal
+    }
+
+    /// format a value that sits inside of a container. strings are quoted so they stand out
+    /// from their neighbours and nested containers stop at `MAX_DISPLAY_DEPTH`.
+    pub(crate) fn fmt_nested(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        match self {
+            LoxObject::ClassInstance(i) => i.borrow().fmt_nested(f, depth),
+            LoxObject::Map(m) => fmt_map(&m.borrow(), f, depth),
+            LoxObject::Array(a) => fmt_array(&a.borrow(), f, depth),
+            LoxObject::Set(set) => fmt_set(&set.borrow(), f, depth),
+            LoxObject::Primitive(_) if self.is_string() => write!(f, "\"{}\"", self),
+            _ => write!(f, "{}", self),
         }
     }
 }
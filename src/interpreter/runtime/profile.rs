@@ -0,0 +1,119 @@
+use super::trace::function_label;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// functions are told apart by where they were declared as well as their name, so methods of the
+// same name on different classes get a profile each.
+type FunctionKey = (Option<Rc<str>>, usize);
+
+/// how often a single function was called and how long those calls took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub declared_at: usize,
+    pub calls: usize,
+    // time spent inside the function, including whatever it called. a recursive call's time is
+    // only counted once, by its outermost call, so this never adds up to more than the run took.
+    pub total: Duration,
+}
+
+/// Profiler collects per function timings while a program runs, see `Lox::with_profiler`.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    // how many calls each function had and the time they took.
+    functions: HashMap<FunctionKey, (usize, Duration)>,
+    // the function and start time of every call still running, outermost first.
+    active: Vec<(FunctionKey, Instant)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enter(&mut self, name: Option<Rc<str>>, declared_at: usize) {
+        self.active.push(((name, declared_at), Instant::now()));
+    }
+
+    /// finish the innermost call started by `enter`.
+    pub fn exit(&mut self) {
+        let Some((key, start)) = self.active.pop() else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        let outermost = !self.active.iter().any(|(active, _)| *active == key);
+        let (calls, total) = self.functions.entry(key).or_default();
+        *calls += 1;
+        if outermost {
+            *total += elapsed;
+        }
+    }
+
+    /// every function called so far, the one that took the longest first. anonymous functions are
+    /// named by where they were declared, by line and column when `src` is given.
+    pub fn report(&self, src: Option<&str>) -> Vec<FunctionProfile> {
+        let mut report: Vec<_> = self
+            .functions
+            .iter()
+            .map(|((name, declared_at), (calls, total))| FunctionProfile {
+                name: function_label(name.as_deref(), *declared_at, src),
+                declared_at: *declared_at,
+                calls: *calls,
+                total: *total,
+            })
+            .collect();
+        report.sort_by(|a, b| {
+            b.total
+                .cmp(&a.total)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.declared_at.cmp(&b.declared_at))
+        });
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recursive_time_counted_once() {
+        let mut profiler = Profiler::new();
+        profiler.enter(Some("outer".into()), 0);
+        profiler.enter(Some("fib".into()), 10);
+        profiler.enter(Some("fib".into()), 10);
+        std::thread::sleep(Duration::from_millis(2));
+        profiler.exit();
+        profiler.exit();
+        profiler.exit();
+
+        let report = profiler.report(None);
+        assert_eq!(report.len(), 2);
+        let outer = &report[0];
+        let fib = &report[1];
+        assert_eq!((outer.name.as_str(), outer.calls), ("outer", 1));
+        assert_eq!((fib.name.as_str(), fib.calls), ("fib", 2));
+        assert!(fib.total >= Duration::from_millis(2));
+        assert!(outer.total >= fib.total);
+    }
+
+    #[test]
+    fn test_same_name_declared_apart_profiled_apart() {
+        let mut profiler = Profiler::new();
+        for declared_at in [4, 20, 20] {
+            profiler.enter(Some("area".into()), declared_at);
+            profiler.exit();
+        }
+        profiler.enter(None, 7);
+        profiler.exit();
+
+        let mut report = profiler.report(None);
+        report.sort_by_key(|f| f.declared_at);
+        let summary: Vec<_> = report
+            .iter()
+            .map(|f| (f.name.as_str(), f.declared_at, f.calls))
+            .collect();
+        assert_eq!(summary, [("area", 4, 1), ("fn@7", 7, 1), ("area", 20, 2)]);
+    }
+}
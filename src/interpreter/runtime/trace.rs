@@ -0,0 +1,59 @@
+use crate::lang::tokenizer::span::View;
+use std::rc::Rc;
+
+/// CallFrame records a single active function call, used to build stack traces for runtime errors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    // anonymous functions don't have a name, so they are labeled by where they were declared.
+    name: Option<Rc<str>>,
+    declared_at: usize,
+    call_site: usize,
+}
+
+impl CallFrame {
+    pub fn new(name: Option<Rc<str>>, declared_at: usize, call_site: usize) -> Self {
+        Self {
+            name,
+            declared_at,
+            call_site,
+        }
+    }
+
+    pub fn call_site(&self) -> usize {
+        self.call_site
+    }
+
+    /// the name shown for this frame in a trace. when the source is available anonymous functions
+    /// are shown by the line and column they were declared at instead of the raw byte offset.
+    pub fn label(&self, src: Option<&str>) -> String {
+        function_label(self.name.as_deref(), self.declared_at, src)
+    }
+}
+
+/// the name shown for a function declared at `declared_at`, see `CallFrame::label`.
+pub fn function_label(name: Option<&str>, declared_at: usize, src: Option<&str>) -> String {
+    match (name, src) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(src)) => format!("fn@{}", View::at(src, declared_at)),
+        (None, None) => format!("fn@{}", declared_at),
+    }
+}
+
+/// format frames from the outermost call to the innermost, e.g. `main -> helper -> fib`.
+/// runs of the same function, as in deep recursion, are collapsed into `fib x30`.
+pub fn format_trace(frames: &[CallFrame], src: Option<&str>) -> String {
+    let mut runs: Vec<(String, usize)> = Vec::new();
+    for label in frames.iter().map(|frame| frame.label(src)) {
+        match runs.last_mut() {
+            Some((last, count)) if *last == label => *count += 1,
+            _ => runs.push((label, 1)),
+        }
+    }
+    runs.into_iter()
+        .map(|(label, count)| match count {
+            1 => label,
+            _ => format!("{} x{}", label, count),
+        })
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
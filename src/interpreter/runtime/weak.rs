@@ -0,0 +1,81 @@
+//! weak references, for breaking the reference cycles the runtime can't free on its own.
+//!
+//! instances, maps and arrays are reference counted, so two instances that point at each other
+//! (a parent and its child, say) keep each other alive after the program has let go of both.
+//! holding one side of the link as `weak(value)` doesn't keep `value` alive, `deref(ref)` hands
+//! it back while something else still does and nil after that.
+//!
+//! functions can't be referred to weakly. a function holds on to the variables it captures, so
+//! one that captures a variable holding itself, like a recursive function declared inside a
+//! block or another function, or a closure stored on an instance it captured, is a cycle `weak`
+//! can't break. those are freed only when the program clears the variable or field by hand.
+use super::class::ClassInstance;
+use super::map::LoxMap;
+use super::object::LoxObject;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+#[derive(Debug, Clone)]
+pub enum WeakRef {
+    Instance(Weak<RefCell<ClassInstance>>),
+    Map(Weak<RefCell<LoxMap>>),
+    Array(Weak<RefCell<Vec<LoxObject>>>),
+}
+
+impl WeakRef {
+    /// a weak reference to `value`, `None` for values that can't hold other values and so can't be
+    /// part of a cycle.
+    pub fn new(value: &LoxObject) -> Option<Self> {
+        match value {
+            LoxObject::ClassInstance(i) => Some(Self::Instance(Rc::downgrade(i))),
+            LoxObject::Map(m) => Some(Self::Map(Rc::downgrade(m))),
+            LoxObject::Array(a) => Some(Self::Array(Rc::downgrade(a))),
+            _ => None,
+        }
+    }
+
+    /// the value this refers to, unless it has already been freed.
+    pub fn upgrade(&self) -> Option<LoxObject> {
+        match self {
+            Self::Instance(i) => i.upgrade().map(LoxObject::ClassInstance),
+            Self::Map(m) => m.upgrade().map(LoxObject::Map),
+            Self::Array(a) => a.upgrade().map(LoxObject::Array),
+        }
+    }
+
+    pub fn ptr_eq(&self, other: &WeakRef) -> bool {
+        match (self, other) {
+            (Self::Instance(a), Self::Instance(b)) => a.ptr_eq(b),
+            (Self::Map(a), Self::Map(b)) => a.ptr_eq(b),
+            (Self::Array(a), Self::Array(b)) => a.ptr_eq(b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for WeakRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.upgrade() {
+            Some(value) => write!(f, "<weak {}>", value.type_name()),
+            None => write!(f, "<weak, freed>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weak_does_not_keep_value_alive() {
+        let array = LoxObject::from(vec![LoxObject::from(1.0)]);
+        let weak = WeakRef::new(&array).unwrap();
+        assert_eq!(weak.upgrade(), Some(array.clone()));
+        assert_eq!(weak.to_string(), "<weak array>");
+        drop(array);
+        assert_eq!(weak.upgrade(), None);
+        assert_eq!(weak.to_string(), "<weak, freed>");
+        assert!(WeakRef::new(&LoxObject::from(1.0)).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use super::span::Span;
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
@@ -11,3 +12,17 @@ pub enum ScanError {
     #[error("ScanError: invalid number '{0}'")]
     InvalidNumber(String, usize),
 }
+
+impl ScanError {
+    /// the offending text, from where it started.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::UnexpectedEOF => None,
+            Self::InvalidToken(lexeme, position)
+            | Self::StrMissingTerminator(lexeme, position)
+            | Self::InvalidNumber(lexeme, position) => {
+                Some(Span::new(*position, position + lexeme.len()))
+            }
+        }
+    }
+}
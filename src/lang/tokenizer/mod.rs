@@ -1,3 +1,4 @@
 pub mod error;
 pub mod scanner;
+pub mod span;
 pub mod token;
@@ -1,8 +1,5 @@
 use super::error::ScanError;
 use super::token::{Token, TokenType};
-use std::collections::HashMap;
-use std::iter::Peekable;
-use std::str::{CharIndices, Chars};
 
 pub const LOX_KEYWORDS: &[(&str, TokenType)] = &[
     ("and", TokenType::And),
@@ -26,12 +23,13 @@ pub const LOX_KEYWORDS: &[(&str, TokenType)] = &[
     ("static", TokenType::Static),
 ];
 
+// the scanner walks the source a byte at a time, all of lox's syntax is ascii so a char only has
+// to be decoded when a non-ascii byte turns up (in a string, a comment or an invalid token).
+// positions are byte offsets either way.
 pub struct Scanner<'src> {
     src: &'src str,
-    ci: Peekable<CharIndices<'src>>,
     marker: usize,  // marker at token start
     current: usize, // current location
-    keywords: HashMap<&'static str, TokenType>,
     iter_done: bool,
 }
 
@@ -39,10 +37,8 @@ impl<'src> Scanner<'src> {
     pub fn new(src: &'src str) -> Self {
         Self {
             src,
-            ci: src.char_indices().peekable(),
             marker: 0,
             current: 0,
-            keywords: make_keyword_map(),
             iter_done: false,
         }
     }
@@ -62,11 +58,17 @@ impl<'src> Scanner<'src> {
             ')' => (TokenType::RightParen, self.take_slice()),
             '{' => (TokenType::LeftBrace, self.take_slice()),
             '}' => (TokenType::RightBrace, self.take_slice()),
+            '[' => (TokenType::LeftBracket, self.take_slice()),
+            ']' => (TokenType::RightBracket, self.take_slice()),
             ',' => (TokenType::Comma, self.take_slice()),
             ';' => (TokenType::Semicolon, self.take_slice()),
+            ':' => (TokenType::Colon, self.take_slice()),
+            '\\' => (TokenType::Backslash, self.take_slice()),
             '+' => {
                 if self.next_char_if(|c| *c == '=').is_some() {
                     (TokenType::PlusEqual, self.take_slice())
+                } else if self.next_char_if(|c| *c == '+').is_some() {
+                    (TokenType::PlusPlus, self.take_slice())
                 } else {
                     (TokenType::Plus, self.take_slice())
                 }
@@ -74,6 +76,8 @@ impl<'src> Scanner<'src> {
             '-' => {
                 if self.next_char_if(|c| *c == '=').is_some() {
                     (TokenType::MinusEqual, self.take_slice())
+                } else if self.next_char_if(|c| *c == '-').is_some() {
+                    (TokenType::MinusMinus, self.take_slice())
                 } else {
                     (TokenType::Minus, self.take_slice())
                 }
@@ -88,6 +92,8 @@ impl<'src> Scanner<'src> {
             '*' => {
                 if self.next_char_if(|c| *c == '=').is_some() {
                     (TokenType::StarEqual, self.take_slice())
+                } else if self.next_char_if(|c| *c == '*').is_some() {
+                    (TokenType::StarStar, self.take_slice())
                 } else {
                     (TokenType::Star, self.take_slice())
                 }
@@ -102,6 +108,8 @@ impl<'src> Scanner<'src> {
             '=' => {
                 if self.next_char_if(|c| *c == '=').is_some() {
                     (TokenType::EqualEqual, self.take_slice())
+                } else if self.next_char_if(|c| *c == '>').is_some() {
+                    (TokenType::Arrow, self.take_slice())
                 } else {
                     (TokenType::Equal, self.take_slice())
                 }
@@ -135,10 +143,15 @@ impl<'src> Scanner<'src> {
             }
             _ if is_ident_char(ch) => {
                 let lexeme = self.scan_identifier();
-                let kind = *self.keywords.get(lexeme).unwrap_or(&TokenType::Identifier);
+                let kind = keyword(lexeme).unwrap_or(TokenType::Identifier);
                 (kind, lexeme)
             }
-            _ => return Err(ScanError::InvalidToken(ch.to_string(), self.position_now())),
+            _ => {
+                return Err(ScanError::InvalidToken(
+                    ch.to_string(),
+                    self.position_start(),
+                ));
+            }
         };
 
         Ok(self.make_token(kind, lexeme, self.position_start()))
@@ -154,7 +167,7 @@ impl<'src> Scanner<'src> {
             if c.is_ascii_digit() {
                 let _ = self.next_char(); // cannot fail
                 continue;
-            } else if *c == '.' && dot_cnt == 0 {
+            } else if c == '.' && dot_cnt == 0 {
                 let _ = self.next_char(); // cannot fail
 
                 if !self.peek_is_digit() {
@@ -175,30 +188,40 @@ impl<'src> Scanner<'src> {
     }
 
     fn scan_string(&mut self) -> Result<&'src str, ScanError> {
+        // the quote and backslash are ascii, and no byte of a multibyte char can be mistaken for
+        // one, so the body can be skipped over byte by byte.
+        let bytes = self.src.as_bytes();
         let mut in_escape = false;
 
-        while let Some(c) = self.next_char() {
+        while let Some(&b) = bytes.get(self.current) {
+            self.current += 1;
             if in_escape {
                 in_escape = false;
                 continue;
             }
-            if c == '\\' {
+            if b == b'\\' {
                 in_escape = true;
                 continue;
             }
-            if c == '"' {
+            if b == b'"' {
                 return Ok(self.take_slice());
             }
         }
 
         Err(ScanError::StrMissingTerminator(
             self.take_slice().to_string(),
-            self.position_now(),
+            self.position_start(),
         ))
     }
 
     fn scan_identifier(&mut self) -> &'src str {
-        while let Some(_) = self.next_char_if(|c| is_ident_char(*c)) {}
+        let bytes = self.src.as_bytes();
+        while bytes
+            .get(self.current)
+            .is_some_and(|b| is_ident_char(*b as char))
+        {
+            self.current += 1;
+        }
         self.take_slice()
     }
 
@@ -206,12 +229,13 @@ impl<'src> Scanner<'src> {
     fn skip_ws_and_comments(&mut self) {
         loop {
             // whitespace
-            while let Some(_) = self.next_char_if(|c| c.is_whitespace()) {}
+            while self.next_char_if(|c| c.is_whitespace()).is_some() {}
             // line comment
             if self.in_comment() {
                 // consume until newline
                 // once we hit a newline, the whitespace loop at the top will cut it off.
-                while let Some(_) = self.next_char_if(|c| *c != '\n') {}
+                let rest = &self.src.as_bytes()[self.current..];
+                self.current += rest.iter().position(|b| *b == b'\n').unwrap_or(rest.len());
             } else {
                 break;
             }
@@ -224,41 +248,41 @@ impl<'src> Scanner<'src> {
     }
 
     #[inline]
-    fn is_eof(&mut self) -> bool {
-        self.ci.peek().is_none()
+    fn is_eof(&self) -> bool {
+        self.current >= self.src.len()
     }
 
     #[inline]
-    fn peek(&mut self) -> Option<&char> {
-        self.ci.peek().map(|(_, c)| c)
+    fn peek(&self) -> Option<char> {
+        match self.src.as_bytes().get(self.current) {
+            Some(b) if b.is_ascii() => Some(*b as char),
+            // the slow path, `current` always sits on a char boundary.
+            Some(_) => self.src[self.current..].chars().next(),
+            None => None,
+        }
     }
 
     #[inline]
-    fn peek_is_digit(&mut self) -> bool {
-        self.ci.peek().map_or(false, |(_, c)| c.is_ascii_digit())
+    fn peek_is_digit(&self) -> bool {
+        self.src
+            .as_bytes()
+            .get(self.current)
+            .is_some_and(|b| b.is_ascii_digit())
     }
 
     fn next_char(&mut self) -> Option<char> {
-        self.ci.next().map(|ch| {
-            self.update_pos(ch);
-            return ch.1;
-        })
+        let c = self.peek()?;
+        self.current += c.len_utf8();
+        Some(c)
     }
 
     fn next_char_if<F>(&mut self, f: F) -> Option<char>
     where
         F: FnOnce(&char) -> bool,
     {
-        if let Some(c) = self.ci.next_if(|(_, c)| f(c)) {
-            self.update_pos(c);
-            Some(c.1)
-        } else {
-            None
-        }
-    }
-
-    fn update_pos(&mut self, (idx, c): (usize, char)) {
-        self.current = idx + c.len_utf8()
+        let c = self.peek().filter(f)?;
+        self.current += c.len_utf8();
+        Some(c)
     }
 
     fn set_marker(&mut self) {
@@ -299,7 +323,7 @@ impl<'src> Iterator for Scanner<'src> {
                 if tok.token_type == TokenType::Eof {
                     self.iter_done = true;
                 }
-                return Some(Ok(tok));
+                Some(Ok(tok))
             }
             res => Some(res),
         }
@@ -311,12 +335,31 @@ fn is_ident_char(c: char) -> bool {
     matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_')
 }
 
-fn make_keyword_map() -> HashMap<&'static str, TokenType> {
-    let mut map = HashMap::with_capacity(LOX_KEYWORDS.len());
-    for &(k, v) in LOX_KEYWORDS {
-        map.insert(k, v);
-    }
-    map
+// the same set as `LOX_KEYWORDS`, as a match so creating a scanner doesn't have to build a map.
+fn keyword(lexeme: &str) -> Option<TokenType> {
+    let kind = match lexeme {
+        "and" => TokenType::And,
+        "class" => TokenType::Class,
+        "else" => TokenType::Else,
+        "false" => TokenType::False,
+        "for" => TokenType::For,
+        "fun" => TokenType::Fun,
+        "if" => TokenType::If,
+        "nil" => TokenType::Nil,
+        "or" => TokenType::Or,
+        "print" => TokenType::Print,
+        "return" => TokenType::Return,
+        "super" => TokenType::Super,
+        "this" => TokenType::This,
+        "true" => TokenType::True,
+        "var" => TokenType::Var,
+        "while" => TokenType::While,
+        "break" => TokenType::Break,
+        "continue" => TokenType::Continue,
+        "static" => TokenType::Static,
+        _ => return None,
+    };
+    Some(kind)
 }
 
 #[cfg(test)]
@@ -325,7 +368,7 @@ mod tests {
 
     #[test]
     fn test_scan_single_tokens() {
-        let src = "(){},;+";
+        let src = "(){}[],;:+";
         let mut scanner = Scanner::new(src);
 
         let expected_tokens = vec![
@@ -333,8 +376,11 @@ mod tests {
             TokenType::RightParen,
             TokenType::LeftBrace,
             TokenType::RightBrace,
+            TokenType::LeftBracket,
+            TokenType::RightBracket,
             TokenType::Comma,
             TokenType::Semicolon,
+            TokenType::Colon,
             TokenType::Plus,
             TokenType::Eof,
         ];
@@ -345,10 +391,97 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_scan_star_operators() {
+        let mut scanner = Scanner::new("* *= ** ***");
+        let expected_tokens = [
+            TokenType::Star,
+            TokenType::StarEqual,
+            TokenType::StarStar,
+            TokenType::StarStar,
+            TokenType::Star,
+            TokenType::Eof,
+        ];
+        for expected in expected_tokens {
+            assert_eq!(scanner.next_token().unwrap().token_type, expected);
+        }
+    }
+
+    #[test]
+    fn test_scan_increment_operators() {
+        let mut scanner = Scanner::new("+ += ++ +++ - -= -- ---");
+        let expected_tokens = [
+            TokenType::Plus,
+            TokenType::PlusEqual,
+            TokenType::PlusPlus,
+            TokenType::PlusPlus,
+            TokenType::Plus,
+            TokenType::Minus,
+            TokenType::MinusEqual,
+            TokenType::MinusMinus,
+            TokenType::MinusMinus,
+            TokenType::Minus,
+            TokenType::Eof,
+        ];
+        for expected in expected_tokens {
+            assert_eq!(scanner.next_token().unwrap().token_type, expected);
+        }
+    }
+
+    #[test]
+    fn test_scan_lambda() {
+        let mut scanner = Scanner::new("\\(a) => a == = >=");
+        let expected_tokens = [
+            TokenType::Backslash,
+            TokenType::LeftParen,
+            TokenType::Identifier,
+            TokenType::RightParen,
+            TokenType::Arrow,
+            TokenType::Identifier,
+            TokenType::EqualEqual,
+            TokenType::Equal,
+            TokenType::GreaterEqual,
+            TokenType::Eof,
+        ];
+        for expected in expected_tokens {
+            assert_eq!(scanner.next_token().unwrap().token_type, expected);
+        }
+    }
+
+    #[test]
+    fn test_scan_mixed_ascii_and_unicode() {
+        let src = "var s = \"héllo 🦀\"; // ünïcode comment\nprint s;\u{a0}é";
+        let mut scanner = Scanner::new(src);
+        let expected = [
+            (TokenType::Var, "var"),
+            (TokenType::Identifier, "s"),
+            (TokenType::Equal, "="),
+            (TokenType::String, "\"héllo 🦀\""),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Print, "print"),
+            (TokenType::Identifier, "s"),
+            (TokenType::Semicolon, ";"),
+        ];
+        for (token_type, lexeme) in expected {
+            let token = scanner.next_token().unwrap();
+            assert_eq!((token.token_type, token.lexeme), (token_type, lexeme));
+            // positions are byte offsets into the source.
+            assert_eq!(&src[token.position..token.position + lexeme.len()], lexeme);
+        }
+        // a non-breaking space is whitespace, the accented letter is not a valid identifier.
+        match scanner.next_token() {
+            Err(ScanError::InvalidToken(lexeme, position)) => {
+                assert_eq!(lexeme, "é");
+                assert_eq!(position, src.rfind('é').unwrap());
+            }
+            other => panic!("expected an invalid token but found {other:?}"),
+        }
+        assert_eq!(scanner.next_token().unwrap().token_type, TokenType::Eof);
+    }
+
     #[test]
     fn test_scan_keywords() {
-        let src =
-            "and class else false for fun if nil or print return super this true var while break";
+        let src = "and class else false for fun if nil or print return super this true var while break continue static";
         let mut scanner = Scanner::new(src);
 
         for &(keyword, token_type) in LOX_KEYWORDS {
@@ -359,6 +492,12 @@ mod tests {
 
         let eof = scanner.next_token().unwrap();
         assert_eq!(eof.token_type, TokenType::Eof);
+
+        // the lookup has to be kept in step with the list by hand.
+        for &(lexeme, token_type) in LOX_KEYWORDS {
+            assert_eq!(keyword(lexeme), Some(token_type));
+        }
+        assert_eq!(keyword("classes"), None);
     }
 
     #[test]
@@ -436,7 +575,10 @@ mod tests {
 
         let error = scanner.next_token().unwrap_err();
         match error {
-            ScanError::InvalidToken(lexeme, _) => assert_eq!(lexeme, "@"),
+            ScanError::InvalidToken(lexeme, position) => {
+                assert_eq!(lexeme, "@");
+                assert_eq!(position, 0);
+            }
             _ => panic!("Expected InvalidToken error"),
         }
     }
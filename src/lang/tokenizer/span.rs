@@ -0,0 +1,259 @@
+use std::fmt;
+
+/// how many columns a tab takes up when none is given, the same as any other character.
+pub const DEFAULT_TAB_WIDTH: usize = 1;
+
+/// Span is a half open range of byte offsets into the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// a span covering the single byte at `position`, for diagnostics that only track where they began.
+    pub fn at(position: usize) -> Self {
+        Self::new(position, position + 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the line and column the span starts and ends at in `src`, found in one walk over it. the end
+    /// is where the span stops, so it sits just after its last character.
+    pub fn to_view(&self, src: &str) -> (View, View) {
+        self.to_view_with_tab_width(src, DEFAULT_TAB_WIDTH)
+    }
+
+    /// like `to_view`, but with every tab taking up `tab_width` columns.
+    pub fn to_view_with_tab_width(&self, src: &str, tab_width: usize) -> (View, View) {
+        let mut view = View::new();
+        let mut start = None;
+        for (idx, ch) in src.char_indices() {
+            if idx >= self.start && start.is_none() {
+                start = Some(view);
+            }
+            if idx >= self.end {
+                break;
+            }
+            view.advance(src, idx, ch, tab_width);
+        }
+        (start.unwrap_or(view), view)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// View is a human readable location in the source text. Both the line and the column are 1-based
+/// so they line up with what an editor shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct View {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl View {
+    pub fn new() -> Self {
+        Self { line: 1, col: 1 }
+    }
+
+    /// walk `src` up to the byte `offset` and report the line and column it lands on.
+    pub fn at(src: &str, offset: usize) -> Self {
+        Span::new(offset, offset).to_view(src).0
+    }
+
+    // step past `ch`, found at byte `idx` of `src`.
+    fn advance(&mut self, src: &str, idx: usize, ch: char, tab_width: usize) {
+        match ch {
+            '\n' => self.inc_line(),
+            // the carriage return of a windows line ending doesn't take up a column.
+            '\r' if src[idx..].starts_with("\r\n") => {}
+            '\t' => self.inc_col(tab_width),
+            _ => self.inc_col(1),
+        }
+    }
+
+    pub fn inc_col(&mut self, width: usize) {
+        self.col += width;
+    }
+
+    pub fn inc_line(&mut self) {
+        self.line += 1;
+        self.col = 1;
+    }
+}
+
+impl fmt::Display for View {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// Render the line of `src` that `span` starts on, followed by a caret line underlining the span.
+/// Spans that run past the end of their first line are clamped to it.
+pub fn code_block(src: &str, span: Span) -> String {
+    code_block_with_tab_width(src, span, DEFAULT_TAB_WIDTH)
+}
+
+/// like `code_block`, but tabs in the rendered line are expanded to `tab_width` spaces so the
+/// carets line up however the terminal shows tabs, and the reported column agrees with them.
+pub fn code_block_with_tab_width(src: &str, span: Span, tab_width: usize) -> String {
+    let start = floor_char_boundary(src, span.start.min(src.len()));
+    let line_begin = src[..start].rfind('\n').map_or(0, |idx| idx + 1);
+    let line_end = src[start..].find('\n').map_or(src.len(), |idx| start + idx);
+    let line_end = if line_end > start && src[..line_end].ends_with('\r') {
+        line_end - 1
+    } else {
+        line_end
+    };
+    let end = span.end.clamp(start, line_end);
+    let end = floor_char_boundary(src, end);
+
+    let (view, _) = Span::new(start, start).to_view_with_tab_width(src, tab_width);
+    let line_no = view.line.to_string();
+    let gutter = " ".repeat(line_no.len());
+    let tab = " ".repeat(tab_width);
+    let width = |text: &str| {
+        text.chars()
+            .map(|c| if c == '\t' { tab_width } else { 1 })
+            .sum()
+    };
+    let padding = " ".repeat(width(&src[line_begin..start]));
+    // always underline something, even when the span is empty or sits at the end of the input.
+    let carets = "^".repeat(width(&src[start..end]).max(1));
+    let line = src[line_begin..line_end].replace('\t', &tab);
+
+    format!("{gutter}--> {view}\n{gutter} |\n{line_no} | {line}\n{gutter} | {padding}{carets}")
+}
+
+/// Best effort span of the token starting at `position`, for diagnostics that only kept
+/// the position around. Runs of identifier characters or operator symbols are grouped together.
+pub fn word_span(src: &str, position: usize) -> Span {
+    let start = floor_char_boundary(src, position.min(src.len()));
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let is_symbol = |c: char| "=!<>+-*/".contains(c);
+    let mut chars = src[start..].char_indices();
+    let Some((_, first)) = chars.next() else {
+        return Span::new(start, start);
+    };
+    let same_kind = |c: char| (is_word(first) && is_word(c)) || (is_symbol(first) && is_symbol(c));
+    let len = chars
+        .find(|(_, c)| !same_kind(*c))
+        .map_or(src.len() - start, |(idx, _)| idx);
+    Span::new(start, start + len)
+}
+
+fn floor_char_boundary(src: &str, mut idx: usize) -> usize {
+    while !src.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_block_underlines_span() {
+        let src = "var a = 1;\nvar b = a +;";
+        let block = code_block(src, Span::new(21, 22));
+        let lines: Vec<&str> = block.lines().collect();
+        assert_eq!(lines[0], " --> 2:11");
+        assert_eq!(lines[2], "2 | var b = a +;");
+        assert_eq!(lines[3], "  |           ^");
+    }
+
+    #[test]
+    fn test_code_block_clamps_to_first_line() {
+        let src = "print \"abc\nprint 1;";
+        let block = code_block(src, Span::new(6, src.len()));
+        let lines: Vec<&str> = block.lines().collect();
+        assert_eq!(lines[2], "1 | print \"abc");
+        assert_eq!(lines[3], "  |       ^^^^");
+    }
+
+    #[test]
+    fn test_word_span() {
+        let src = "a == foo(1);";
+        assert_eq!(word_span(src, 0), Span::new(0, 1));
+        assert_eq!(word_span(src, 2), Span::new(2, 4));
+        assert_eq!(word_span(src, 5), Span::new(5, 8));
+        assert_eq!(word_span(src, 8), Span::new(8, 9));
+    }
+
+    #[test]
+    fn test_span_to_view() {
+        let src = "var a = 1;\nvar bé = \"ü\";\nprint bé;";
+        // on the first line.
+        let (start, end) = Span::new(4, 5).to_view(src);
+        assert_eq!(
+            (start, end),
+            (View { line: 1, col: 5 }, View { line: 1, col: 6 })
+        );
+        // columns count characters, so the accented name and string take one column each.
+        let string = src.find('"').unwrap();
+        let (start, end) = Span::new(string, string + "\"ü\"".len()).to_view(src);
+        assert_eq!(start, View { line: 2, col: 10 });
+        assert_eq!(end, View { line: 2, col: 13 });
+        // running over a newline.
+        let semi = src.find(';').unwrap();
+        let (start, end) = Span::new(semi, semi + 5).to_view(src);
+        assert_eq!(start, View { line: 1, col: 10 });
+        assert_eq!(end, View { line: 2, col: 4 });
+        // past the end of the source.
+        let (start, end) = Span::new(src.len(), src.len() + 3).to_view(src);
+        assert_eq!(start, View { line: 3, col: 10 });
+        assert_eq!(end, start);
+    }
+
+    #[test]
+    fn test_tab_width() {
+        let src = "fun f() {\n\t\treturn x +;\n}";
+        let semi = src.find("+;").unwrap() + 1;
+        assert_eq!(View::at(src, semi), View { line: 2, col: 13 });
+        let (start, _) = Span::at(semi).to_view_with_tab_width(src, 4);
+        assert_eq!(start, View { line: 2, col: 19 });
+
+        let block = code_block_with_tab_width(src, Span::at(semi), 4);
+        let lines: Vec<&str> = block.lines().collect();
+        assert_eq!(lines[0], " --> 2:19");
+        assert_eq!(lines[2], "2 |         return x +;");
+        assert_eq!(lines[3], "  |                   ^");
+        // with the default width a tab is a single column, and is rendered as one space.
+        let lines: Vec<String> = code_block(src, Span::at(semi))
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(lines[2], "2 |   return x +;");
+        assert_eq!(lines[3], "  |             ^");
+    }
+
+    #[test]
+    fn test_view_windows_line_endings() {
+        let src = "var a;\r\nvar b;\r\n  b;";
+        assert_eq!(View::at(src, src.len() - 2), View { line: 3, col: 3 });
+        let block = code_block(src, Span::new(10, 11));
+        let lines: Vec<&str> = block.lines().collect();
+        assert_eq!(lines[2], "2 | var b;");
+    }
+}
@@ -1,3 +1,4 @@
+use super::span::Span;
 use std::fmt;
 
 #[derive(Clone, Debug, PartialEq, Copy)]
@@ -7,23 +8,31 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Semicolon,
+    Colon,
+    Backslash,
 
     // One or two character tokens.
     Minus,
     MinusEqual,
+    MinusMinus,
     Plus,
     PlusEqual,
+    PlusPlus,
     Slash,
     SlashEqual,
     Star,
     StarEqual,
+    StarStar,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
@@ -66,21 +75,29 @@ impl fmt::Display for TokenType {
             TokenType::RightParen => ")",
             TokenType::LeftBrace => "{",
             TokenType::RightBrace => "}",
+            TokenType::LeftBracket => "[",
+            TokenType::RightBracket => "]",
             TokenType::Comma => ",",
             TokenType::Dot => ".",
             TokenType::Semicolon => ";",
+            TokenType::Colon => ":",
+            TokenType::Backslash => "\\",
             TokenType::Minus => "-",
             TokenType::MinusEqual => "-=",
+            TokenType::MinusMinus => "--",
             TokenType::Plus => "+",
             TokenType::PlusEqual => "+=",
+            TokenType::PlusPlus => "++",
             TokenType::Slash => "/",
             TokenType::SlashEqual => "/=",
             TokenType::Star => "*",
             TokenType::StarEqual => "*=",
+            TokenType::StarStar => "**",
             TokenType::Bang => "!",
             TokenType::BangEqual => "!=",
             TokenType::Equal => "=",
             TokenType::EqualEqual => "==",
+            TokenType::Arrow => "=>",
             TokenType::Greater => ">",
             TokenType::GreaterEqual => ">=",
             TokenType::Less => "<",
@@ -134,6 +151,10 @@ impl<'src> Token<'src> {
             position,
         }
     }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.position, self.position + self.lexeme.len())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
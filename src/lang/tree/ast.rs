@@ -1,11 +1,12 @@
 use super::error::ConversionError;
+use crate::lang::tokenizer::span::Span;
 use crate::lang::tokenizer::token::{Token, TokenType};
 use crate::lang::visitor::Visitor;
-use std::cell::Cell;
+use std::cell::{Cell, Ref, RefCell};
 use std::fmt;
 use std::rc::Rc;
 // "==" | "!=" | "<" | "<=" | ">" | ">=" |
-// "+"  | "-"  | "*" | "/" ;
+// "+"  | "-"  | "*" | "/" | "**" ;
 #[derive(Debug, Clone, Copy)]
 pub enum BinaryOperator {
     Equal(usize),
@@ -18,6 +19,7 @@ pub enum BinaryOperator {
     Minus(usize),
     Star(usize),
     Slash(usize),
+    Power(usize),
 }
 
 impl TryFrom<Token<'_>> for BinaryOperator {
@@ -34,9 +36,8 @@ impl TryFrom<Token<'_>> for BinaryOperator {
             TokenType::Minus => Ok(BinaryOperator::Minus(value.position)),
             TokenType::Star => Ok(BinaryOperator::Star(value.position)),
             TokenType::Slash => Ok(BinaryOperator::Slash(value.position)),
-            _ => {
-                return Err(ConversionError::InvalidBinaryOperator(value.into()));
-            }
+            TokenType::StarStar => Ok(BinaryOperator::Power(value.position)),
+            _ => Err(ConversionError::InvalidBinaryOperator(value.into())),
         }
     }
 }
@@ -54,6 +55,7 @@ impl fmt::Display for BinaryOperator {
             Self::Minus(_) => write!(f, "'-'"),
             Self::Star(_) => write!(f, "'*'"),
             Self::Slash(_) => write!(f, "'/'"),
+            Self::Power(_) => write!(f, "'**'"),
         }
     }
 }
@@ -71,6 +73,7 @@ impl BinaryOperator {
             Self::Minus(view) => *view,
             Self::Star(view) => *view,
             Self::Slash(view) => *view,
+            Self::Power(view) => *view,
         }
     }
 }
@@ -86,9 +89,7 @@ impl TryFrom<Token<'_>> for LogicalOperator {
         match value.token_type {
             TokenType::And => Ok(LogicalOperator::And(value.position)),
             TokenType::Or => Ok(LogicalOperator::Or(value.position)),
-            _ => {
-                return Err(ConversionError::InvalidLogicalOperator(value.into()));
-            }
+            _ => Err(ConversionError::InvalidLogicalOperator(value.into())),
         }
     }
 }
@@ -125,9 +126,7 @@ impl TryFrom<Token<'_>> for UnaryPrefix {
         match value.token_type {
             TokenType::Bang => Ok(UnaryPrefix::Bang(value.position)),
             TokenType::Minus => Ok(UnaryPrefix::Minus(value.position)),
-            _ => {
-                return Err(ConversionError::InvalidUnaryOperator(value.into()));
-            }
+            _ => Err(ConversionError::InvalidUnaryOperator(value.into())),
         }
     }
 }
@@ -150,38 +149,102 @@ impl UnaryPrefix {
     }
 }
 
+// "++" | "--" ;
+#[derive(Debug, Clone, Copy)]
+pub enum PostfixOperator {
+    Increment(usize),
+    Decrement(usize),
+}
+
+impl TryFrom<Token<'_>> for PostfixOperator {
+    type Error = ConversionError;
+    fn try_from(value: Token<'_>) -> Result<Self, Self::Error> {
+        match value.token_type {
+            TokenType::PlusPlus => Ok(PostfixOperator::Increment(value.position)),
+            TokenType::MinusMinus => Ok(PostfixOperator::Decrement(value.position)),
+            _ => Err(ConversionError::InvalidUnaryOperator(value.into())),
+        }
+    }
+}
+
+impl fmt::Display for PostfixOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Increment(_) => write!(f, "'++'"),
+            Self::Decrement(_) => write!(f, "'--'"),
+        }
+    }
+}
+
+impl PostfixOperator {
+    pub fn position(&self) -> usize {
+        match self {
+            PostfixOperator::Increment(pos) => *pos,
+            PostfixOperator::Decrement(pos) => *pos,
+        }
+    }
+
+    /// how much the operator changes its variable by.
+    pub fn step(&self) -> f64 {
+        match self {
+            PostfixOperator::Increment(_) => 1.0,
+            PostfixOperator::Decrement(_) => -1.0,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
-    Number { value: f64, position: usize },
-    String { value: Rc<String>, position: usize },
-    Boolean { value: bool, position: usize },
-    Nil { position: usize },
+    Number { value: f64, span: Span },
+    String { value: Rc<String>, span: Span },
+    Boolean { value: bool, span: Span },
+    Nil { span: Span },
 }
 
 impl Literal {
-    pub fn new_number(n: f64, v: usize) -> Self {
-        Self::Number {
-            value: n,
-            position: v,
-        }
+    pub fn new_number(n: f64, span: Span) -> Self {
+        Self::Number { value: n, span }
     }
 
-    pub fn new_string(s: String, v: usize) -> Self {
+    pub fn new_string(s: String, span: Span) -> Self {
         Self::String {
             value: Rc::new(s),
-            position: v,
+            span,
         }
     }
 
-    pub fn new_boolean(b: bool, v: usize) -> Self {
-        Self::Boolean {
-            value: b,
-            position: v,
+    pub fn new_boolean(b: bool, span: Span) -> Self {
+        Self::Boolean { value: b, span }
+    }
+
+    pub fn new_nil(span: Span) -> Self {
+        Self::Nil { span }
+    }
+
+    pub fn position(&self) -> usize {
+        self.span().start
+    }
+
+    /// the same value, spanning `span` instead.
+    pub fn with_span(mut self, span: Span) -> Self {
+        match &mut self {
+            Literal::Number { span: s, .. }
+            | Literal::String { span: s, .. }
+            | Literal::Boolean { span: s, .. }
+            | Literal::Nil { span: s } => *s = span,
         }
+        self
     }
 
-    pub fn new_nil(v: usize) -> Self {
-        Self::Nil { position: v }
+    /// the source the literal was written as, or for a folded one, the whole expression it
+    /// replaced.
+    pub fn span(&self) -> Span {
+        match self {
+            Literal::Number { span, .. }
+            | Literal::String { span, .. }
+            | Literal::Boolean { span, .. }
+            | Literal::Nil { span } => *span,
+        }
     }
 }
 
@@ -189,27 +252,21 @@ impl TryFrom<Token<'_>> for Literal {
     type Error = ConversionError;
     fn try_from(value: Token<'_>) -> Result<Self, Self::Error> {
         match value.token_type {
-            TokenType::Number => {
-                let num = value.lexeme.parse::<f64>();
-                if num.is_err() {
-                    Err(ConversionError::InvalidNumber(value.into()))
-                } else {
-                    Ok(Literal::new_number(num.unwrap(), value.position))
-                }
-            }
+            TokenType::Number => match value.lexeme.parse::<f64>() {
+                Ok(num) => Ok(Literal::new_number(num, value.span())),
+                Err(_) => Err(ConversionError::InvalidNumber(value.into())),
+            },
             TokenType::String => {
                 let end = value.lexeme.len() - 1;
                 Ok(Literal::new_string(
                     value.lexeme[1..end].to_string(),
-                    value.position,
+                    value.span(),
                 ))
             }
-            TokenType::True => Ok(Literal::new_boolean(true, value.position)),
-            TokenType::False => Ok(Literal::new_boolean(false, value.position)),
-            TokenType::Nil => Ok(Literal::new_nil(value.position)),
-            _ => {
-                return Err(ConversionError::InvalidLiteralType(value.into()));
-            }
+            TokenType::True => Ok(Literal::new_boolean(true, value.span())),
+            TokenType::False => Ok(Literal::new_boolean(false, value.span())),
+            TokenType::Nil => Ok(Literal::new_nil(value.span())),
+            _ => Err(ConversionError::InvalidLiteralType(value.into())),
         }
     }
 }
@@ -225,11 +282,33 @@ impl fmt::Display for Literal {
     }
 }
 
+/// Binding is the resolver's verdict on where the value behind an identifier lives at runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    /// a variable of the function being run, `slot` in its frame.
+    Local { slot: usize },
+    /// a variable captured from an enclosing function, `index` into the running function's upvalues.
+    UpValue { index: usize },
+    /// anything that could not be found in a local scope lives in the globals map.
+    Global,
+}
+
+/// where a function finds each of the variables it captures when it is created, in the order of
+/// its upvalues.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+    /// `slot` in the frame of the function creating it.
+    Local(usize),
+    /// one of the upvalues of the function creating it.
+    UpValue(usize),
+}
+
+// clones keep whatever binding the resolver gave the original, a clone that ends up somewhere
+// else in the tree needs resolving again.
 #[derive(Debug, Clone)]
 pub struct Identifier {
     name: String,
-    slot: Cell<Option<usize>>,
-    depth: Cell<Option<usize>>,
+    binding: Cell<Option<Binding>>,
     position: usize,
 }
 
@@ -242,28 +321,39 @@ impl Identifier {
         self.position
     }
 
-    pub fn swap_depth(&self, value: usize) {
-        self.depth.replace(Some(value));
+    /// the name as it was written in the source.
+    pub fn span(&self) -> Span {
+        Span::new(self.position, self.position + self.name.len())
     }
 
-    pub fn swap_slot(&self, value: usize) {
-        self.slot.replace(Some(value));
+    pub fn binding(&self) -> Option<Binding> {
+        self.binding.get()
+    }
+
+    pub fn set_local_binding(&self, slot: usize) {
+        self.binding.set(Some(Binding::Local { slot }));
+    }
+
+    pub fn set_upvalue_binding(&self, index: usize) {
+        self.binding.set(Some(Binding::UpValue { index }));
+    }
+
+    pub fn set_global_binding(&self) {
+        self.binding.set(Some(Binding::Global));
     }
 
     pub fn is_global(&self) -> bool {
-        self.slot.get().is_none() || self.depth.get().is_none()
+        matches!(self.binding.get(), Some(Binding::Global) | None)
     }
 
-    pub fn depth_slot(&self) -> Option<(usize, usize)> {
-        // if self.name_str() == "count" {
-        //     println!("printing self to get depth slot -> {:#?}", self);
-        // }
-        if let Some(depth) = self.depth.get() {
-            if let Some(slot) = self.slot.get() {
-                return Some((depth, slot));
-            }
+    /// the `this` read by a `super` expression at `position`, which needs the instance as well as
+    /// the superclass.
+    pub fn this_at(position: usize) -> Self {
+        Self {
+            name: "this".to_string(),
+            position,
+            binding: Cell::new(None),
         }
-        None
     }
 }
 
@@ -280,34 +370,77 @@ impl TryFrom<Token<'_>> for Identifier {
             // you can convert a fun to an identifier because
             // we support anonymous functions whose name essentially becomes the
             // location where it was declared.
-            TokenType::Identifier | TokenType::Fun | TokenType::This => Ok(Self {
-                name: value.lexeme.to_string(),
-                position: value.position,
-                slot: Cell::new(None),
-                depth: Cell::new(None),
-            }),
+            TokenType::Identifier | TokenType::Fun | TokenType::This | TokenType::Super => {
+                Ok(Self {
+                    name: value.lexeme.to_string(),
+                    position: value.position,
+                    binding: Cell::new(None),
+                })
+            }
             _ => Err(ConversionError::InvalidIdentifier(value.into())),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Callee {
     pub expr: Box<Expr>,
+    // the opening paren of the arguments.
     position: usize,
+    // the part of the callee that names what is being called.
+    place: usize,
+    // just past the closing paren of the arguments.
+    end: usize,
 }
 
 impl Callee {
-    pub fn new(expr: Expr, position: usize) -> Self {
+    pub fn new(expr: Expr, position: usize, end: usize) -> Self {
+        let place = callee_place(&expr).unwrap_or(position);
         Self {
             expr: Box::new(expr),
             position,
+            place,
+            end,
         }
     }
 
+    /// where the call is made from, i.e. the paren that starts the arguments.
     pub fn position(&self) -> usize {
         self.position
     }
+
+    /// where the value being called comes from, which is what to point at when it can't be called.
+    /// for `a.b()` this is `b`, for `a[0]()` the bracket and for `(f)()` whatever `f` points at.
+    pub fn place(&self) -> usize {
+        self.place
+    }
+
+    /// the whole call as written, from `place` through the closing paren.
+    pub fn span(&self) -> Span {
+        Span::new(self.place, self.end)
+    }
+}
+
+fn callee_place(expr: &Expr) -> Option<usize> {
+    match expr {
+        Expr::Variable { value }
+        | Expr::Assignment { name: value, .. }
+        | Expr::Postfix { name: value, .. } => Some(value.position()),
+        Expr::Get { property, .. } => Some(property.position()),
+        Expr::This { ident } => Some(ident.position()),
+        Expr::Super { property, .. } => Some(property.position()),
+        Expr::Index { position, .. } | Expr::Map { position, .. } => Some(*position),
+        Expr::Call { callee, .. } => Some(callee.position()),
+        Expr::Grouping { expr, .. } | Expr::Cached { expr, .. } => callee_place(expr),
+        Expr::Function { value } => Some(value.position()),
+        Expr::Class { value } => Some(value.position()),
+        Expr::Literal { value } => Some(value.position()),
+        Expr::Binary { op, .. } => Some(op.position()),
+        Expr::Logical { op, .. } => Some(op.position()),
+        Expr::Unary { prefix, .. } => Some(prefix.position()),
+        Expr::Set { property, .. } => Some(property.position()),
+        Expr::IndexSet { position, .. } => Some(*position),
+    }
 }
 
 #[derive(Debug)]
@@ -322,6 +455,25 @@ pub struct Function {
     marker_position: usize,
     // this tells us whether or not the function is a static function, declared on the class instance itself.
     is_static: bool,
+    // getters are class methods declared without a parameter list, they run as soon as they are accessed.
+    is_getter: bool,
+    // the variables the body uses from enclosing functions, filled in by the resolver.
+    captures: RefCell<Vec<Capture>>,
+}
+
+// the body is copied rather than shared so passes can rewrite a clone without touching the original.
+impl Clone for Function {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: Rc::new(self.body.as_ref().clone()),
+            marker_position: self.marker_position,
+            is_static: self.is_static,
+            is_getter: self.is_getter,
+            captures: self.captures.clone(),
+        }
+    }
 }
 
 impl Function {
@@ -345,6 +497,15 @@ impl Function {
         self.is_static
     }
 
+    pub fn as_getter(mut self) -> Self {
+        self.is_getter = true;
+        self
+    }
+
+    pub fn is_getter(&self) -> bool {
+        self.is_getter
+    }
+
     pub fn params(&self) -> &[Identifier] {
         &self.params[..]
     }
@@ -360,6 +521,12 @@ impl Function {
         self.body.clone()
     }
 
+    /// mutable access to the body for passes that rewrite the tree, only possible
+    /// while nothing else holds onto it.
+    pub fn body_mut(&mut self) -> Option<&mut Stmt> {
+        Rc::get_mut(&mut self.body)
+    }
+
     pub fn name(&self) -> Option<Identifier> {
         self.name.clone()
     }
@@ -377,11 +544,143 @@ impl Function {
             body,
             marker_position,
             is_static,
+            is_getter: false,
+            captures: RefCell::default(),
         }
     }
+
+    pub fn captures(&self) -> Ref<'_, [Capture]> {
+        Ref::map(self.captures.borrow(), Vec::as_slice)
+    }
+
+    pub fn set_captures(&self, captures: Vec<Capture>) {
+        self.captures.replace(captures);
+    }
 }
 
+/// a field declared in a class body along with the expression that initializes it, e.g. `x = 0;`
+/// or `static PI = 3.14159;`.
 #[derive(Debug)]
+pub struct Field {
+    name: Identifier,
+    // instance fields are evaluated every time the class is instantiated, so the runtime holds onto these.
+    value: Rc<Expr>,
+    is_static: bool,
+}
+
+// like `Function`, a clone gets its own copy of the initializer rather than sharing it.
+impl Clone for Field {
+    fn clone(&self) -> Self {
+        Self::new(
+            self.name.clone(),
+            self.value.as_ref().clone(),
+            self.is_static,
+        )
+    }
+}
+
+impl Field {
+    pub fn new(name: Identifier, value: Expr, is_static: bool) -> Self {
+        Self {
+            name,
+            value: Rc::new(value),
+            is_static,
+        }
+    }
+
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+
+    pub fn value(&self) -> Rc<Expr> {
+        self.value.clone()
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.is_static
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Class {
+    name: Option<Identifier>,
+    superclass: Option<Identifier>,
+    methods: Vec<Function>,
+    fields: Vec<Field>,
+    // like functions, classes can be anonymous when used as an expression
+    // so this is where we point diagnostics when there is no name.
+    marker_position: usize,
+    // what the instance field initializers capture, they run like a method would.
+    field_captures: RefCell<Vec<Capture>>,
+}
+
+impl Class {
+    pub fn new(
+        name: Option<Identifier>,
+        methods: Vec<Function>,
+        fields: Vec<Field>,
+        marker_position: usize,
+    ) -> Self {
+        Self {
+            name,
+            superclass: None,
+            methods,
+            fields,
+            marker_position,
+            field_captures: RefCell::default(),
+        }
+    }
+
+    pub fn field_captures(&self) -> Ref<'_, [Capture]> {
+        Ref::map(self.field_captures.borrow(), Vec::as_slice)
+    }
+
+    pub fn set_field_captures(&self, captures: Vec<Capture>) {
+        self.field_captures.replace(captures);
+    }
+
+    pub fn with_superclass(mut self, superclass: Option<Identifier>) -> Self {
+        self.superclass = superclass;
+        self
+    }
+
+    pub fn superclass(&self) -> Option<&Identifier> {
+        self.superclass.as_ref()
+    }
+
+    pub fn position(&self) -> usize {
+        self.name
+            .as_ref()
+            .map(|ident| ident.position())
+            .unwrap_or(self.marker_position)
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.name.is_none()
+    }
+
+    pub fn name(&self) -> Option<&Identifier> {
+        self.name.as_ref()
+    }
+
+    pub fn methods(&self) -> &[Function] {
+        &self.methods[..]
+    }
+
+    pub fn methods_mut(&mut self) -> &mut [Function] {
+        &mut self.methods[..]
+    }
+
+    pub fn static_fields(&self) -> impl Iterator<Item = &Field> {
+        self.fields.iter().filter(|field| field.is_static())
+    }
+
+    pub fn instance_fields(&self) -> impl Iterator<Item = &Field> {
+        self.fields.iter().filter(|field| !field.is_static())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Expr {
     Binary {
         left: Box<Expr>,
@@ -395,8 +694,10 @@ pub enum Expr {
         right: Box<Expr>,
     },
 
+    // the span covers the parentheses.
     Grouping {
         expr: Box<Expr>,
+        span: Span,
     },
 
     Literal {
@@ -417,6 +718,12 @@ pub enum Expr {
         value: Box<Expr>,
     },
 
+    // `i++` or `i--`, stores one more or less than `i` and evaluates to what `i` was before.
+    Postfix {
+        name: Identifier,
+        op: PostfixOperator,
+    },
+
     Call {
         callee: Callee,
         args: Vec<Expr>,
@@ -441,6 +748,44 @@ pub enum Expr {
         // it needs to be an identifier because we will look it up like any other variable name.
         ident: Identifier,
     },
+
+    // `super.property`, the keyword is looked up like `this` is, and so is the `this` it binds to.
+    Super {
+        keyword: Identifier,
+        this: Identifier,
+        property: Identifier,
+    },
+
+    Class {
+        value: Class,
+    },
+
+    Map {
+        entries: Vec<(Expr, Expr)>,
+        // where the opening brace was, maps have no name to point at.
+        position: usize,
+    },
+
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        // the position of the opening bracket.
+        position: usize,
+    },
+
+    IndexSet {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+        position: usize,
+    },
+
+    // a loop invariant expression whose value is kept in `slot` after it is first evaluated.
+    // these only come from the optional pass in `invariant.rs`, never from the parser.
+    Cached {
+        expr: Box<Expr>,
+        slot: usize,
+    },
 }
 
 impl Expr {
@@ -450,11 +795,12 @@ impl Expr {
     {
         match self {
             Expr::Binary { left, op, right } => v.visit_binary(left, *op, right),
-            Expr::Grouping { expr } => v.visit_grouping(expr),
+            Expr::Grouping { expr, .. } => v.visit_grouping(expr),
             Expr::Literal { value } => v.visit_literal(value),
             Expr::Unary { prefix, value } => v.visit_unary(*prefix, value),
             Expr::Variable { value } => v.visit_variable(value),
             Expr::Assignment { name, value } => v.visit_assignment(name, value),
+            Expr::Postfix { name, op } => v.visit_postfix(name, *op),
             Expr::Logical { left, op, right } => v.visit_logical(left, *op, right),
             Expr::Call { callee, args } => v.visit_call(callee, args),
             Expr::Function { value } => v.visit_function(value),
@@ -465,6 +811,25 @@ impl Expr {
                 value,
             } => v.visit_set(object, property, value),
             Expr::This { ident } => v.visit_this(ident),
+            Expr::Super {
+                keyword,
+                this,
+                property,
+            } => v.visit_super(keyword, this, property),
+            Expr::Class { value } => v.visit_class(value),
+            Expr::Map { entries, position } => v.visit_map(entries, *position),
+            Expr::Index {
+                object,
+                index,
+                position,
+            } => v.visit_index(object, index, *position),
+            Expr::IndexSet {
+                object,
+                index,
+                value,
+                position,
+            } => v.visit_index_set(object, index, value, *position),
+            Expr::Cached { expr, slot } => v.visit_cached(expr, *slot),
         }
     }
 
@@ -476,24 +841,34 @@ impl Expr {
             Self::Unary { .. } => "unary",
             Self::Variable { .. } => "var",
             Self::Assignment { .. } => "assignment",
+            Self::Postfix { .. } => "postfix",
             Self::Logical { .. } => "logical",
             Self::Call { .. } => "call",
             Self::Function { .. } => "function expression",
             Self::Get { .. } => "get",
             Self::Set { .. } => "set",
             Self::This { .. } => "this",
+            Self::Super { .. } => "super",
+            Self::Class { .. } => "class expression",
+            Self::Map { .. } => "map",
+            Self::Index { .. } => "index",
+            Self::IndexSet { .. } => "index assignment",
+            Self::Cached { expr, .. } => expr.type_str(),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Stmt {
     Expression {
         expr: Expr,
     },
 
+    // `print a, b;` writes each value separated by a space, `print;` just ends the line. the span
+    // runs from `print` to the semicolon.
     Print {
-        expr: Expr,
+        exprs: Vec<Expr>,
+        span: Span,
     },
 
     Var {
@@ -501,6 +876,13 @@ pub enum Stmt {
         initializer: Option<Expr>,
     },
 
+    // `var a, b = pair();`, binds each name to the element of an array at the same position.
+    Destructure {
+        names: Vec<Identifier>,
+        initializer: Expr,
+        position: usize,
+    },
+
     Block {
         statements: Vec<Stmt>,
     },
@@ -516,9 +898,22 @@ pub enum Stmt {
         block: Box<Stmt>,
     },
 
+    // what a `for` loop leaves behind once its initializer is pulled out into an enclosing block.
+    // unlike a plain while loop, every iteration gets its own copy of the loop variables.
+    For {
+        condition: Expr,
+        increment: Option<Expr>,
+        body: Box<Stmt>,
+    },
+
     Class {
-        name: Identifier,
-        methods: Vec<Function>,
+        value: Class,
+    },
+
+    // a loop whose cached invariants in `slots` need to be forgotten every time it starts.
+    CachedLoop {
+        slots: Vec<usize>,
+        body: Box<Stmt>,
     },
 
     Break,
@@ -535,8 +930,13 @@ impl Stmt {
     {
         match self {
             Self::Expression { expr } => v.visit_expression_statement(expr),
-            Self::Print { expr } => v.visit_print_statement(expr),
+            Self::Print { exprs, span } => v.visit_print_statement(exprs, *span),
             Self::Var { name, initializer } => v.visit_var_statement(name, initializer.as_ref()),
+            Self::Destructure {
+                names,
+                initializer,
+                position,
+            } => v.visit_destructure_statement(names, initializer, *position),
             Self::Block { statements } => v.visit_block_statement(statements),
             Self::If {
                 condition,
@@ -548,11 +948,17 @@ impl Stmt {
                 else_block.as_ref().map(|stmt| stmt.as_ref()),
             ),
             Self::While { condition, block } => v.visit_while_statement(condition, block),
+            Self::For {
+                condition,
+                increment,
+                body,
+            } => v.visit_for_statement(condition, increment.as_ref(), body),
 
             Self::Break => v.visit_break_statement(),
             Self::Continue => v.visit_continue_statment(),
             Self::Return { value } => v.visit_return_statment(value.as_ref()),
-            Self::Class { name, methods } => v.visit_class_statement(name, methods),
+            Self::Class { value } => v.visit_class_statement(value),
+            Self::CachedLoop { slots, body } => v.visit_cached_loop(slots, body),
         }
     }
 
@@ -561,13 +967,29 @@ impl Stmt {
             Stmt::Expression { .. } => "expression",
             Stmt::Print { .. } => "print",
             Stmt::Var { .. } => "var",
+            Stmt::Destructure { .. } => "destructuring var",
             Stmt::Block { .. } => "block",
             Self::If { .. } => "if",
             Self::While { .. } => "while",
+            Self::For { .. } => "for",
             Self::Break => "break",
             Self::Continue => "continue",
             Self::Return { .. } => "return",
             Self::Class { .. } => "class",
+            Self::CachedLoop { body, .. } => body.type_str(),
+        }
+    }
+
+    /// whether a block holding this statement needs a scope of its own to run in. only
+    /// declarations put anything in a scope, and `for` loops copy the variables of the one they
+    /// run in every iteration, so blocks without either can share their enclosing scope.
+    pub fn needs_scope(&self) -> bool {
+        match self {
+            Self::Var { .. } | Self::Destructure { .. } | Self::Class { .. } | Self::For { .. } => {
+                true
+            }
+            Self::CachedLoop { body, .. } => body.needs_scope(),
+            _ => false,
         }
     }
 }
@@ -1,4 +1,5 @@
 use crate::lang::tokenizer::error::ScanError;
+use crate::lang::tokenizer::span::{Span, code_block};
 use crate::lang::tokenizer::token::{OwnedToken, TokenType};
 use thiserror::Error;
 
@@ -18,6 +19,24 @@ pub enum ConversionError {
     InvalidIdentifier(OwnedToken),
 }
 
+impl ConversionError {
+    pub fn token(&self) -> &OwnedToken {
+        match self {
+            Self::InvalidBinaryOperator(token)
+            | Self::InvalidUnaryOperator(token)
+            | Self::InvalidLogicalOperator(token)
+            | Self::InvalidLiteralType(token)
+            | Self::InvalidNumber(token)
+            | Self::InvalidIdentifier(token) => token,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        let token = self.token();
+        Span::new(token.position, token.position + token.lexeme.len())
+    }
+}
+
 // todo: fill this out.s
 #[derive(Error, Debug)]
 pub enum ParseError {
@@ -30,6 +49,15 @@ pub enum ParseError {
         expected: TokenType,
         recieved: String,
         msg: &'static str,
+        span: Span,
+        // every token that would have been accepted here, `expected` included.
+        candidates: Vec<TokenType>,
+    },
+    #[error("SyntaxError: expected an expression but recieved {recieved}")]
+    ExpectedExpression {
+        recieved: String,
+        span: Span,
+        candidates: Vec<TokenType>,
     },
     #[error("SyntaxError: cannot assign to type '{type_str}'")]
     UnexpectedAssignment { type_str: String, location: usize },
@@ -46,3 +74,230 @@ pub enum ParseError {
     #[error("SyntaxError: unexpected end of file")]
     UnexpectedEof,
 }
+
+/// something that parses but is most likely a mistake. the parser collects these without stopping.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ParseWarning {
+    #[error("warning: {}", self.message())]
+    AssignmentInCondition { span: Span },
+}
+
+impl ParseWarning {
+    /// what the warning is about, without the leading `warning: `.
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::AssignmentInCondition { .. } => {
+                "assignment used as a condition, did you mean '=='? wrap it in another pair of parentheses if not"
+            }
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Self::AssignmentInCondition { span } => *span,
+        }
+    }
+
+    /// render the offending line of `src` with the warning's span underlined.
+    pub fn code_block(&self, src: &str) -> String {
+        code_block(src, self.span())
+    }
+}
+
+/// a problem the resolver found with a program that parsed, reported before any of it runs.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ResolveError {
+    #[error("Resolver error: {name} already declared in this scope")]
+    AlreadyDeclared { name: String, span: Span },
+    #[error("Resolver error: cannot read '{name}' in its own initializer")]
+    ReadInOwnInitializer { name: String, span: Span },
+    #[error("Resolver error: class '{name}' cannot inherit from itself")]
+    InheritsFromItself { name: String, span: Span },
+    #[error("Resolver error: 'super' can only be used in the methods of a subclass")]
+    SuperOutsideSubclass { span: Span },
+    #[error("Resolver error: 'this' cannot be used in the global scope")]
+    ThisOutsideMethod { span: Span },
+    #[error("Resolver error: '{keyword}' cannot be used in a static method")]
+    InStaticMethod { keyword: String, span: Span },
+    #[error("Resolver error: undeclared identifier '{name}'")]
+    UndefinedGlobal { name: String, span: Span },
+}
+
+impl ResolveError {
+    /// the name or keyword the error is about.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::AlreadyDeclared { span, .. }
+            | Self::ReadInOwnInitializer { span, .. }
+            | Self::InheritsFromItself { span, .. }
+            | Self::SuperOutsideSubclass { span }
+            | Self::ThisOutsideMethod { span }
+            | Self::InStaticMethod { span, .. }
+            | Self::UndefinedGlobal { span, .. } => *span,
+        }
+    }
+
+    /// render the offending line of `src` with the error's span underlined.
+    pub fn code_block(&self, src: &str) -> String {
+        code_block(src, self.span())
+    }
+}
+
+impl ParseError {
+    /// the region of source the error should point at, if we know one.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::UnexpectedToken { span, .. } | Self::ExpectedExpression { span, .. } => {
+                Some(*span)
+            }
+            Self::InvalidLoopKeyword { type_str, location } => {
+                Some(Span::new(*location, location + type_str.len()))
+            }
+            Self::InvalidReturn { location } => {
+                Some(Span::new(*location, location + "return".len()))
+            }
+            Self::UnexpectedAssignment { location, .. }
+            | Self::FuncExceedMaxArgs { location, .. }
+            | Self::InvalidFuncStatement { location }
+            | Self::InvalidClassMethod { location } => Some(Span::at(*location)),
+            Self::ScanError(e) => e.span(),
+            Self::ConversionError(e) => Some(e.span()),
+            Self::UnexpectedEof => None,
+        }
+    }
+
+    /// the tokens the parser would have accepted where the error happened, for editors to offer as
+    /// completions. empty for errors that aren't about an unexpected token.
+    pub fn expected_tokens(&self) -> &[TokenType] {
+        match self {
+            Self::UnexpectedToken { candidates, .. }
+            | Self::ExpectedExpression { candidates, .. } => &candidates[..],
+            _ => &[],
+        }
+    }
+
+    /// render the offending line of `src` with the error's span underlined.
+    pub fn code_block(&self, src: &str) -> Option<String> {
+        self.span().map(|span| code_block(src, span))
+    }
+
+    pub fn print_code_block(&self, src: &str) {
+        if let Some(block) = self.code_block(src) {
+            println!("{}", block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang::tokenizer::token::TokenType;
+    use crate::lang::tree::parser::Parser;
+
+    fn first_error_block(src: &str) -> String {
+        let mut parser = Parser::new(src);
+        parser.parse();
+        let err = parser
+            .errors()
+            .first()
+            .expect("source should fail to parse");
+        err.code_block(src).expect("error should carry a span")
+    }
+
+    #[test]
+    fn test_code_block_line_numbers_unix() {
+        let block = first_error_block("var a = 1;\nvar b = 2;\nvar = 3;\n");
+        assert!(block.starts_with(" --> 3:5"), "{block}");
+        assert!(block.contains("3 | var = 3;"), "{block}");
+    }
+
+    #[test]
+    fn test_code_block_line_numbers_windows() {
+        let block = first_error_block("var a = 1;\r\nvar b = 2;\r\nvar = 3;\r\n");
+        assert!(block.starts_with(" --> 3:5"), "{block}");
+        assert!(block.contains("3 | var = 3;\n"), "{block}");
+    }
+
+    #[test]
+    fn test_code_block_first_line() {
+        let block = first_error_block("var 1 = 2;");
+        assert!(block.starts_with(" --> 1:5"), "{block}");
+        assert!(block.ends_with("1 | var 1 = 2;\n  |     ^"), "{block}");
+    }
+
+    #[test]
+    fn test_code_block_scan_error() {
+        let block = first_error_block("var a = 1;\nvar b = @;");
+        assert!(block.starts_with(" --> 2:9"), "{block}");
+        assert!(block.ends_with("2 | var b = @;\n  |         ^"), "{block}");
+    }
+
+    #[test]
+    fn test_code_block_unterminated_string() {
+        let block = first_error_block("print \"abc\nprint 1;");
+        assert!(block.starts_with(" --> 1:7"), "{block}");
+        assert!(block.ends_with("  |       ^^^^"), "{block}");
+    }
+
+    #[test]
+    fn test_expected_tokens_at_statement_start() {
+        let mut parser = Parser::new("var a = 1;\n) print a;");
+        parser.parse();
+        let expected = parser.errors()[0].expected_tokens();
+        for t in [
+            TokenType::Var,
+            TokenType::Print,
+            TokenType::If,
+            TokenType::Return,
+            TokenType::LeftBrace,
+            TokenType::Identifier,
+            TokenType::Number,
+            TokenType::Minus,
+            TokenType::LeftParen,
+        ] {
+            assert!(expected.contains(&t), "{t} missing from {expected:?}");
+        }
+        // only what was tried at the failing token counts, not what was tried before it.
+        assert!(!expected.contains(&TokenType::Semicolon), "{expected:?}");
+        assert!(!expected.contains(&TokenType::RightParen), "{expected:?}");
+
+        let mut parser = Parser::new("var a = 1");
+        parser.parse();
+        let expected = parser.errors()[0].expected_tokens();
+        assert!(expected.contains(&TokenType::Semicolon), "{expected:?}");
+        assert!(expected.contains(&TokenType::Plus), "{expected:?}");
+    }
+
+    #[test]
+    fn test_code_block_conversion_error() {
+        let block = first_error_block("print 1, ;");
+        assert!(block.ends_with("1 | print 1, ;\n  |          ^"), "{block}");
+    }
+
+    #[test]
+    fn test_assignment_in_condition_warns() {
+        let warnings = |src: &str| {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            assert!(!parser.had_errors(), "{src} should parse");
+            parser.warnings().to_vec()
+        };
+        for src in [
+            "var x; if (x = 1) print x;",
+            "var x; while (x = false) {}",
+            "var o; for (; o.done = true; o = nil) {}",
+        ] {
+            let found = warnings(src);
+            assert_eq!(found.len(), 1, "{src} should warn once");
+            let span = found[0].span();
+            let target = if src.contains("o.done") { "done" } else { "x" };
+            assert_eq!(&src[span.start..span.end], target, "{src}");
+        }
+        for src in [
+            "var x; if ((x = 1)) print x;",
+            "var x; if (x == 1) print x;",
+            "var x; x = 1; for (var i = 0; i < 1; i = i + 1) {}",
+        ] {
+            assert!(warnings(src).is_empty(), "{src} should not warn");
+        }
+    }
+}
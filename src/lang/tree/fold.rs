@@ -0,0 +1,247 @@
+//! An optional pass that folds operations on literals into a single literal.
+//!
+//! `2 * 60 * 60` or `"a" + "b"` are worked out once here instead of every time they run. Only
+//! operations that can't fail are folded: arithmetic and comparisons between two numbers, joining
+//! two strings, and `==`/`!=` between any two literals. Anything that would raise a type error,
+//! such as `"a" + 1`, is left for the interpreter so the error still happens when (and if) the
+//! expression runs. A folded literal spans the whole expression it replaced.
+//!
+//! Like the loop invariant pass it has to run before the tree is shared.
+use super::ast::{BinaryOperator, Expr, Literal, Stmt, UnaryPrefix};
+use super::invariant::children;
+use crate::lang::tokenizer::span::Span;
+
+/// fold the constant expressions in `stmts`, including those inside functions and classes.
+pub fn fold_constants(stmts: &mut [Stmt]) {
+    for stmt in stmts {
+        fold_stmt(stmt);
+    }
+}
+
+fn fold_stmt(stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Expression { expr } => fold_expr(expr),
+        Stmt::Print { exprs, .. } => exprs.iter_mut().for_each(fold_expr),
+        Stmt::Var {
+            initializer: Some(expr),
+            ..
+        }
+        | Stmt::Destructure {
+            initializer: expr, ..
+        }
+        | Stmt::Return { value: Some(expr) } => fold_expr(expr),
+        Stmt::Block { statements } => statements.iter_mut().for_each(fold_stmt),
+        Stmt::If {
+            condition,
+            if_block,
+            else_block,
+        } => {
+            fold_expr(condition);
+            fold_stmt(if_block);
+            if let Some(else_block) = else_block {
+                fold_stmt(else_block);
+            }
+        }
+        Stmt::While { condition, block } => {
+            fold_expr(condition);
+            fold_stmt(block);
+        }
+        Stmt::For {
+            condition,
+            increment,
+            body,
+        } => {
+            fold_expr(condition);
+            if let Some(increment) = increment {
+                fold_expr(increment);
+            }
+            fold_stmt(body);
+        }
+        Stmt::Class { value } => {
+            for method in value.methods_mut() {
+                if let Some(body) = method.body_mut() {
+                    fold_stmt(body);
+                }
+            }
+        }
+        Stmt::CachedLoop { body, .. } => fold_stmt(body),
+        Stmt::Var { .. } | Stmt::Return { .. } | Stmt::Break | Stmt::Continue => {}
+    }
+}
+
+fn fold_expr(expr: &mut Expr) {
+    match expr {
+        Expr::Function { value } => {
+            if let Some(body) = value.body_mut() {
+                fold_stmt(body);
+            }
+            return;
+        }
+        Expr::Class { value } => {
+            for method in value.methods_mut() {
+                if let Some(body) = method.body_mut() {
+                    fold_stmt(body);
+                }
+            }
+            return;
+        }
+        _ => children(expr).into_iter().for_each(fold_expr),
+    }
+    if let Some(value) = folded(expr) {
+        *expr = Expr::Literal { value };
+    }
+}
+
+// the literal `expr` works out to, given its operands have already been folded.
+fn folded(expr: &Expr) -> Option<Literal> {
+    match expr {
+        // the parentheses are part of what the literal replaces.
+        Expr::Grouping { expr, span } => literal(expr).map(|value| value.clone().with_span(*span)),
+        // `!` is left alone, it follows the interpreter's idea of truthiness.
+        Expr::Unary {
+            prefix: UnaryPrefix::Minus(position),
+            value,
+        } => match literal(value)? {
+            Literal::Number { value, span } => {
+                Some(Literal::new_number(-value, Span::new(*position, span.end)))
+            }
+            _ => None,
+        },
+        Expr::Binary { left, op, right } => binary(literal(left)?, *op, literal(right)?),
+        _ => None,
+    }
+}
+
+fn literal(expr: &Expr) -> Option<&Literal> {
+    match expr {
+        Expr::Literal { value } => Some(value),
+        _ => None,
+    }
+}
+
+fn binary(left: &Literal, op: BinaryOperator, right: &Literal) -> Option<Literal> {
+    let span = Span::new(left.span().start, right.span().end);
+    match op {
+        BinaryOperator::Equal(_) => return Some(Literal::new_boolean(same(left, right), span)),
+        BinaryOperator::NotEqual(_) => {
+            return Some(Literal::new_boolean(!same(left, right), span));
+        }
+        _ => {}
+    }
+    match (left, right) {
+        (Literal::Number { value: a, .. }, Literal::Number { value: b, .. }) => {
+            let (a, b) = (*a, *b);
+            // results like `0 / 0` are left for the interpreter, which may be set to reject them.
+            let number = |n: f64| n.is_finite().then(|| Literal::new_number(n, span));
+            let boolean = |b: bool| Some(Literal::new_boolean(b, span));
+            match op {
+                BinaryOperator::Plus(_) => number(a + b),
+                BinaryOperator::Minus(_) => number(a - b),
+                BinaryOperator::Star(_) => number(a * b),
+                BinaryOperator::Slash(_) => number(a / b),
+                BinaryOperator::Power(_) => number(a.powf(b)),
+                BinaryOperator::Greater(_) => boolean(a > b),
+                BinaryOperator::GreaterEqual(_) => boolean(a >= b),
+                BinaryOperator::Less(_) => boolean(a < b),
+                BinaryOperator::LessEqual(_) => boolean(a <= b),
+                BinaryOperator::Equal(_) | BinaryOperator::NotEqual(_) => None,
+            }
+        }
+        (Literal::String { value: a, .. }, Literal::String { value: b, .. }) => match op {
+            BinaryOperator::Plus(_) => Some(Literal::new_string(format!("{a}{b}"), span)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// `==` between two literals, the same way the interpreter compares primitives.
+fn same(left: &Literal, right: &Literal) -> bool {
+    match (left, right) {
+        (Literal::Number { value: a, .. }, Literal::Number { value: b, .. }) => a == b,
+        (Literal::String { value: a, .. }, Literal::String { value: b, .. }) => a == b,
+        (Literal::Boolean { value: a, .. }, Literal::Boolean { value: b, .. }) => a == b,
+        (Literal::Nil { .. }, Literal::Nil { .. }) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::tree::parser::Parser;
+
+    // the folded form of the single expression statement in `src`.
+    fn fold(src: &str) -> Expr {
+        let mut parser = Parser::new(src);
+        parser.parse();
+        assert!(!parser.had_errors(), "failed to parse {src}");
+        let mut stmts = parser.take_statements();
+        fold_constants(&mut stmts);
+        match stmts.remove(0) {
+            Stmt::Expression { expr } => expr,
+            other => panic!("expected an expression statement, got {}", other.type_str()),
+        }
+    }
+
+    fn number(src: &str) -> f64 {
+        match fold(src) {
+            Expr::Literal {
+                value: Literal::Number { value, .. },
+            } => value,
+            other => panic!("{src} did not fold to a number, got {}", other.type_str()),
+        }
+    }
+
+    #[test]
+    fn test_fold_arithmetic() {
+        assert_eq!(number("2 * 60 * 60;"), 7200.0);
+        assert_eq!(number("-(1 + 2) ** 2;"), -9.0);
+        assert_eq!(number("(10 - 4) / 4;"), 1.5);
+        assert!(matches!(
+            fold("\"a\" + \"b\" == \"ab\";"),
+            Expr::Literal {
+                value: Literal::Boolean { value: true, .. }
+            }
+        ));
+    }
+
+    #[test]
+    fn test_folded_literal_spans_the_expression() {
+        for (src, expected) in [
+            ("  1 + 2;", "1 + 2"),
+            ("-3 * 2 == nil;", "-3 * 2 == nil"),
+            ("(4 - 1) * 2;", "(4 - 1) * 2"),
+            ("-(5);", "-(5)"),
+        ] {
+            let Expr::Literal { value } = fold(src) else {
+                panic!("{src} did not fold");
+            };
+            let span = value.span();
+            assert_eq!(&src[span.start..span.end], expected, "{src}");
+            assert_eq!(value.position(), span.start);
+        }
+    }
+
+    #[test]
+    fn test_leaves_what_could_fail() {
+        for src in [
+            "\"a\" + 1;",
+            "\"a\" < \"b\";",
+            "-\"a\";",
+            "1 / 0;",
+            "!true;",
+            "x + 1;",
+        ] {
+            assert!(
+                !matches!(fold(src), Expr::Literal { .. }),
+                "{src} should not fold"
+            );
+        }
+        // the operands still fold even when the operation can't.
+        let Expr::Binary { left, .. } = fold("(1 + 2) + \"a\";") else {
+            panic!("expected a binary expression");
+        };
+        assert!(matches!(*left, Expr::Literal { .. }));
+    }
+}
@@ -0,0 +1,469 @@
+//! An optional pass that caches loop invariant subexpressions.
+//!
+//! Rather than hoisting an invariant out of the loop (which would evaluate it even when the loop
+//! never runs, or raise its errors early) the expression is wrapped in an `Expr::Cached` node.
+//! The first evaluation inside a run of the loop stores the value and later iterations reuse it,
+//! the enclosing `Stmt::CachedLoop` forgets the values every time the loop starts over.
+//!
+//! The pass is deliberately conservative. Only literals, unary, binary and logical operators are
+//! ever cached, and variables only count as invariant when nothing in the loop assigns or
+//! re-declares a name like them and the loop makes no calls (getters included) that could
+//! change them behind our back.
+//!
+//! It relies on the resolver having already run, and has to run before the tree is shared.
+use super::ast::{Class, Expr, Literal, Stmt};
+use crate::lang::tokenizer::span::Span;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// slots are handed out process wide so trees processed separately (e.g. REPL lines) never collide.
+static NEXT_SLOT: AtomicUsize = AtomicUsize::new(0);
+
+/// wrap the invariant subexpressions of every loop in `stmts`, including loops inside functions.
+pub fn cache_loop_invariants(stmts: &mut [Stmt]) {
+    for stmt in stmts {
+        walk_stmt(stmt);
+    }
+}
+
+fn walk_stmt(stmt: &mut Stmt) {
+    match stmt {
+        Stmt::While { .. } | Stmt::For { .. } => cache_loop(stmt),
+        Stmt::Expression { expr } => walk_expr(expr),
+        Stmt::Print { exprs, .. } => exprs.iter_mut().for_each(walk_expr),
+        Stmt::Var {
+            initializer: Some(expr),
+            ..
+        }
+        | Stmt::Destructure {
+            initializer: expr, ..
+        }
+        | Stmt::Return { value: Some(expr) } => walk_expr(expr),
+        Stmt::Block { statements } => statements.iter_mut().for_each(walk_stmt),
+        Stmt::If {
+            condition,
+            if_block,
+            else_block,
+        } => {
+            walk_expr(condition);
+            walk_stmt(if_block);
+            if let Some(else_block) = else_block {
+                walk_stmt(else_block);
+            }
+        }
+        Stmt::Class { value } => {
+            for method in value.methods_mut() {
+                if let Some(body) = method.body_mut() {
+                    walk_stmt(body);
+                }
+            }
+        }
+        Stmt::CachedLoop { body, .. } => walk_stmt(body),
+        Stmt::Var { .. } | Stmt::Return { .. } | Stmt::Break | Stmt::Continue => {}
+    }
+}
+
+// expressions only matter here for the functions they might define.
+fn walk_expr(expr: &mut Expr) {
+    match expr {
+        Expr::Function { value } => {
+            if let Some(body) = value.body_mut() {
+                walk_stmt(body);
+            }
+        }
+        Expr::Class { value } => {
+            for method in value.methods_mut() {
+                if let Some(body) = method.body_mut() {
+                    walk_stmt(body);
+                }
+            }
+        }
+        _ => children(expr).into_iter().for_each(walk_expr),
+    }
+}
+
+fn cache_loop(stmt: &mut Stmt) {
+    let (condition, increment, block) = match stmt {
+        Stmt::While { condition, block } => (condition, None, block),
+        Stmt::For {
+            condition,
+            increment,
+            body,
+        } => (condition, increment.as_mut(), body),
+        _ => return,
+    };
+    let mut scan = Scan::default();
+    scan.expr(condition);
+    increment.iter().for_each(|inc| scan.expr(inc));
+    scan.stmt(block);
+
+    let mut slots = Vec::new();
+    scan.wrap_expr(condition, &mut slots);
+    if let Some(increment) = increment {
+        scan.wrap_expr(increment, &mut slots);
+    }
+    scan.wrap_stmt(block, &mut slots);
+    // loops and functions nested inside get their own chance at what is invariant to them.
+    walk_stmt(block);
+
+    if !slots.is_empty() {
+        let body = std::mem::replace(stmt, Stmt::Break);
+        *stmt = Stmt::CachedLoop {
+            slots,
+            body: Box::new(body),
+        };
+    }
+}
+
+/// what a loop does that decides which of its expressions are invariant.
+#[derive(Default)]
+struct Scan {
+    // every name assigned or declared anywhere in the loop.
+    assigned: HashSet<String>,
+    // whether the loop runs any code we can't see, which could assign anything.
+    calls: bool,
+}
+
+impl Scan {
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Var { name, initializer } => {
+                self.assigned.insert(name.name_str().to_string());
+                initializer.iter().for_each(|expr| self.expr(expr));
+            }
+            Stmt::Destructure {
+                names, initializer, ..
+            } => {
+                for name in names {
+                    self.assigned.insert(name.name_str().to_string());
+                }
+                self.expr(initializer);
+            }
+            Stmt::Class { value } => {
+                if let Some(name) = value.name() {
+                    self.assigned.insert(name.name_str().to_string());
+                }
+                self.class(value);
+            }
+            _ => {
+                let (exprs, stmts) = stmt_children(stmt);
+                exprs.into_iter().for_each(|expr| self.expr(expr));
+                stmts.into_iter().for_each(|stmt| self.stmt(stmt));
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assignment { name, value } => {
+                self.assigned.insert(name.name_str().to_string());
+                self.expr(value);
+            }
+            Expr::Postfix { name, .. } => {
+                self.assigned.insert(name.name_str().to_string());
+            }
+            Expr::Call { callee, args } => {
+                self.calls = true;
+                self.expr(&callee.expr);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::Get { object, .. } => {
+                // properties might be getters.
+                self.calls = true;
+                self.expr(object);
+            }
+            Expr::Super { .. } => self.calls = true,
+            Expr::Function { value } => {
+                value.params().iter().for_each(|p| {
+                    self.assigned.insert(p.name_str().to_string());
+                });
+                self.stmt(&value.body());
+            }
+            Expr::Class { value } => self.class(value),
+            _ => children_ref(expr).into_iter().for_each(|e| self.expr(e)),
+        }
+    }
+
+    // static field initializers run when the class is declared, inside the loop, and instance
+    // ones whenever it is instantiated, so both count along with the methods.
+    fn class(&mut self, value: &Class) {
+        value.methods().iter().for_each(|m| self.stmt(&m.body()));
+        value
+            .static_fields()
+            .chain(value.instance_fields())
+            .for_each(|field| self.expr(&field.value()));
+    }
+
+    fn is_invariant(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Literal { .. } | Expr::Cached { .. } => true,
+            Expr::Variable { value } => !self.calls && !self.assigned.contains(value.name_str()),
+            Expr::Grouping { expr, .. } | Expr::Unary { value: expr, .. } => {
+                self.is_invariant(expr)
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.is_invariant(left) && self.is_invariant(right)
+            }
+            _ => false,
+        }
+    }
+
+    fn wrap_expr(&self, expr: &mut Expr, slots: &mut Vec<usize>) {
+        if self.is_invariant(expr) {
+            if is_operation(expr) {
+                let slot = NEXT_SLOT.fetch_add(1, Ordering::Relaxed);
+                let inner = std::mem::replace(
+                    expr,
+                    Expr::Literal {
+                        value: Literal::new_nil(Span::default()),
+                    },
+                );
+                *expr = Expr::Cached {
+                    expr: Box::new(inner),
+                    slot,
+                };
+                slots.push(slot);
+            }
+            return;
+        }
+        match expr {
+            // function bodies don't run as part of the loop.
+            Expr::Function { .. } | Expr::Class { .. } => {}
+            _ => children(expr)
+                .into_iter()
+                .for_each(|e| self.wrap_expr(e, slots)),
+        }
+    }
+
+    fn wrap_stmt(&self, stmt: &mut Stmt, slots: &mut Vec<usize>) {
+        match stmt {
+            Stmt::Expression { expr } => self.wrap_expr(expr, slots),
+            Stmt::Print { exprs, .. } => {
+                for expr in exprs {
+                    self.wrap_expr(expr, slots);
+                }
+            }
+            Stmt::Var {
+                initializer: Some(expr),
+                ..
+            }
+            | Stmt::Destructure {
+                initializer: expr, ..
+            }
+            | Stmt::Return { value: Some(expr) } => self.wrap_expr(expr, slots),
+            Stmt::Block { statements } => {
+                for stmt in statements {
+                    self.wrap_stmt(stmt, slots);
+                }
+            }
+            Stmt::If {
+                condition,
+                if_block,
+                else_block,
+            } => {
+                self.wrap_expr(condition, slots);
+                self.wrap_stmt(if_block, slots);
+                if let Some(else_block) = else_block {
+                    self.wrap_stmt(else_block, slots);
+                }
+            }
+            Stmt::While { condition, block } => {
+                self.wrap_expr(condition, slots);
+                self.wrap_stmt(block, slots);
+            }
+            Stmt::For {
+                condition,
+                increment,
+                body,
+            } => {
+                self.wrap_expr(condition, slots);
+                if let Some(increment) = increment {
+                    self.wrap_expr(increment, slots);
+                }
+                self.wrap_stmt(body, slots);
+            }
+            Stmt::CachedLoop { body, .. } => self.wrap_stmt(body, slots),
+            Stmt::Class { .. }
+            | Stmt::Var { .. }
+            | Stmt::Return { .. }
+            | Stmt::Break
+            | Stmt::Continue => {}
+        }
+    }
+}
+
+// only worth caching if there's actually some work to skip.
+fn is_operation(expr: &Expr) -> bool {
+    match expr {
+        Expr::Grouping { expr, .. } => is_operation(expr),
+        Expr::Unary { .. } | Expr::Binary { .. } | Expr::Logical { .. } => true,
+        _ => false,
+    }
+}
+
+pub(super) fn children(expr: &mut Expr) -> Vec<&mut Expr> {
+    match expr {
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => vec![left, right],
+        Expr::Grouping { expr, .. } | Expr::Cached { expr, .. } => vec![expr],
+        Expr::Unary { value, .. } | Expr::Assignment { value, .. } => vec![value],
+        Expr::Call { callee, args } => {
+            let mut children = vec![callee.expr.as_mut()];
+            children.extend(args.iter_mut());
+            children
+        }
+        Expr::Get { object, .. } => vec![object],
+        Expr::Set { object, value, .. } => vec![object, value],
+        Expr::Map { entries, .. } => entries
+            .iter_mut()
+            .flat_map(|(key, value)| [key, value])
+            .collect(),
+        Expr::Index { object, index, .. } => vec![object, index],
+        Expr::IndexSet {
+            object,
+            index,
+            value,
+            ..
+        } => vec![object, index, value],
+        Expr::Literal { .. }
+        | Expr::Variable { .. }
+        | Expr::Postfix { .. }
+        | Expr::This { .. }
+        | Expr::Super { .. }
+        | Expr::Function { .. }
+        | Expr::Class { .. } => vec![],
+    }
+}
+
+fn children_ref(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => vec![left, right],
+        Expr::Grouping { expr, .. } | Expr::Cached { expr, .. } => vec![expr],
+        Expr::Unary { value, .. } | Expr::Assignment { value, .. } => vec![value],
+        Expr::Call { callee, args } => {
+            let mut children = vec![callee.expr.as_ref()];
+            children.extend(args.iter());
+            children
+        }
+        Expr::Get { object, .. } => vec![object],
+        Expr::Set { object, value, .. } => vec![object, value],
+        Expr::Map { entries, .. } => entries
+            .iter()
+            .flat_map(|(key, value)| [key, value])
+            .collect(),
+        Expr::Index { object, index, .. } => vec![object, index],
+        Expr::IndexSet {
+            object,
+            index,
+            value,
+            ..
+        } => vec![object, index, value],
+        Expr::Literal { .. }
+        | Expr::Variable { .. }
+        | Expr::Postfix { .. }
+        | Expr::This { .. }
+        | Expr::Super { .. }
+        | Expr::Function { .. }
+        | Expr::Class { .. } => vec![],
+    }
+}
+
+fn stmt_children(stmt: &Stmt) -> (Vec<&Expr>, Vec<&Stmt>) {
+    match stmt {
+        Stmt::Expression { expr } => (vec![expr], vec![]),
+        Stmt::Print { exprs, .. } => (exprs.iter().collect(), vec![]),
+        Stmt::Var { initializer, .. } => (initializer.iter().collect(), vec![]),
+        Stmt::Destructure { initializer, .. } => (vec![initializer], vec![]),
+        Stmt::Return { value } => (value.iter().collect(), vec![]),
+        Stmt::Block { statements } => (vec![], statements.iter().collect()),
+        Stmt::If {
+            condition,
+            if_block,
+            else_block,
+        } => {
+            let mut stmts = vec![if_block.as_ref()];
+            stmts.extend(else_block.as_deref());
+            (vec![condition], stmts)
+        }
+        Stmt::While { condition, block } => (vec![condition], vec![block]),
+        Stmt::For {
+            condition,
+            increment,
+            body,
+        } => {
+            let mut exprs = vec![condition];
+            exprs.extend(increment.as_ref());
+            (exprs, vec![body])
+        }
+        Stmt::CachedLoop { body, .. } => (vec![], vec![body]),
+        Stmt::Class { .. } | Stmt::Break | Stmt::Continue => (vec![], vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::tree::parser::Parser;
+    use crate::lang::tree::resolver::Resolver;
+
+    fn cached(src: &str) -> Vec<String> {
+        let mut parser = Parser::new(src);
+        parser.parse();
+        assert!(!parser.had_errors(), "failed to parse {src}");
+        let mut stmts = parser.take_statements();
+        let mut resolver = Resolver::new();
+        for stmt in &stmts {
+            stmt.accept(&mut resolver).unwrap();
+        }
+        cache_loop_invariants(&mut stmts);
+        let mut found = Vec::new();
+        stmts
+            .iter()
+            .for_each(|stmt| collect_cached(stmt, &mut found));
+        found
+    }
+
+    fn collect_cached(stmt: &Stmt, found: &mut Vec<String>) {
+        let (exprs, stmts) = stmt_children(stmt);
+        for expr in exprs {
+            collect_cached_expr(expr, found);
+        }
+        for stmt in stmts {
+            collect_cached(stmt, found);
+        }
+    }
+
+    fn collect_cached_expr(expr: &Expr, found: &mut Vec<String>) {
+        match expr {
+            Expr::Cached { expr, .. } => found.push(expr.type_str().to_string()),
+            Expr::Function { value } => collect_cached(&value.body(), found),
+            _ => children_ref(expr)
+                .into_iter()
+                .for_each(|e| collect_cached_expr(e, found)),
+        }
+    }
+
+    #[test]
+    fn test_caches_arithmetic_over_unassigned_variables() {
+        let found = cached("var a = 2; var i = 0; while (i < 10) { i = i + a * 3; }");
+        assert_eq!(found, vec!["binary"]);
+    }
+
+    #[test]
+    fn test_never_caches_assigned_variables() {
+        let found = cached("var a = 2; var i = 0; while (i < 10) { a = a + 1; i = i + a * 3; }");
+        assert!(found.is_empty(), "{found:?}");
+    }
+
+    #[test]
+    fn test_calls_make_variables_variant() {
+        let src = "var a = 2; fun bump() { a = a + 1; } var i = 0; while (i < 10) { bump(); i = i + a * 3 + (1 + 2); }";
+        // only the literal-only expression survives.
+        assert_eq!(cached(src), vec!["grouping"]);
+    }
+
+    #[test]
+    fn test_loops_inside_functions() {
+        let found = cached("fun f(n) { var i = 0; while (i < n) { i = i + n * 2; } } ");
+        assert_eq!(found, vec!["binary"]);
+    }
+}
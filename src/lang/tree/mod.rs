@@ -1,4 +1,6 @@
 pub mod ast;
 pub mod error;
+pub mod fold;
+pub mod invariant;
 pub mod parser;
 pub mod resolver;
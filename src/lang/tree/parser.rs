@@ -1,29 +1,47 @@
 use super::ast::Expr;
-use super::error::ParseError;
+use super::error::{ParseError, ParseWarning};
+use crate::lang::tokenizer::error::ScanError;
 use crate::lang::tokenizer::scanner::Scanner;
+use crate::lang::tokenizer::span::Span;
 use crate::lang::tokenizer::token::{Token, TokenType};
-use crate::lang::tree::ast::{BinaryOperator, Callee, Function, Identifier, Literal, Stmt};
-use std::iter::{Iterator, Peekable};
+use crate::lang::tree::ast::{
+    BinaryOperator, Callee, Class, Field, Function, Identifier, Literal, Stmt,
+};
+use std::collections::VecDeque;
+use std::iter::Iterator;
 use std::rc::Rc;
 
 const MAX_FUNC_ARGS: usize = 255;
 
 struct TokenStream<'a> {
-    tokens: Peekable<Scanner<'a>>,
+    scanner: Scanner<'a>,
+    // tokens scanned ahead of the parser but not consumed yet, at most two.
+    lookahead: VecDeque<Result<Token<'a>, ScanError>>,
     last_token: Option<Token<'a>>,
 }
 
 impl<'a> TokenStream<'a> {
     fn new(src: &'a str) -> Self {
         Self {
-            tokens: Scanner::new(src).peekable(),
+            scanner: Scanner::new(src),
+            lookahead: VecDeque::with_capacity(2),
             last_token: None,
         }
     }
 
+    fn fill(&mut self, n: usize) {
+        while self.lookahead.len() < n {
+            match self.scanner.next() {
+                Some(result) => self.lookahead.push_back(result),
+                None => break,
+            }
+        }
+    }
+
     fn next(&mut self) -> Result<Token<'a>, ParseError> {
-        if let Some(result) = self.tokens.next() {
-            let token = result.map_err(|e| ParseError::from(e))?;
+        self.fill(1);
+        if let Some(result) = self.lookahead.pop_front() {
+            let token = result.map_err(ParseError::from)?;
             self.last_token = Some(token.clone());
             return Ok(token);
         }
@@ -34,36 +52,42 @@ impl<'a> TokenStream<'a> {
     where
         F: FnOnce(&Token<'a>) -> bool,
     {
-        if let Some(result) = self.tokens.peek() {
-            match result {
-                Ok(t) if condition(t) => {
-                    let token = self.next().unwrap();
-                    return Some(token);
-                }
-                _ => return None,
-            }
+        match self.peek() {
+            Some(Ok(t)) if condition(t) => Some(self.next().unwrap()),
+            _ => None,
         }
-        None
     }
 
     fn peek(&mut self) -> Option<Result<&Token<'a>, ParseError>> {
-        self.tokens
-            .peek()
+        self.fill(1);
+        self.lookahead
+            .front()
             .map(|r| r.as_ref().map_err(|e| e.clone().into()))
     }
 
+    /// the token after the next one. a scan error in the next token surfaces here first,
+    /// so errors are still reported in the order they appear in the source.
+    fn peek2(&mut self) -> Option<Result<&Token<'a>, ParseError>> {
+        self.fill(2);
+        match self.lookahead.front()? {
+            Err(e) => Some(Err(e.clone().into())),
+            Ok(_) => self
+                .lookahead
+                .get(1)
+                .map(|r| r.as_ref().map_err(|e| e.clone().into())),
+        }
+    }
+
     fn peek_next_if<F>(&mut self, condition: F) -> Result<Option<&Token<'a>>, ParseError>
     where
         F: FnOnce(&Token<'a>) -> bool,
     {
-        if let Some(t) = self.tokens.peek() {
-            match t {
-                Ok(toke) if condition(toke) => return Ok(Some(toke)),
-                Ok(_) => return Ok(None),
-                Err(e) => return Err(e.clone().into()),
-            }
+        match self.peek() {
+            Some(Ok(toke)) if condition(toke) => Ok(Some(toke)),
+            Some(Ok(_)) => Ok(None),
+            Some(Err(e)) => Err(e),
+            None => Err(ParseError::UnexpectedEof),
         }
-        Err(ParseError::UnexpectedEof)
     }
 
     fn assert(&mut self, t: TokenType, msg: &'static str) -> Result<Token<'a>, ParseError> {
@@ -73,50 +97,127 @@ impl<'a> TokenStream<'a> {
                 expected: t,
                 recieved: token.token_type.to_string(),
                 msg,
+                span: token.span(),
+                candidates: vec![t],
             });
         }
         Ok(token)
     }
 
+    /// break a `--` at the front of the stream into two `-`, for when it's a minus sign and a
+    /// negation rather than a decrement.
+    fn split_minus_minus(&mut self) {
+        self.fill(1);
+        let Some(Ok(toke)) = self.lookahead.front() else {
+            return;
+        };
+        if toke.token_type != TokenType::MinusMinus {
+            return;
+        }
+        let (first, second) = toke.lexeme.split_at(1);
+        let position = toke.position;
+        self.lookahead.pop_front();
+        self.lookahead
+            .push_front(Ok(Token::new(TokenType::Minus, second, position + 1)));
+        self.lookahead
+            .push_front(Ok(Token::new(TokenType::Minus, first, position)));
+    }
+
     fn last(&self) -> Option<&Token<'a>> {
         self.last_token.as_ref()
     }
 }
 
 pub struct Parser<'a> {
+    src: &'a str,
     tokens: TokenStream<'a>,
     statements: Vec<Stmt>,
     errors: Vec<ParseError>,
     loop_cnt: i8,
     fn_cnt: i8,
+    warnings: Vec<ParseWarning>,
+    // whether an expression at the very end of the input may leave off its semicolon.
+    allow_bare_expression: bool,
+    // whether the statement being parsed sits directly in a function body, where an expression
+    // right before the closing `}` may leave off its semicolon and is returned.
+    implicit_return: bool,
+    // whether errors are printed as they're reported, on top of being collected.
+    print_errors: bool,
+    // the tokens tried against the next token so far, and where that token starts. errors there
+    // report them as what was expected instead.
+    expected: Vec<TokenType>,
+    expected_at: Option<usize>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(src: &'a str) -> Self {
         Self {
+            src,
             tokens: TokenStream::new(src),
             statements: Vec::with_capacity(1024),
             errors: Vec::with_capacity(1024),
+            warnings: Vec::new(),
             loop_cnt: 0,
             fn_cnt: 0,
+            allow_bare_expression: false,
+            implicit_return: false,
+            print_errors: true,
+            expected: Vec::new(),
+            expected_at: None,
         }
     }
 
+    /// accept input ending in an expression without a semicolon, like `1 + 2`, as if it had one.
+    /// every other statement still needs its terminator.
+    pub fn with_bare_expression(mut self, allow: bool) -> Self {
+        self.allow_bare_expression = allow;
+        self
+    }
+
+    /// collect errors without printing them as they're found, for hosts that report them their own
+    /// way.
+    pub fn with_printed_errors(mut self, print: bool) -> Self {
+        self.print_errors = print;
+        self
+    }
+
     pub fn parse(&mut self) {
         while !self.take_done() {
             match self.declaration() {
                 Ok(stmt) => self.statements.push(stmt),
                 Err(e) => {
-                    println!("{}", e);
-                    self.errors.push(e);
-                    self.recover();
+                    self.report(e);
+                    // there's no block for a stray `}` to close out here, so skip past it.
+                    let _ = self.match_one(TokenType::RightBrace);
                 }
             }
         }
     }
 
+    fn report(&mut self, e: ParseError) {
+        if self.print_errors {
+            println!("{}", e);
+            e.print_code_block(self.src);
+        }
+        self.errors.push(e);
+        self.recover();
+    }
+
     pub fn had_errors(&self) -> bool {
-        self.errors.len() > 0
+        !self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors[..]
+    }
+
+    /// likely mistakes found in a program that otherwise parsed, see `ParseWarning`.
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings[..]
+    }
+
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        std::mem::take(&mut self.errors)
     }
 
     pub fn take_statements(self) -> Vec<Stmt> {
@@ -128,11 +229,27 @@ impl<'a> Parser<'a> {
             return self.var_declaration();
         }
 
-        if self.match_one(TokenType::Class).is_some() {
+        // `fun` and `class` only declare something when a name follows, otherwise they start an
+        // anonymous function or class expression like `fun (x) { ... }(1);`.
+        if self.next_two_are(TokenType::Fun, TokenType::Identifier) {
+            let fun = self.tokens.next()?;
+            let name = self.expect("function name", TokenType::Identifier)?;
+            let name = Identifier::try_from(name)?;
+            let value = self.function_tail(Some(name.clone()), Some(fun.position), false)?;
+            return Ok(desugar_function_statement(name, value));
+        }
+
+        if self.next_two_are(TokenType::Class, TokenType::Identifier) {
+            let _ = self.tokens.next()?;
             return self.class_declaration();
         }
 
-        return self.statement();
+        self.statement()
+    }
+
+    fn next_two_are(&mut self, first: TokenType, second: TokenType) -> bool {
+        matches!(self.tokens.peek(), Some(Ok(t)) if t.token_type == first)
+            && matches!(self.tokens.peek2(), Some(Ok(t)) if t.token_type == second)
     }
 
     fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
@@ -141,6 +258,10 @@ impl<'a> Parser<'a> {
             TokenType::Identifier,
         )?;
 
+        if self.match_one(TokenType::Comma).is_some() {
+            return self.destructure_declaration(name);
+        }
+
         let initializer = if self.match_one(TokenType::Equal).is_some() {
             Some(self.expression()?)
         } else {
@@ -155,37 +276,128 @@ impl<'a> Parser<'a> {
         })
     }
 
+    // `var a, b = pair();` binds each name to the element of the array at the same position.
+    fn destructure_declaration(&mut self, first: Token) -> Result<Stmt, ParseError> {
+        let position = first.position;
+        let mut names = vec![Identifier::try_from(first)?];
+        loop {
+            let name = self.expect(
+                "var delcaration requires an identifier",
+                TokenType::Identifier,
+            )?;
+            names.push(name.try_into()?);
+            if self.match_one(TokenType::Comma).is_none() {
+                break;
+            }
+        }
+        self.expect(
+            "destructuring var statement requires an initializer",
+            TokenType::Equal,
+        )?;
+        let initializer = self.expression()?;
+        self.expect("unterminated var statement", TokenType::Semicolon)?;
+        Ok(Stmt::Destructure {
+            names,
+            initializer,
+            position,
+        })
+    }
+
     fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
         let class_name = self.expect(
             "class delcaration requires an identifier",
             TokenType::Identifier,
         )?;
+        let position = class_name.position;
+        let value = self.class_body(Some(class_name.try_into()?), position)?;
+        Ok(Stmt::Class { value })
+    }
+
+    fn class_expression(&mut self, marker_location: usize) -> Result<Expr, ParseError> {
+        // much like functions, a class used as an expression doesn't need a name.
+        let name = if let Some(t) = self.match_one(TokenType::Identifier) {
+            Some(Identifier::try_from(t)?)
+        } else {
+            None
+        };
+        Ok(Expr::Class {
+            value: self.class_body(name, marker_location)?,
+        })
+    }
+
+    fn class_body(
+        &mut self,
+        name: Option<Identifier>,
+        marker_location: usize,
+    ) -> Result<Class, ParseError> {
+        // `class Name < Base { ... }` inherits from `Base`.
+        let superclass = if self.match_one(TokenType::Less).is_some() {
+            let base = self.expect("superclass must be named", TokenType::Identifier)?;
+            Some(Identifier::try_from(base)?)
+        } else {
+            None
+        };
         self.expect("class statement left brace", TokenType::LeftBrace)?;
         let mut methods = Vec::new();
+        let mut fields = Vec::new();
         while let Some(t) = self.tokens.peek() {
             if t.is_err() || t.unwrap().token_type == TokenType::RightBrace {
                 break;
             }
             let is_static = self.match_one(TokenType::Static).is_some();
-            let func = self.function(None, is_static)?;
-            if func.is_anonymous() {
-                return Err(ParseError::InvalidClassMethod {
-                    location: func.position(),
-                });
+            if let Some(var) = self.match_one(TokenType::Var) {
+                fields.push(self.var_field(var.position, is_static)?);
+                continue;
+            }
+            let member = self.function_name()?;
+            match member {
+                // `NAME = value;` declares a field given to every instance,
+                // or one stored on the class itself when it is static.
+                Some(field) if self.match_one(TokenType::Equal).is_some() => {
+                    let value = self.expression()?;
+                    self.expect("unterminated class field", TokenType::Semicolon)?;
+                    fields.push(Field::new(field, value, is_static));
+                }
+                _ => {
+                    let func = self.method(member, is_static)?;
+                    if func.is_anonymous() {
+                        return Err(ParseError::InvalidClassMethod {
+                            location: func.position(),
+                        });
+                    }
+                    methods.push(func);
+                }
             }
-            methods.push(func);
         }
         self.expect("class statement right brace", TokenType::RightBrace)?;
-        Ok(Stmt::Class {
-            name: class_name.try_into()?,
-            methods,
-        })
+        Ok(Class::new(name, methods, fields, marker_location).with_superclass(superclass))
+    }
+
+    // `var NAME (= value)?;` is the same as a `NAME = value;` field, except the value is optional
+    // and defaults to nil like it would for a variable.
+    fn var_field(&mut self, marker_location: usize, is_static: bool) -> Result<Field, ParseError> {
+        let name = self.expect("class field must be named", TokenType::Identifier)?;
+        let name = Identifier::try_from(name)?;
+        let value = if self.match_one(TokenType::Equal).is_some() {
+            self.expression()?
+        } else {
+            Expr::Literal {
+                value: Literal::new_nil(Span::at(marker_location)),
+            }
+        };
+        self.expect("unterminated class field", TokenType::Semicolon)?;
+        Ok(Field::new(name, value, is_static))
     }
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
-        if self.match_one(TokenType::Print).is_some() {
-            return self.print_statement();
+        // only this statement can be the implicit return, not any nested inside it.
+        let implicit_return = std::mem::take(&mut self.implicit_return);
+        if let Some(print) = self.match_one(TokenType::Print) {
+            return self.print_statement(print.position);
         }
+        // a statement starting with `{` is always a block, even `{}`. map literals are only
+        // recognised where an expression is expected, see `primary`, so a map on its own line has
+        // to be wrapped as in `({});`.
         if self.match_one(TokenType::LeftBrace).is_some() {
             return self.block_statement();
         }
@@ -207,7 +419,7 @@ impl<'a> Parser<'a> {
         if self.match_one(TokenType::Return).is_some() {
             return self.return_statement();
         }
-        self.expression_statement()
+        self.expression_statement(implicit_return)
     }
 
     fn for_statement(&mut self) -> Result<Stmt, ParseError> {
@@ -219,13 +431,13 @@ impl<'a> Parser<'a> {
         } else if self.match_one(TokenType::Var).is_some() {
             Some(self.var_declaration()?)
         } else {
-            Some(self.expression_statement()?)
+            Some(self.expression_statement(false)?)
         };
 
         let condition = if self.match_one(TokenType::Semicolon).is_some() {
             None
         } else {
-            let expr = self.expression()?;
+            let expr = self.condition()?;
             self.expect("for statement semicolon", TokenType::Semicolon)?;
             Some(expr)
         };
@@ -245,7 +457,7 @@ impl<'a> Parser<'a> {
     fn while_statement(&mut self) -> Result<Stmt, ParseError> {
         self.enter_loop();
         self.expect("while statement left parens", TokenType::LeftParen)?;
-        let condition = self.expression()?;
+        let condition = self.condition()?;
         self.expect("while statement right parens", TokenType::RightParen)?;
         let block = Box::new(self.statement()?);
         self.exit_loop();
@@ -254,7 +466,7 @@ impl<'a> Parser<'a> {
 
     fn if_statement(&mut self) -> Result<Stmt, ParseError> {
         self.expect("if statement left parens", TokenType::LeftParen)?;
-        let condition = self.expression()?;
+        let condition = self.condition()?;
         self.expect("if statement right parens", TokenType::RightParen)?;
 
         let if_block = Box::new(self.statement()?);
@@ -272,6 +484,23 @@ impl<'a> Parser<'a> {
         })
     }
 
+    // the condition of an `if`, `while` or `for`. `if (x = 1)` is far more likely to be a typo for
+    // `==` than on purpose, so it gets a warning, which `if ((x = 1))` can be used to avoid.
+    fn condition(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.expression()?;
+        let span = match &expr {
+            Expr::Assignment { name, .. } => Some(name.span()),
+            Expr::Set { property, .. } => Some(property.span()),
+            Expr::IndexSet { position, .. } => Some(Span::at(*position)),
+            _ => None,
+        };
+        if let Some(span) = span {
+            self.warnings
+                .push(ParseWarning::AssignmentInCondition { span });
+        }
+        Ok(expr)
+    }
+
     fn break_statement(&mut self) -> Result<Stmt, ParseError> {
         let keyword = self.tokens.last().unwrap();
         if !self.is_in_loop() {
@@ -292,8 +521,8 @@ impl<'a> Parser<'a> {
                 location: keyword.position,
             });
         }
-        self.expect("unterminated break statement", TokenType::Semicolon)?;
-        Ok(Stmt::Break)
+        self.expect("unterminated continue statement", TokenType::Semicolon)?;
+        Ok(Stmt::Continue)
     }
 
     fn return_statement(&mut self) -> Result<Stmt, ParseError> {
@@ -304,7 +533,7 @@ impl<'a> Parser<'a> {
             });
         }
 
-        if let Some(_) = self.match_one(TokenType::Semicolon) {
+        if self.match_one(TokenType::Semicolon).is_some() {
             return Ok(Stmt::Return { value: None });
         }
 
@@ -324,38 +553,69 @@ impl<'a> Parser<'a> {
     }
 
     fn block_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.block(false)
+    }
+
+    // the statements up to the closing `}`. in a function body the last of them can be an
+    // expression without a semicolon, as in `fun double(x) { x * 2 }`, which the function returns.
+    fn block(&mut self, is_fn_body: bool) -> Result<Stmt, ParseError> {
         let not_terminated = |t: &'_ Token<'_>| {
             t.token_type != TokenType::RightBrace && t.token_type != TokenType::Eof
         };
         let mut statements = Vec::new();
-        while let Some(_) = self.tokens.peek_next_if(not_terminated)? {
-            statements.push(self.declaration()?);
+        while self.tokens.peek_next_if(not_terminated)?.is_some() {
+            // an error is reported and skipped over here so the rest of the block still parses,
+            // instead of unwinding out of it and leaving its `}` to be misread as a stray one.
+            self.implicit_return = is_fn_body;
+            let stmt = self.declaration();
+            self.implicit_return = false;
+            match stmt {
+                Ok(stmt) => statements.push(stmt),
+                Err(e) => self.report(e),
+            }
         }
         self.expect("unclosed block scope", TokenType::RightBrace)?;
         Ok(Stmt::Block { statements })
     }
 
-    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
-        let expr = self.expression()?;
-        self.expect("unterminated print statement", TokenType::Semicolon)?;
-        Ok(Stmt::Print { expr })
+    fn print_statement(&mut self, start: usize) -> Result<Stmt, ParseError> {
+        let mut exprs = Vec::new();
+        if let Some(semicolon) = self.match_one(TokenType::Semicolon) {
+            let span = Span::new(start, semicolon.span().end);
+            return Ok(Stmt::Print { exprs, span });
+        }
+        loop {
+            exprs.push(self.expression()?);
+            if self.match_one(TokenType::Comma).is_none() {
+                break;
+            }
+        }
+        let semicolon = self.expect("unterminated print statement", TokenType::Semicolon)?;
+        let span = Span::new(start, semicolon.span().end);
+        Ok(Stmt::Print { exprs, span })
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn expression_statement(&mut self, implicit_return: bool) -> Result<Stmt, ParseError> {
         let expr = self.expression()?;
-        match expr {
-            Expr::Function { value } => Ok(desugar_function_statement(value)),
-            other => {
-                self.expect("unterminated expression statement", TokenType::Semicolon)?;
-                Ok(Stmt::Expression { expr: other })
-            }
+        if self.allow_bare_expression && self.take_done() {
+            return Ok(Stmt::Expression { expr });
+        }
+        let at_end =
+            matches!(self.tokens.peek(), Some(Ok(t)) if t.token_type == TokenType::RightBrace);
+        if implicit_return && at_end {
+            return Ok(Stmt::Return { value: Some(expr) });
         }
+        self.expect("unterminated expression statement", TokenType::Semicolon)?;
+        Ok(Stmt::Expression { expr })
     }
 
     fn expression(&mut self) -> Result<Expr, ParseError> {
         self.assignment()
     }
 
+    // assignment is right associative, `a = obj.x = 5` parses as `a = (obj.x = 5)`. the targets
+    // are worked out left to right, so `obj` is evaluated before `5`, and each assignment hands the
+    // value it stored on to the one outside it.
     fn assignment(&mut self) -> Result<Expr, ParseError> {
         let expr = self.logical_or()?;
         if let Some(eq) = self.match_one(TokenType::Equal) {
@@ -367,6 +627,16 @@ impl<'a> Parser<'a> {
                     property,
                     value,
                 }),
+                Expr::Index {
+                    object,
+                    index,
+                    position,
+                } => Ok(Expr::IndexSet {
+                    object,
+                    index,
+                    value,
+                    position,
+                }),
                 _ => Err(ParseError::UnexpectedAssignment {
                     type_str: expr.type_str().to_string(),
                     location: eq.position,
@@ -403,7 +673,7 @@ impl<'a> Parser<'a> {
                 right: Box::new(rhs),
             }
         }
-        return Ok(lhs);
+        Ok(lhs)
     }
 
     fn logical_and(&mut self) -> Result<Expr, ParseError> {
@@ -416,7 +686,7 @@ impl<'a> Parser<'a> {
                 right: Box::new(rhs),
             }
         }
-        return Ok(lhs);
+        Ok(lhs)
     }
 
     fn equality(&mut self) -> Result<Expr, ParseError> {
@@ -482,14 +752,31 @@ impl<'a> Parser<'a> {
     }
 
     fn unary(&mut self) -> Result<Expr, ParseError> {
+        // a leading `--x` is a double negation, there's no prefix decrement.
+        self.tokens.split_minus_minus();
         if let Some(op) = self.match_many(&[TokenType::Bang, TokenType::Minus]) {
             Ok(Expr::Unary {
                 prefix: op.try_into()?,
                 value: Box::new(self.unary()?),
             })
         } else {
-            self.call()
+            self.power()
+        }
+    }
+
+    // power binds tighter than a prefix on its left, so `-2 ** 2` is `-(2 ** 2)`,
+    // and is right associative, so `2 ** 3 ** 2` is `2 ** (3 ** 2)`.
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.call()?;
+        if let Some(op) = self.match_one(TokenType::StarStar) {
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary {
+                left: Box::new(base),
+                op: op.try_into()?,
+                right: Box::new(exponent),
+            });
         }
+        Ok(base)
     }
 
     fn call(&mut self) -> Result<Expr, ParseError> {
@@ -502,16 +789,44 @@ impl<'a> Parser<'a> {
                 Ok(t) if t.token_type == TokenType::Dot => {
                     expr = self.handle_dot_access(expr)?;
                 }
+                Ok(t) if t.token_type == TokenType::LeftBracket => {
+                    expr = self.handle_index(expr)?;
+                }
                 Ok(_) => break,
                 Err(e) => return Err(e),
             }
         }
+        // `a--b` is `a - -b`, `--` only decrements when no operand follows it.
+        if self.minus_minus_is_negation() {
+            self.tokens.split_minus_minus();
+            return Ok(expr);
+        }
+        if let Some(op) = self.match_many(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            return match expr {
+                Expr::Variable { value: name } => Ok(Expr::Postfix {
+                    name,
+                    op: op.try_into()?,
+                }),
+                _ => Err(ParseError::UnexpectedAssignment {
+                    type_str: expr.type_str().to_string(),
+                    location: op.position,
+                }),
+            };
+        }
         Ok(expr)
     }
 
+    fn minus_minus_is_negation(&mut self) -> bool {
+        if !matches!(self.tokens.peek(), Some(Ok(toke)) if toke.token_type == TokenType::MinusMinus)
+        {
+            return false;
+        }
+        matches!(self.tokens.peek2(), Some(Ok(toke)) if starts_operand(toke.token_type))
+    }
+
     fn handle_call(&mut self, expr: Expr) -> Result<Expr, ParseError> {
         let paren = self.tokens.next()?;
-        let args = self.arguments()?;
+        let (args, end) = self.arguments()?;
         if args.len() > MAX_FUNC_ARGS {
             return Err(ParseError::FuncExceedMaxArgs {
                 max: MAX_FUNC_ARGS,
@@ -519,11 +834,22 @@ impl<'a> Parser<'a> {
             });
         }
         Ok(Expr::Call {
-            callee: Callee::new(expr, paren.position),
+            callee: Callee::new(expr, paren.position, end),
             args,
         })
     }
 
+    fn handle_index(&mut self, expr: Expr) -> Result<Expr, ParseError> {
+        let bracket = self.tokens.next()?;
+        let index = self.expression()?;
+        self.expect("index did not terminate", TokenType::RightBracket)?;
+        Ok(Expr::Index {
+            object: Box::new(expr),
+            index: Box::new(index),
+            position: bracket.position,
+        })
+    }
+
     fn handle_dot_access(&mut self, expr: Expr) -> Result<Expr, ParseError> {
         let _dot = self.tokens.next()?;
         let name = self.expect("dot access missing identifier", TokenType::Identifier)?;
@@ -533,17 +859,18 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+    // the arguments of a call, and where the call ends just past its closing paren.
+    fn arguments(&mut self) -> Result<(Vec<Expr>, usize), ParseError> {
         let mut args = Vec::with_capacity(MAX_FUNC_ARGS);
-        if self.match_one(TokenType::RightParen).is_some() {
-            return Ok(args);
+        if let Some(paren) = self.match_one(TokenType::RightParen) {
+            return Ok((args, paren.span().end));
         }
         args.push(self.expression()?);
         while self.match_one(TokenType::Comma).is_some() {
             args.push(self.expression()?);
         }
-        self.expect("function call did not terminate", TokenType::RightParen)?;
-        Ok(args)
+        let paren = self.expect("function call did not terminate", TokenType::RightParen)?;
+        Ok((args, paren.span().end))
     }
 
     fn parameters(&mut self) -> Result<Vec<Identifier>, ParseError> {
@@ -568,14 +895,15 @@ impl<'a> Parser<'a> {
     }
 
     fn primary(&mut self) -> Result<Expr, ParseError> {
-        if self.match_one(TokenType::LeftParen).is_some() {
+        if let Some(open) = self.match_one(TokenType::LeftParen) {
             let expr = self.expression()?;
-            let _ = self.expect(
+            let close = self.expect(
                 "primary grouping did not terminate correctly",
                 TokenType::RightParen,
             )?;
             return Ok(Expr::Grouping {
                 expr: Box::new(expr),
+                span: Span::new(open.position, close.span().end),
             });
         }
 
@@ -583,6 +911,19 @@ impl<'a> Parser<'a> {
             return self.fun_expression(fun.position);
         }
 
+        if let Some(slash) = self.match_one(TokenType::Backslash) {
+            return self.lambda(slash.position);
+        }
+
+        // statements starting with a brace are blocks, so one showing up here must be a map.
+        if let Some(brace) = self.match_one(TokenType::LeftBrace) {
+            return self.map_literal(brace.position);
+        }
+
+        if let Some(class) = self.match_one(TokenType::Class) {
+            return self.class_expression(class.position);
+        }
+
         if let Some(name) = self.match_one(TokenType::Identifier) {
             return Ok(Expr::Variable {
                 value: name.try_into()?,
@@ -595,41 +936,142 @@ impl<'a> Parser<'a> {
             });
         }
 
+        if let Some(keyword) = self.match_one(TokenType::Super) {
+            self.expect("super must be followed by a '.'", TokenType::Dot)?;
+            let property = self.expect("super property must be named", TokenType::Identifier)?;
+            return Ok(Expr::Super {
+                this: Identifier::this_at(keyword.position),
+                keyword: keyword.try_into()?,
+                property: property.try_into()?,
+            });
+        }
+
+        const LITERALS: [TokenType; 5] = [
+            TokenType::Number,
+            TokenType::String,
+            TokenType::True,
+            TokenType::False,
+            TokenType::Nil,
+        ];
+        LITERALS.into_iter().for_each(|t| self.note_expected(t));
         let next_tok = self.tokens.next()?;
+        if !LITERALS.contains(&next_tok.token_type) {
+            return Err(ParseError::ExpectedExpression {
+                recieved: next_tok.to_string(),
+                span: next_tok.span(),
+                candidates: self.expected_at(next_tok.position),
+            });
+        }
         let value = next_tok.try_into()?;
         Ok(Expr::Literal { value })
     }
 
+    fn map_literal(&mut self, position: usize) -> Result<Expr, ParseError> {
+        let mut entries = Vec::new();
+        while self.match_one(TokenType::RightBrace).is_none() {
+            let key = self.expression()?;
+            self.expect("map key must be followed by a colon", TokenType::Colon)?;
+            let value = self.expression()?;
+            entries.push((key, value));
+            // entries are comma separated, a trailing comma is allowed.
+            if self.match_one(TokenType::Comma).is_none() {
+                self.expect("map literal did not terminate", TokenType::RightBrace)?;
+                break;
+            }
+        }
+        Ok(Expr::Map { entries, position })
+    }
+
     fn fun_expression(&mut self, marker_location: usize) -> Result<Expr, ParseError> {
         Ok(Expr::Function {
             value: self.function(Some(marker_location), false)?,
         })
     }
 
+    // `\(a, b) => a + b` is shorthand for `fun (a, b) { return a + b; }`.
+    fn lambda(&mut self, marker_location: usize) -> Result<Expr, ParseError> {
+        self.expect("lambda must open its parameters", TokenType::LeftParen)?;
+        let params = self.parameters()?;
+        self.expect(
+            "lambda parameters must be followed by '=>'",
+            TokenType::Arrow,
+        )?;
+        self.enter_fn();
+        let body = self.expression();
+        self.exit_fn();
+        let body = Stmt::Block {
+            statements: vec![Stmt::Return { value: Some(body?) }],
+        };
+        Ok(Expr::Function {
+            value: Function::new(None, params, Rc::new(body), marker_location, false),
+        })
+    }
+
     fn function(
         &mut self,
         marker_location: Option<usize>,
         is_static: bool,
     ) -> Result<Function, ParseError> {
         // if the function is anonymous then there will be no identifier after it.
-        let name = if let Some(t) = self.match_one(TokenType::Identifier) {
-            Some(Identifier::try_from(t)?)
-        } else {
-            None
-        };
-        // regardless of the above point, it must be followed by some params
+        let name = self.function_name()?;
+        self.function_tail(name, marker_location, is_static)
+    }
+
+    fn method(
+        &mut self,
+        name: Option<Identifier>,
+        is_static: bool,
+    ) -> Result<Function, ParseError> {
+        // a method without a parameter list, e.g. `area { ... }`, is a getter.
+        let is_getter =
+            matches!(self.tokens.peek(), Some(Ok(t)) if t.token_type == TokenType::LeftBrace);
+        match name {
+            Some(ident) if is_getter => {
+                let position = ident.position();
+                let getter = self.function_body(Some(ident), Vec::new(), position, is_static)?;
+                Ok(getter.as_getter())
+            }
+            _ => self.function_tail(name, None, is_static),
+        }
+    }
+
+    fn function_name(&mut self) -> Result<Option<Identifier>, ParseError> {
+        self.match_one(TokenType::Identifier)
+            .map(Identifier::try_from)
+            .transpose()
+            .map_err(ParseError::from)
+    }
+
+    fn function_tail(
+        &mut self,
+        name: Option<Identifier>,
+        marker_location: Option<usize>,
+        is_static: bool,
+    ) -> Result<Function, ParseError> {
+        // regardless of whether it has a name, it must be followed by some params
         let begin_args = self.expect("function dec must open", TokenType::LeftParen)?;
         let params = self.parameters()?;
+        // if the caller didn't already have a place to point
+        // diagnostics, then we should default to whereever the args began.
+        let marker_location = marker_location.unwrap_or(begin_args.position);
+        self.function_body(name, params, marker_location, is_static)
+    }
+
+    fn function_body(
+        &mut self,
+        name: Option<Identifier>,
+        params: Vec<Identifier>,
+        marker_location: usize,
+        is_static: bool,
+    ) -> Result<Function, ParseError> {
         // functions are required to be followed by a block scope, so we force this by doing a little look-ahead.
         let _ = self.expect("function must open to block scope", TokenType::LeftBrace)?;
         self.enter_fn();
         let ret = Function::new(
             name,
             params,
-            Rc::new(self.block_statement()?),
-            // if the caller didn't already have a place to point
-            // diagnostics, then we should default to whereever the args began.
-            marker_location.unwrap_or(begin_args.position),
+            Rc::new(self.block(true)?),
+            marker_location,
             is_static,
         );
         self.exit_fn();
@@ -637,30 +1079,63 @@ impl<'a> Parser<'a> {
     }
 
     fn match_one(&mut self, t: TokenType) -> Option<Token<'a>> {
-        self.tokens.next_if(|toke| toke.token_type == t)
+        let matched = self.tokens.next_if(|toke| toke.token_type == t);
+        if matched.is_none() {
+            self.note_expected(t);
+        }
+        matched
+    }
+
+    // remember that `t` would have been accepted as the next token.
+    fn note_expected(&mut self, t: TokenType) {
+        let Some(Ok(next)) = self.tokens.peek() else {
+            return;
+        };
+        let at = next.position;
+        if self.expected_at != Some(at) {
+            self.expected.clear();
+            self.expected_at = Some(at);
+        }
+        if !self.expected.contains(&t) {
+            self.expected.push(t);
+        }
+    }
+
+    // the tokens that would have been accepted in place of the token at `position`.
+    fn expected_at(&self, position: usize) -> Vec<TokenType> {
+        match self.expected_at {
+            Some(at) if at == position => self.expected.clone(),
+            _ => Vec::new(),
+        }
     }
 
     fn match_many(&mut self, ts: &[TokenType]) -> Option<Token<'a>> {
         for t in ts {
-            match self.match_one(*t) {
-                Some(t) => return Some(t),
-                _ => {}
+            if let Some(t) = self.match_one(*t) {
+                return Some(t);
             }
         }
         None
     }
 
     fn expect(&mut self, msg: &'static str, t: TokenType) -> Result<Token<'a>, ParseError> {
-        let toke = self.tokens.next()?;
-        if toke.token_type != t {
-            Err(ParseError::UnexpectedToken {
-                expected: t,
-                recieved: toke.to_string(),
-                msg,
-            })
-        } else {
-            Ok(toke)
+        self.note_expected(t);
+        if let Some(toke) = self.tokens.next_if(|toke| toke.token_type == t) {
+            return Ok(toke);
         }
+        // a token that doesn't match is left in place, it may be the `}` or keyword recovery stops at.
+        let toke = match self.tokens.peek() {
+            Some(Ok(toke)) => toke.clone(),
+            Some(Err(e)) => return Err(e),
+            None => return Err(ParseError::UnexpectedEof),
+        };
+        Err(ParseError::UnexpectedToken {
+            expected: t,
+            recieved: toke.to_string(),
+            msg,
+            span: toke.span(),
+            candidates: self.expected_at(toke.position),
+        })
     }
 
     fn take_done(&mut self) -> bool {
@@ -697,7 +1172,9 @@ impl<'a> Parser<'a> {
         self.fn_cnt -= 1;
     }
 
-    /// recover from a panic state by reading through until we hit the end of the stream, or alternatively a semi-colon terminator.
+    /// recover from a panic state by reading through until we hit the end of the stream, a semi-colon
+    /// terminator, or a token that begins a new statement or closes a block. only the semi-colon is
+    /// consumed, so the next statement starts from its first token.
     fn recover(&mut self) {
         while let Some(result) = self.tokens.peek() {
             match result {
@@ -705,7 +1182,7 @@ impl<'a> Parser<'a> {
                     let _ = self.tokens.next();
                     break;
                 }
-                Ok(toke) if toke.token_type == TokenType::Eof => {
+                Ok(toke) if starts_statement(toke.token_type) => {
                     break;
                 }
                 _ => {
@@ -716,6 +1193,42 @@ impl<'a> Parser<'a> {
     }
 }
 
+// tokens that begin a value on their own. a prefix `-` or `!` isn't counted, so `a-- - b`
+// decrements `a` like it would in C.
+fn starts_operand(t: TokenType) -> bool {
+    matches!(
+        t,
+        TokenType::Identifier
+            | TokenType::Number
+            | TokenType::String
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::This
+            | TokenType::Super
+            | TokenType::Fun
+            | TokenType::LeftParen
+            | TokenType::Backslash
+    )
+}
+
+// tokens that can only start a statement or end a block, so are a safe place to pick parsing back up.
+fn starts_statement(t: TokenType) -> bool {
+    matches!(
+        t,
+        TokenType::Class
+            | TokenType::Fun
+            | TokenType::Var
+            | TokenType::For
+            | TokenType::If
+            | TokenType::While
+            | TokenType::Return
+            | TokenType::Print
+            | TokenType::RightBrace
+            | TokenType::Eof
+    )
+}
+
 fn desugar_op_assignment(name: Identifier, op: Token<'_>, rhs: Expr) -> Result<Expr, ParseError> {
     let location = op.position;
     let op = match op.token_type {
@@ -725,14 +1238,18 @@ fn desugar_op_assignment(name: Identifier, op: Token<'_>, rhs: Expr) -> Result<E
         TokenType::SlashEqual => BinaryOperator::Slash(location),
         _ => unreachable!("desugar should already be confirmed to be of a discrete set."),
     };
-    Ok(Expr::Assignment {
+    Ok(desugar_assignment(name, op, rhs))
+}
+
+fn desugar_assignment(name: Identifier, op: BinaryOperator, rhs: Expr) -> Expr {
+    Expr::Assignment {
         name: name.clone(),
         value: Box::new(Expr::Binary {
             left: Box::new(Expr::Variable { value: name }),
-            op: op,
+            op,
             right: Box::new(rhs),
         }),
-    })
+    }
 }
 
 fn desugar_for_statement(
@@ -741,56 +1258,76 @@ fn desugar_for_statement(
     increment: Option<Expr>,
     body: Stmt,
 ) -> Result<Stmt, ParseError> {
-    let mut inner_block = vec![body];
-    if let Some(inc) = increment {
-        inner_block.push(make_expression_statment(inc))
-    }
     let mut outer_block = vec![];
     if let Some(init) = initializer {
         outer_block.push(init);
     }
-    let cond = condition.unwrap_or(make_true_expression());
-    let while_stmt = make_while_statement(cond, inner_block);
-    outer_block.push(while_stmt);
+    // the increment stays separate from the body so the interpreter can give each iteration
+    // a fresh copy of the loop variables before it runs.
+    outer_block.push(Stmt::For {
+        condition: condition.unwrap_or(make_true_expression()),
+        increment,
+        body: Box::new(body),
+    });
     Ok(Stmt::Block {
         statements: outer_block,
     })
 }
 
-fn desugar_function_statement(value: Function) -> Stmt {
-    if let Some(name) = value.name() {
-        return Stmt::Var {
-            name: name,
-            initializer: Some(Expr::Function { value }),
-        };
-    } else {
-        return Stmt::Expression {
-            expr: Expr::Function { value },
-        };
+fn desugar_function_statement(name: Identifier, value: Function) -> Stmt {
+    Stmt::Var {
+        name,
+        initializer: Some(Expr::Function { value }),
     }
 }
 
-fn make_expression_statment(expr: Expr) -> Stmt {
-    Stmt::Expression { expr }
-}
-
-fn make_while_statement(condition: Expr, stmts: Vec<Stmt>) -> Stmt {
-    Stmt::While {
-        condition,
-        block: Box::new(make_block_statement(stmts)),
+fn make_true_expression() -> Expr {
+    // it is okay to make up the "location" here because it is synthetic and can never fail at runtime reasonably.
+    Expr::Literal {
+        value: Literal::new_boolean(true, Span::default()),
     }
 }
 
-fn make_block_statement(stmts: Vec<Stmt>) -> Stmt {
-    Stmt::Block { statements: stmts }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_brace_is_block_in_statement_position() {
+        let parse = |src: &str| {
+            let mut parser = Parser::new(src).with_bare_expression(true);
+            parser.parse();
+            assert!(!parser.had_errors(), "{src} should parse");
+            parser.take_statements()
+        };
+        for (src, expected) in [
+            ("{}", "block"),
+            ("{ a; }", "block"),
+            ("({});", "map"),
+            ("({ \"a\": 1 });", "map"),
+            ("({})", "map"),
+        ] {
+            let stmts = parse(src);
+            let kind = match &stmts[0] {
+                Stmt::Expression { expr } => match expr {
+                    Expr::Grouping { expr, .. } => expr.type_str(),
+                    other => other.type_str(),
+                },
+                other => other.type_str(),
+            };
+            assert_eq!(kind, expected, "{src}");
+        }
+        let Stmt::Var {
+            initializer: Some(init),
+            ..
+        } = &parse("var m = {};")[0]
+        else {
+            panic!("expected a var declaration");
+        };
+        assert_eq!(init.type_str(), "map");
 
-fn make_true_expression() -> Expr {
-    // it is okay to make up the "location" here because it is synthetic and can never fail at runtime reasonably.
-    Expr::Literal {
-        value: Literal::Boolean {
-            value: true,
-            position: 0,
-        },
+        let mut parser = Parser::new("{ \"a\": 1 }");
+        parser.parse();
+        assert!(parser.had_errors(), "a map can't start a statement");
     }
 }
@@ -1,32 +1,133 @@
+use crate::lang::tokenizer::span::Span;
 use crate::lang::tree::ast::*;
+use crate::lang::tree::error::ResolveError;
 use crate::lang::visitor::Visitor;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Default)]
+struct Scope {
+    /// variable name → (slot index in the frame, is_defined?)
+    names: HashMap<String, (usize, bool)>,
+    /// The slot the first variable declared here gets. Blocks carry on numbering from the block
+    /// they are in, functions start again from 0.
+    first_slot: usize,
+}
+
+impl Scope {
+    fn next_slot(&self) -> usize {
+        self.first_slot + self.names.len()
+    }
+}
+
+#[derive(Debug)]
+struct FunctionScope {
+    /// Index into `scopes` of the function's first scope. Anything resolved below it is captured
+    /// from an enclosing function.
+    scope_start: usize,
+    /// What the function captures, in the order of its upvalues.
+    captures: Vec<Capture>,
+}
 
 enum FuncType {
     Method,
+    StaticMethod,
     Function,
 }
 
 /// A Resolver walks your AST **before** runtime and:
-/// 1. Assigns each variable use a (depth, slot) pair.
-/// 2. Detects reads in their own initializer.
+/// 1. Assigns each variable use a slot in its function's frame, or an upvalue when it belongs to
+///    an enclosing function, and records what every function captures.
+/// 2. Detects reads in their own initializer, unless they read a name being shadowed.
 /// 3. Errors on duplicate declarations in the same scope.
+/// 4. Marks everything else as global, optionally erroring on globals that are never declared.
 #[derive(Debug)]
 pub struct Resolver {
-    /// Stack of scopes. Each scope maps:
-    ///   variable name → (slot index in this frame, is_defined?)
-    scopes: Vec<HashMap<String, (usize, bool)>>,
+    /// Stack of scopes, innermost last.
+    scopes: Vec<Scope>,
+    /// The functions we are inside of, innermost last.
+    functions: Vec<FunctionScope>,
+    /// When set, the globals a program may use without declaring them itself, like natives. Any
+    /// other global it reads or assigns without a top level declaration is an error.
+    known_globals: Option<HashSet<String>>,
+    /// The globals declared at the top level of the program being resolved.
+    declared_globals: HashSet<String>,
+    /// Whether the innermost method we are inside of is static, so has no `this` or `super`.
+    in_static_method: bool,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Resolver {
     /// Create a brand new resolver (no scopes yet).
     pub fn new() -> Self {
-        Resolver { scopes: Vec::new() }
+        Resolver {
+            scopes: Vec::new(),
+            functions: Vec::new(),
+            known_globals: None,
+            declared_globals: HashSet::new(),
+            in_static_method: false,
+        }
+    }
+
+    /// Report globals that are used but never declared, rather than leaving them to fail when
+    /// they run. `names` are the globals that exist before the program does, e.g.
+    /// `Lox::global_names`. A global declared anywhere at the top level of the program can be
+    /// used before its declaration, from inside a function say.
+    pub fn with_known_globals<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.known_globals = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Resolve a whole program. Every identifier visited is rebound, and the resolver starts over
+    /// from the global scope each time, so it is safe to run again over the same statements or to
+    /// reuse after an error left scopes open.
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Result<(), ResolveError> {
+        self.reset();
+        if self.known_globals.is_some() {
+            self.declared_globals = statements.iter().flat_map(declared_names).collect();
+        }
+        let result = statements.iter().try_for_each(|stmt| stmt.accept(self));
+        self.reset();
+        result
+    }
+
+    fn reset(&mut self) {
+        self.scopes.clear();
+        self.functions.clear();
+        self.declared_globals.clear();
+        self.in_static_method = false;
     }
 
     /// Begin a new lexical scope.
     fn begin_scope(&mut self) {
-        self.scopes.push(HashMap::new());
+        let first_slot = self.scopes.last().map_or(0, Scope::next_slot);
+        self.scopes.push(Scope {
+            names: HashMap::new(),
+            first_slot,
+        });
+    }
+
+    /// Begin the outermost scope of a function, whose frame starts out empty.
+    fn begin_function(&mut self) {
+        self.scopes.push(Scope::default());
+        self.functions.push(FunctionScope {
+            scope_start: self.scopes.len() - 1,
+            captures: Vec::new(),
+        });
+    }
+
+    /// End a function's outermost scope, handing back what it captured.
+    fn end_function(&mut self) -> Vec<Capture> {
+        self.scopes.pop();
+        self.functions.pop().map_or_else(Vec::new, |f| f.captures)
     }
 
     /// End the innermost lexical scope.
@@ -36,39 +137,91 @@ impl Resolver {
 
     /// Declare a variable in the current scope.
     /// Returns Err if that name is already declared here.
-    fn declare(&mut self, name: &Identifier) -> Result<(), String> {
+    fn declare(&mut self, name: &Identifier) -> Result<(), ResolveError> {
         if let Some(scope) = self.scopes.last_mut() {
-            if scope.contains_key(name.name_str()) {
+            if scope.names.contains_key(name.name_str()) {
                 // Duplicate var in the same block is an error.
-                return Err(format!(
-                    "Resolver error: {} already declared in this scope",
-                    name
-                ));
+                return Err(ResolveError::AlreadyDeclared {
+                    name: name.name_str().to_string(),
+                    span: name.span(),
+                });
             }
-            // Assign the next available slot (0-based).
-            let slot = scope.len();
+            // Assign the next available slot in the frame.
+            let slot = scope.next_slot();
             // Initially marked "not yet defined" so we catch self-initialization.
-            scope.insert(name.to_string(), (slot, false));
+            scope.names.insert(name.to_string(), (slot, false));
         }
         Ok(())
     }
 
     /// Mark a declared variable as fully initialized.
     fn define(&mut self, name: &Identifier) {
-        let depth = self.scopes.len();
-        if let Some(scope) = self.scopes.last_mut() {
-            if let Some((slot, is_defined)) = scope.get_mut(name.name_str()) {
-                name.swap_depth(depth);
-                name.swap_slot(*slot);
-                *is_defined = true;
+        let Some(scope) = self.scopes.last_mut() else {
+            // declarations outside of any scope live in the globals map.
+            name.set_global_binding();
+            return;
+        };
+        if let Some((slot, is_defined)) = scope.names.get_mut(name.name_str()) {
+            name.set_local_binding(*slot);
+            *is_defined = true;
+        }
+    }
+
+    /// Mark a name that isn't a local as global, checking it exists if asked to.
+    fn bind_global(&self, name: &Identifier) -> Result<(), ResolveError> {
+        name.set_global_binding();
+        let Some(known) = &self.known_globals else {
+            return Ok(());
+        };
+        let name_str = name.name_str();
+        if known.contains(name_str) || self.declared_globals.contains(name_str) {
+            return Ok(());
+        }
+        Err(ResolveError::UndefinedGlobal {
+            name: name_str.to_string(),
+            span: name.span(),
+        })
+    }
+
+    /// Record where a resolved local lives, distinguishing variables of the current
+    /// function from those captured out of an enclosing one.
+    fn bind_local(&mut self, name: &Identifier, depth: usize, slot: usize) {
+        let scope_idx = self.scopes.len() - 1 - depth;
+        match self.functions.last() {
+            Some(function) if scope_idx < function.scope_start => {
+                let index = self.capture(self.functions.len() - 1, scope_idx, slot);
+                name.set_upvalue_binding(index);
+            }
+            _ => name.set_local_binding(slot),
+        }
+    }
+
+    /// The upvalue function `level` reads `slot` of the frame owning `scopes[scope_idx]` through,
+    /// capturing it in every function between the two along the way.
+    fn capture(&mut self, level: usize, scope_idx: usize, slot: usize) -> usize {
+        let enclosing_start = match level {
+            0 => 0,
+            _ => self.functions[level - 1].scope_start,
+        };
+        let capture = if scope_idx >= enclosing_start {
+            Capture::Local(slot)
+        } else {
+            Capture::UpValue(self.capture(level - 1, scope_idx, slot))
+        };
+        let captures = &mut self.functions[level].captures;
+        match captures.iter().position(|c| *c == capture) {
+            Some(index) => index,
+            None => {
+                captures.push(capture);
+                captures.len() - 1
             }
         }
     }
 
     fn put_str(&mut self, name: &str) {
         if let Some(scope) = self.scopes.last_mut() {
-            let slot = scope.len();
-            scope.insert(name.to_string(), (slot, true));
+            let slot = scope.next_slot();
+            scope.names.insert(name.to_string(), (slot, true));
         }
     }
 
@@ -76,32 +229,126 @@ impl Resolver {
     /// Returns `Some((depth, (slot, is_defined)))` or `None` if not found.
     fn resolve_local(&self, name: &str) -> Option<(usize, (usize, bool))> {
         for (depth, scope) in self.scopes.iter().rev().enumerate() {
-            if let Some(&slot_info) = scope.get(name) {
+            if let Some(&slot_info) = scope.names.get(name) {
                 return Some((depth, slot_info));
             }
         }
         None
     }
 
-    fn resolve_function(&mut self, _: FuncType, value: &Function) -> Result<(), String> {
-        // now we begin a scope for local vars.
-        self.begin_scope();
+    // like `resolve_local`, but skipping the innermost scope.
+    fn resolve_shadowed(&self, name: &str) -> Option<(usize, usize)> {
+        self.scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .skip(1)
+            .find_map(|(depth, scope)| scope.names.get(name).map(|&(slot, _)| (depth, slot)))
+    }
+
+    fn resolve_function(&mut self, kind: FuncType, value: &Function) -> Result<(), ResolveError> {
+        let enclosing_static = self.in_static_method;
+        self.in_static_method = match kind {
+            FuncType::Method => false,
+            FuncType::StaticMethod => true,
+            // functions nested in a method can use `this` and `super` as much as the method can.
+            FuncType::Function => enclosing_static,
+        };
+        // now we begin a scope for local vars. methods are called with `this` ahead of their
+        // arguments.
+        self.begin_function();
+        if let FuncType::Method = kind {
+            self.put_str("this");
+        }
         for param in value.params() {
             self.declare(param)?;
             self.define(param);
         }
         value.body().accept(self)?;
-        self.end_scope();
+        value.set_captures(self.end_function());
+        self.in_static_method = enclosing_static;
+        Ok(())
+    }
+
+    // `this` and `super` have no slot to resolve to in a static method.
+    fn check_not_static(&self, keyword: &Identifier) -> Result<(), ResolveError> {
+        if !self.in_static_method {
+            return Ok(());
+        }
+        Err(ResolveError::InStaticMethod {
+            keyword: keyword.name_str().to_string(),
+            span: keyword.span(),
+        })
+    }
+}
+
+// the names a top level statement declares as globals.
+fn declared_names(stmt: &Stmt) -> Vec<String> {
+    match stmt {
+        Stmt::Var { name, .. } => vec![name.name_str().to_string()],
+        Stmt::Destructure { names, .. } => names.iter().map(|n| n.name_str().to_string()).collect(),
+        Stmt::Class { value } => value
+            .name()
+            .map(|name| name.name_str().to_string())
+            .into_iter()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+impl Resolver {
+    fn resolve_class(&mut self, value: &Class) -> Result<(), ResolveError> {
+        if let Some(superclass) = value.superclass() {
+            if value.name().map(|name| name.name_str()) == Some(superclass.name_str()) {
+                return Err(ResolveError::InheritsFromItself {
+                    name: superclass.name_str().to_string(),
+                    span: superclass.span(),
+                });
+            }
+            self.visit_variable(superclass)?;
+        }
+        // static fields are initialized when the class is defined, outside of any method.
+        for field in value.static_fields() {
+            field.value().accept(self)?;
+        }
+        // subclasses declare `super` in a scope of its own around the methods, which capture it
+        // like any other variable.
+        if value.superclass().is_some() {
+            self.begin_scope();
+            self.put_str("super");
+        }
+        // instance fields are initialized with `this` in scope before `init` runs, as though they
+        // were the body of a method.
+        let enclosing_static = std::mem::take(&mut self.in_static_method);
+        self.begin_function();
+        self.put_str("this");
+        for field in value.instance_fields() {
+            field.value().accept(self)?;
+        }
+        value.set_field_captures(self.end_function());
+        self.in_static_method = enclosing_static;
+        // static methods are called without an instance, so have no `this`.
+        for method in value.methods() {
+            let kind = if method.is_static() {
+                FuncType::StaticMethod
+            } else {
+                FuncType::Method
+            };
+            self.resolve_function(kind, method)?;
+        }
+        if value.superclass().is_some() {
+            self.end_scope();
+        }
         Ok(())
     }
 }
 
-impl Visitor<Result<(), String>, Expr, Stmt> for Resolver {
+impl Visitor<Result<(), ResolveError>, Expr, Stmt> for Resolver {
     fn visit_var_statement(
         &mut self,
         ident: &Identifier,
         init: Option<&Expr>,
-    ) -> Result<(), String> {
+    ) -> Result<(), ResolveError> {
         // 1. Declare (adds slot=false). Errors on duplicate.
         self.declare(ident)?;
         // if there is nothing to initalize with, define the var and move on.
@@ -119,67 +366,106 @@ impl Visitor<Result<(), String>, Expr, Stmt> for Resolver {
             Expr::Function { value } if !value.is_anonymous() => {
                 self.define(ident);
                 expr.accept(self)?;
-                return Ok(());
+                Ok(())
             }
             // everything else cannot so only define it AFTER we have visited the intializer;
             _ => {
                 expr.accept(self)?;
                 self.define(ident);
-                return Ok(());
+                Ok(())
             }
         }
     }
 
-    fn visit_variable(&mut self, name: &Identifier) -> Result<(), String> {
+    fn visit_destructure_statement(
+        &mut self,
+        names: &[Identifier],
+        init: &Expr,
+        _: usize,
+    ) -> Result<(), ResolveError> {
+        // the slots are handed out in order, which is the order the interpreter binds them in.
+        for name in names {
+            self.declare(name)?;
+        }
+        init.accept(self)?;
+        for name in names {
+            self.define(name);
+        }
+        Ok(())
+    }
+
+    fn visit_variable(&mut self, name: &Identifier) -> Result<(), ResolveError> {
         // Attempt to resolve a use of `name`.
         if let Some((depth, (slot, is_defined))) = self.resolve_local(name.name_str()) {
-            // If it’s in our current scope (depth==0) but not yet defined, that’s an error.
+            // If it’s in our current scope (depth==0) but not yet defined, the initializer is
+            // reading the local it shadows, as in `fun f(x) { var x = x + 1; }`. When nothing is
+            // being shadowed that’s an error.
             if depth == 0 && !is_defined {
-                return Err(format!(
-                    "Resolver error: cannot read '{}' in its own initializer {}",
-                    name.name_str(),
-                    name.position()
-                ));
+                if let Some((depth, slot)) = self.resolve_shadowed(name.name_str()) {
+                    self.bind_local(name, depth, slot);
+                    return Ok(());
+                }
+                return Err(ResolveError::ReadInOwnInitializer {
+                    name: name.name_str().to_string(),
+                    span: name.span(),
+                });
             }
             // Store the resolved metadata back into the AST node.
-            name.swap_depth(depth);
-            name.swap_slot(slot);
+            self.bind_local(name, depth, slot);
+        } else {
+            // Otherwise it's a global, which the interpreter looks up without checking scopes.
+            self.bind_global(name)?;
         }
-        // Otherwise it's a global—interpreter will handle or error later.
         Ok(())
     }
 
-    fn visit_function(&mut self, value: &Function) -> Result<(), String> {
+    fn visit_function(&mut self, value: &Function) -> Result<(), ResolveError> {
         self.resolve_function(FuncType::Function, value)
     }
 
-    fn visit_assignment(&mut self, name: &Identifier, value: &Expr) -> Result<(), String> {
+    fn visit_assignment(&mut self, name: &Identifier, value: &Expr) -> Result<(), ResolveError> {
         // Resolve the value first.
         value.accept(self)?;
         // now figure out if the target is a local or global var
         if let Some((depth, (slot, _))) = self.resolve_local(name.name_str()) {
             // Store the resolved metadata back into the AST node if it was a local var.
-            name.swap_depth(depth);
-            name.swap_slot(slot);
+            self.bind_local(name, depth, slot);
+        } else {
+            self.bind_global(name)?;
         }
         Ok(())
     }
 
-    fn visit_print_statement(&mut self, expr: &Expr) -> Result<(), String> {
-        expr.accept(self)
+    fn visit_postfix(
+        &mut self,
+        name: &Identifier,
+        _op: PostfixOperator,
+    ) -> Result<(), ResolveError> {
+        // the variable is read before it's written, so it resolves like any other read.
+        self.visit_variable(name)
+    }
+
+    fn visit_print_statement(&mut self, exprs: &[Expr], _span: Span) -> Result<(), ResolveError> {
+        exprs.iter().try_for_each(|expr| expr.accept(self))
     }
 
-    fn visit_expression_statement(&mut self, expr: &Expr) -> Result<(), String> {
+    fn visit_expression_statement(&mut self, expr: &Expr) -> Result<(), ResolveError> {
         expr.accept(self)
     }
 
-    fn visit_block_statement(&mut self, statements: &[Stmt]) -> Result<(), String> {
-        // Every `{` starts a new inner scope.
-        self.begin_scope();
+    fn visit_block_statement(&mut self, statements: &[Stmt]) -> Result<(), ResolveError> {
+        // a `{` only starts a new inner scope when something is going to be put in it, the
+        // interpreter makes the same call so the depths agree.
+        let scoped = statements.iter().any(Stmt::needs_scope);
+        if scoped {
+            self.begin_scope();
+        }
         for stmt in statements {
             stmt.accept(self)?;
         }
-        self.end_scope();
+        if scoped {
+            self.end_scope();
+        }
         Ok(())
     }
 
@@ -188,7 +474,7 @@ impl Visitor<Result<(), String>, Expr, Stmt> for Resolver {
         condition: &Expr,
         then_branch: &Stmt,
         else_branch: Option<&Stmt>,
-    ) -> Result<(), String> {
+    ) -> Result<(), ResolveError> {
         condition.accept(self)?;
         then_branch.accept(self)?;
         if let Some(else_stmt) = else_branch {
@@ -197,17 +483,31 @@ impl Visitor<Result<(), String>, Expr, Stmt> for Resolver {
         Ok(())
     }
 
-    fn visit_while_statement(&mut self, condition: &Expr, body: &Stmt) -> Result<(), String> {
+    fn visit_while_statement(&mut self, condition: &Expr, body: &Stmt) -> Result<(), ResolveError> {
         condition.accept(self)?;
         body.accept(self)
     }
 
+    fn visit_for_statement(
+        &mut self,
+        condition: &Expr,
+        increment: Option<&Expr>,
+        body: &Stmt,
+    ) -> Result<(), ResolveError> {
+        condition.accept(self)?;
+        body.accept(self)?;
+        if let Some(increment) = increment {
+            increment.accept(self)?;
+        }
+        Ok(())
+    }
+
     fn visit_binary(
         &mut self,
         left: &Expr,
         _operator: BinaryOperator,
         right: &Expr,
-    ) -> Result<(), String> {
+    ) -> Result<(), ResolveError> {
         left.accept(self)?;
         right.accept(self)?;
         Ok(())
@@ -218,25 +518,25 @@ impl Visitor<Result<(), String>, Expr, Stmt> for Resolver {
         left: &Expr,
         _operator: LogicalOperator,
         right: &Expr,
-    ) -> Result<(), String> {
+    ) -> Result<(), ResolveError> {
         left.accept(self)?;
         right.accept(self)?;
         Ok(())
     }
 
-    fn visit_grouping(&mut self, expr: &Expr) -> Result<(), String> {
+    fn visit_grouping(&mut self, expr: &Expr) -> Result<(), ResolveError> {
         expr.accept(self)
     }
 
-    fn visit_literal(&mut self, _literal: &Literal) -> Result<(), String> {
+    fn visit_literal(&mut self, _literal: &Literal) -> Result<(), ResolveError> {
         Ok(())
     }
 
-    fn visit_unary(&mut self, _operator: UnaryPrefix, expr: &Expr) -> Result<(), String> {
+    fn visit_unary(&mut self, _operator: UnaryPrefix, expr: &Expr) -> Result<(), ResolveError> {
         expr.accept(self)
     }
 
-    fn visit_call(&mut self, callee: &Callee, arguments: &[Expr]) -> Result<(), String> {
+    fn visit_call(&mut self, callee: &Callee, arguments: &[Expr]) -> Result<(), ResolveError> {
         callee.expr.accept(self)?;
         for arg in arguments {
             arg.accept(self)?;
@@ -244,39 +544,34 @@ impl Visitor<Result<(), String>, Expr, Stmt> for Resolver {
         Ok(())
     }
 
-    fn visit_break_statement(&mut self) -> Result<(), String> {
+    fn visit_break_statement(&mut self) -> Result<(), ResolveError> {
         Ok(())
     }
 
-    fn visit_continue_statment(&mut self) -> Result<(), String> {
+    fn visit_continue_statment(&mut self) -> Result<(), ResolveError> {
         Ok(())
     }
 
-    fn visit_return_statment(&mut self, value: Option<&Expr>) -> Result<(), String> {
+    fn visit_return_statment(&mut self, value: Option<&Expr>) -> Result<(), ResolveError> {
         if let Some(expr) = value {
             expr.accept(self)?;
         }
         Ok(())
     }
 
-    fn visit_class_statement(
-        &mut self,
-        name: &Identifier,
-        methods: &[Function],
-    ) -> Result<(), String> {
+    fn visit_class_statement(&mut self, value: &Class) -> Result<(), ResolveError> {
+        // the parser should have already confirmed that this is safe.
+        let name = value.name().unwrap();
         self.declare(name)?;
         self.define(name);
+        self.resolve_class(value)
+    }
 
-        self.begin_scope();
-        self.put_str("this");
-        for method in methods {
-            self.resolve_function(FuncType::Method, method)?;
-        }
-        self.end_scope();
-        Ok(())
+    fn visit_class(&mut self, value: &Class) -> Result<(), ResolveError> {
+        self.resolve_class(value)
     }
 
-    fn visit_get(&mut self, object: &Expr, _property: &Identifier) -> Result<(), String> {
+    fn visit_get(&mut self, object: &Expr, _property: &Identifier) -> Result<(), ResolveError> {
         object.accept(self)
     }
 
@@ -285,24 +580,402 @@ impl Visitor<Result<(), String>, Expr, Stmt> for Resolver {
         object: &Expr,
         _property: &Identifier,
         value: &Expr,
-    ) -> Result<(), String> {
+    ) -> Result<(), ResolveError> {
         object.accept(self)?;
         value.accept(self)?;
         Ok(())
     }
 
-    fn visit_this(&mut self, ident: &Identifier) -> Result<(), String> {
+    fn visit_map(
+        &mut self,
+        entries: &[(Expr, Expr)],
+        _position: usize,
+    ) -> Result<(), ResolveError> {
+        for (key, value) in entries {
+            key.accept(self)?;
+            value.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        _position: usize,
+    ) -> Result<(), ResolveError> {
+        object.accept(self)?;
+        index.accept(self)
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        value: &Expr,
+        _position: usize,
+    ) -> Result<(), ResolveError> {
+        object.accept(self)?;
+        index.accept(self)?;
+        value.accept(self)
+    }
+
+    fn visit_cached(&mut self, expr: &Expr, _slot: usize) -> Result<(), ResolveError> {
+        expr.accept(self)
+    }
+
+    fn visit_cached_loop(&mut self, _slots: &[usize], body: &Stmt) -> Result<(), ResolveError> {
+        body.accept(self)
+    }
+
+    fn visit_super(
+        &mut self,
+        keyword: &Identifier,
+        this: &Identifier,
+        _property: &Identifier,
+    ) -> Result<(), ResolveError> {
+        self.check_not_static(keyword)?;
+        if let Some((depth, (slot, _))) = self.resolve_local(keyword.name_str()) {
+            self.bind_local(keyword, depth, slot);
+            // the method found is bound to the current instance.
+            return self.visit_this(this);
+        }
+        Err(ResolveError::SuperOutsideSubclass {
+            span: keyword.span(),
+        })
+    }
+
+    fn visit_this(&mut self, ident: &Identifier) -> Result<(), ResolveError> {
+        self.check_not_static(ident)?;
         // now figure out if the target is a local or global var
         if let Some((depth, (slot, _))) = self.resolve_local(ident.name_str()) {
             // Store the resolved metadata back into the AST node if it was a local var.
-            ident.swap_depth(depth);
-            ident.swap_slot(slot);
+            self.bind_local(ident, depth, slot);
         } else {
-            return Err(format!(
-                "'this' cannot be used in the global scope {}",
-                ident.position()
-            ));
+            return Err(ResolveError::ThisOutsideMethod { span: ident.span() });
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::tree::parser::Parser;
+
+    fn resolve(src: &str) -> Vec<Stmt> {
+        let mut parser = Parser::new(src);
+        parser.parse();
+        assert!(!parser.had_errors(), "failed to parse {src}");
+        let stmts = parser.take_statements();
+        let mut resolver = Resolver::new();
+        for stmt in &stmts {
+            stmt.accept(&mut resolver).unwrap();
+        }
+        stmts
+    }
+
+    fn block_statements(stmt: &Stmt) -> &[Stmt] {
+        match stmt {
+            Stmt::Block { statements } => statements,
+            other => panic!("expected block but found {}", other.type_str()),
+        }
+    }
+
+    fn printed_identifier(stmt: &Stmt) -> &Identifier {
+        match stmt {
+            Stmt::Print { exprs, .. } if exprs.len() == 1 => match &exprs[0] {
+                Expr::Variable { value } => value,
+                other => panic!(
+                    "expected print of a variable but found {}",
+                    other.type_str()
+                ),
+            },
+            other => panic!(
+                "expected print of a variable but found {}",
+                other.type_str()
+            ),
+        }
+    }
+
+    #[test]
+    fn test_local_binding() {
+        let stmts = resolve("{ var a = 1; var b = 2; print b; }");
+        let block = block_statements(&stmts[0]);
+        let ident = printed_identifier(&block[2]);
+        assert_eq!(ident.binding(), Some(Binding::Local { slot: 1 }));
+    }
+
+    #[test]
+    fn test_local_binding_in_enclosing_block() {
+        let stmts = resolve("{ var a = 1; { var b = 2; print a; } }");
+        let outer = block_statements(&stmts[0]);
+        let inner = block_statements(&outer[1]);
+        let ident = printed_identifier(&inner[1]);
+        assert_eq!(ident.binding(), Some(Binding::Local { slot: 0 }));
+    }
+
+    #[test]
+    fn test_block_without_declarations_shares_scope() {
+        let stmts = resolve("{ var a = 1; { print a; } }");
+        let outer = block_statements(&stmts[0]);
+        let inner = block_statements(&outer[1]);
+        let ident = printed_identifier(&inner[0]);
+        assert_eq!(ident.binding(), Some(Binding::Local { slot: 0 }));
+    }
+
+    #[test]
+    fn test_upvalue_binding() {
+        let stmts = resolve("{ var a = 1; fun f() { print a; } }");
+        let outer = block_statements(&stmts[0]);
+        let Stmt::Var {
+            initializer: Some(Expr::Function { value }),
+            ..
+        } = &outer[1]
+        else {
+            panic!("expected a function declaration");
+        };
+        let body = value.body();
+        let ident = printed_identifier(&block_statements(&body)[0]);
+        assert_eq!(ident.binding(), Some(Binding::UpValue { index: 0 }));
+        assert_eq!(&value.captures()[..], [Capture::Local(0)]);
+    }
+
+    #[test]
+    fn test_upvalues_pass_through_enclosing_functions() {
+        let stmts = resolve("{ var a = 1; var b = 2; fun f() { fun g() { print b; } } }");
+        let function = |stmt: &Stmt| match stmt {
+            Stmt::Var {
+                initializer: Some(Expr::Function { value }),
+                ..
+            } => value.clone(),
+            other => panic!("expected a function but found {}", other.type_str()),
+        };
+        let f = function(&block_statements(&stmts[0])[2]);
+        let f_body = f.body();
+        let g = function(&block_statements(&f_body)[0]);
+        let g_body = g.body();
+        // `f` never reads `b` itself, but has to capture it for `g` to capture from it.
+        assert_eq!(&f.captures()[..], [Capture::Local(1)]);
+        assert_eq!(&g.captures()[..], [Capture::UpValue(0)]);
+        let ident = printed_identifier(&block_statements(&g_body)[0]);
+        assert_eq!(ident.binding(), Some(Binding::UpValue { index: 0 }));
+    }
+
+    #[test]
+    fn test_global_binding() {
+        let stmts = resolve("var a = 1; print a;");
+        let Stmt::Var { name, .. } = &stmts[0] else {
+            panic!("expected a var declaration");
+        };
+        assert_eq!(name.binding(), Some(Binding::Global));
+        assert_eq!(
+            printed_identifier(&stmts[1]).binding(),
+            Some(Binding::Global)
+        );
+    }
+
+    #[test]
+    fn test_clone_keeps_bindings_until_resolved_again() {
+        let stmts = resolve("{ var a = 1; { var b = 2; print a; } }");
+        let outer = block_statements(&stmts[0]);
+        let original = &block_statements(&outer[1])[1];
+        let copy = original.clone();
+        let local = Some(Binding::Local { slot: 0 });
+        assert_eq!(printed_identifier(&copy).binding(), local);
+
+        // moved out of its blocks, the copy now refers to a global.
+        copy.accept(&mut Resolver::new()).unwrap();
+        assert_eq!(printed_identifier(&copy).binding(), Some(Binding::Global));
+        assert_eq!(printed_identifier(original).binding(), local);
+    }
+
+    #[test]
+    fn test_clone_copies_function_bodies() {
+        let stmts = resolve("fun f() { print 1; }");
+        let function = |stmt: &Stmt| match stmt {
+            Stmt::Var {
+                initializer: Some(Expr::Function { value }),
+                ..
+            } => value.body(),
+            other => panic!("expected a function but found {}", other.type_str()),
+        };
+        let copy = stmts[0].clone();
+        assert!(!std::rc::Rc::ptr_eq(&function(&stmts[0]), &function(&copy)));
+    }
+
+    #[test]
+    fn test_resolve_twice_gives_identical_bindings() {
+        let src = "var g = 0; { var a = 1; { var b = 2; print a; } } print g;";
+        let mut parser = Parser::new(src);
+        parser.parse();
+        let stmts = parser.take_statements();
+        let bindings = |stmts: &[Stmt]| {
+            let outer = block_statements(&stmts[1]);
+            let inner = block_statements(&outer[1]);
+            (
+                printed_identifier(&inner[1]).binding(),
+                printed_identifier(&stmts[2]).binding(),
+            )
+        };
+        let expected = (Some(Binding::Local { slot: 0 }), Some(Binding::Global));
+
+        let mut resolver = Resolver::new();
+        resolver.resolve(&stmts).unwrap();
+        assert_eq!(bindings(&stmts), expected);
+        resolver.resolve(&stmts).unwrap();
+        assert_eq!(bindings(&stmts), expected);
+
+        // an error part way through a block must not leave its scope behind for the next run.
+        let mut parser = Parser::new("{ var a = a; }");
+        parser.parse();
+        assert!(resolver.resolve(&parser.take_statements()).is_err());
+        resolver.resolve(&stmts).unwrap();
+        assert_eq!(bindings(&stmts), expected);
+    }
+
+    #[test]
+    fn test_initializer_reads_the_name_it_shadows() {
+        let stmts = resolve("{ var a = 1; { var a = a; } }");
+        let outer = block_statements(&stmts[0]);
+        let Stmt::Var {
+            initializer: Some(Expr::Variable { value }),
+            ..
+        } = &block_statements(&outer[1])[0]
+        else {
+            panic!("expected a var declaration");
+        };
+        assert_eq!(value.binding(), Some(Binding::Local { slot: 0 }));
+    }
+
+    #[test]
+    fn test_class_cannot_inherit_from_itself() {
+        let mut parser = Parser::new("class A < A {}");
+        parser.parse();
+        let stmts = parser.take_statements();
+        let err = stmts[0].accept(&mut Resolver::new()).unwrap_err();
+        assert_eq!(
+            err,
+            ResolveError::InheritsFromItself {
+                name: "A".to_string(),
+                span: Span::new(10, 11),
+            }
+        );
+    }
+
+    #[test]
+    fn test_duplicate_declaration_points_at_second_name() {
+        let src = "{\n  var a = 1;\n  var a = 2;\n}";
+        let mut parser = Parser::new(src);
+        parser.parse();
+        let err = Resolver::new()
+            .resolve(&parser.take_statements())
+            .unwrap_err();
+        let second = src.rfind('a').unwrap();
+        assert_eq!(err.span(), Span::new(second, second + 1));
+        assert_eq!(
+            err.to_string(),
+            "Resolver error: a already declared in this scope"
+        );
+        assert!(
+            err.code_block(src)
+                .ends_with("3 |   var a = 2;\n  |       ^"),
+            "{}",
+            err.code_block(src)
+        );
+    }
+
+    #[test]
+    fn test_resolve_program_end_to_end() {
+        let src = "var total = 0;\nfun add(n) {\n  var doubled = n * 2;\n  print doubled;\n}\n";
+        let stmts = resolve(src);
+        let Stmt::Var {
+            name,
+            initializer: Some(Expr::Function { value }),
+        } = &stmts[1]
+        else {
+            panic!("expected a function declaration");
+        };
+        assert_eq!(name.binding(), Some(Binding::Global));
+        let body = value.body();
+        let ident = printed_identifier(&block_statements(&body)[1]);
+        // the body's declarations get a scope of their own, numbered on from `n` in the same frame.
+        assert_eq!(ident.binding(), Some(Binding::Local { slot: 1 }));
+        // an identifier's span covers exactly the name it was parsed from.
+        for ident in [name, &value.params()[0], ident] {
+            let span = ident.span();
+            assert_eq!(span.start, ident.position());
+            assert_eq!(&src[span.start..span.end], ident.name_str());
+        }
+    }
+
+    #[test]
+    fn test_undeclared_globals() {
+        let check = |src: &str| {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            let stmts = parser.take_statements();
+            let plain = Resolver::new().resolve(&stmts);
+            let checked = Resolver::new()
+                .with_known_globals(["clock"])
+                .resolve(&stmts);
+            (stmts, plain, checked)
+        };
+
+        // declared at the top level, even after the function using it.
+        let (stmts, plain, checked) =
+            check("fun f() { return late + clock(); } var late = 1; print late;");
+        assert_eq!((plain, checked), (Ok(()), Ok(())));
+        assert_eq!(
+            printed_identifier(&stmts[2]).binding(),
+            Some(Binding::Global)
+        );
+
+        // only reads and assignments of names declared nowhere are caught, and only when asked.
+        for (src, name) in [("x = 1;", "x"), ("print missing;", "missing")] {
+            let (_, plain, checked) = check(src);
+            assert_eq!(plain, Ok(()));
+            let at = src.find(name).unwrap();
+            assert_eq!(
+                checked,
+                Err(ResolveError::UndefinedGlobal {
+                    name: name.to_string(),
+                    span: Span::new(at, at + name.len()),
+                })
+            );
+        }
+        // a name declared in a block is local to it, not a global.
+        let (_, _, checked) = check("{ var inner = 1; } print inner;");
+        assert!(checked.is_err());
+    }
+
+    #[test]
+    fn test_this_and_super_rejected_in_static_methods() {
+        let resolve_err = |src: &str| {
+            let mut parser = Parser::new(src);
+            parser.parse();
+            assert!(!parser.had_errors(), "failed to parse {src}");
+            Resolver::new().resolve(&parser.take_statements())
+        };
+        let base = "class A { m() { return \"A\"; } } ";
+        for (body, keyword) in [
+            ("static s() { return super.m; }", "super"),
+            ("static s() { return this; }", "this"),
+            ("static s() { return fun() { return super.m; } }", "super"),
+        ] {
+            let src = format!("{base}class B < A {{ {body} }}");
+            let at = src.rfind(keyword).unwrap();
+            assert_eq!(
+                resolve_err(&src),
+                Err(ResolveError::InStaticMethod {
+                    keyword: keyword.to_string(),
+                    span: Span::new(at, at + keyword.len()),
+                }),
+                "{src}"
+            );
+        }
+        // a class declared inside a static method still has its own `this`.
+        let src = "class A { static make() { class B { m() { return this; } } return B(); } }";
+        assert_eq!(resolve_err(src), Ok(()));
+    }
+}
@@ -1,5 +1,7 @@
+use super::tokenizer::span::Span;
 use super::tree::ast::{
-    BinaryOperator, Callee, Function, Identifier, Literal, LogicalOperator, UnaryPrefix,
+    BinaryOperator, Callee, Class, Function, Identifier, Literal, LogicalOperator, PostfixOperator,
+    UnaryPrefix,
 };
 
 pub trait Visitor<T, Expr, Stmt> {
@@ -11,15 +13,28 @@ pub trait Visitor<T, Expr, Stmt> {
     fn visit_unary(&mut self, prefix: UnaryPrefix, expr: &Expr) -> T;
     fn visit_variable(&mut self, name: &Identifier) -> T;
     fn visit_assignment(&mut self, name: &Identifier, value: &Expr) -> T;
+    fn visit_postfix(&mut self, name: &Identifier, op: PostfixOperator) -> T;
     fn visit_call(&mut self, callee: &Callee, args: &[Expr]) -> T;
     fn visit_function(&mut self, value: &Function) -> T;
     fn visit_get(&mut self, object: &Expr, property: &Identifier) -> T;
     fn visit_set(&mut self, object: &Expr, property: &Identifier, value: &Expr) -> T;
     fn visit_this(&mut self, ident: &Identifier) -> T;
+    fn visit_super(&mut self, keyword: &Identifier, this: &Identifier, property: &Identifier) -> T;
+    fn visit_class(&mut self, value: &Class) -> T;
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], position: usize) -> T;
+    fn visit_index(&mut self, object: &Expr, index: &Expr, position: usize) -> T;
+    fn visit_index_set(&mut self, object: &Expr, index: &Expr, value: &Expr, position: usize) -> T;
+    fn visit_cached(&mut self, expr: &Expr, slot: usize) -> T;
     // statments
     fn visit_expression_statement(&mut self, expr: &Expr) -> T;
-    fn visit_print_statement(&mut self, expr: &Expr) -> T;
+    fn visit_print_statement(&mut self, exprs: &[Expr], span: Span) -> T;
     fn visit_var_statement(&mut self, name: &Identifier, expr: Option<&Expr>) -> T;
+    fn visit_destructure_statement(
+        &mut self,
+        names: &[Identifier],
+        expr: &Expr,
+        position: usize,
+    ) -> T;
     fn visit_block_statement(&mut self, statments: &[Stmt]) -> T;
     fn visit_if_statement(
         &mut self,
@@ -28,8 +43,11 @@ pub trait Visitor<T, Expr, Stmt> {
         else_block: Option<&Stmt>,
     ) -> T;
     fn visit_while_statement(&mut self, condition: &Expr, block: &Stmt) -> T;
+    fn visit_for_statement(&mut self, condition: &Expr, increment: Option<&Expr>, body: &Stmt)
+    -> T;
     fn visit_break_statement(&mut self) -> T;
     fn visit_continue_statment(&mut self) -> T;
     fn visit_return_statment(&mut self, value: Option<&Expr>) -> T;
-    fn visit_class_statement(&mut self, name: &Identifier, methods: &[Function]) -> T;
+    fn visit_class_statement(&mut self, value: &Class) -> T;
+    fn visit_cached_loop(&mut self, slots: &[usize], body: &Stmt) -> T;
 }
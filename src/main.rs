@@ -1,6 +1,7 @@
 use rloxv2::interpreter::lox::Lox;
 use rloxv2::lang::tree::parser::Parser;
 use rloxv2::lang::tree::resolver::Resolver;
+use std::io::{self, BufRead, Write};
 const INPUT: &str = r#"
 class Int {
     static test(n) {
@@ -22,23 +23,63 @@ print "'" + i.string() + "'";
 "#;
 
 fn main() {
-    let mut parser = Parser::new(&INPUT);
+    if std::env::args().nth(1).as_deref() == Some("--repl") {
+        return repl();
+    }
+    let mut parser = Parser::new(INPUT);
     parser.parse();
     if parser.had_errors() {
         return;
     }
+    for warning in parser.warnings() {
+        println!("{}", warning);
+        println!("{}", warning.code_block(INPUT));
+    }
     let mut res = Resolver::new();
-    let mut lox = Lox::new();
+    let mut lox = Lox::new().with_shadowed_native_warnings(true);
+    lox.set_source(INPUT);
     let stmts = parser.take_statements();
-    for stmt in &stmts {
-        if let Err(e) = stmt.accept(&mut res) {
-            println!("{e}");
-            break;
-        }
+    if let Err(e) = res.resolve(&stmts) {
+        println!("{e}");
+        println!("{}", e.code_block(INPUT));
     }
     if let Err(e) = lox.interpret(stmts) {
         println!("{}", e);
+        e.print_code_block(INPUT);
+        if let Some(trace) = e.stack_trace(Some(INPUT)) {
+            println!("trace: {}", trace);
+        }
     };
+    for warning in lox.take_warnings() {
+        println!("{}", warning);
+        println!("{}", warning.code_block(INPUT));
+    }
+}
+
+fn repl() {
+    let mut lox = Lox::new();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        match lox.run_line(&line) {
+            // echo whatever a bare expression evaluated to, so there's no need to `print` it.
+            Ok(value) if !value.is_nil() => println!("{}", value),
+            Ok(_) => {}
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    println!("{}", diagnostic.render(&line));
+                }
+            }
+        }
+        for warning in lox.take_warnings() {
+            println!("{}", warning);
+            println!("{}", warning.code_block(&line));
+        }
+    }
 }
 
 // expression     → assignment ;
@@ -53,8 +94,11 @@ fn main() {
 // term           → factor ( ( "-" | "+" ) factor )* ;
 // factor         → unary ( ( "/" | "*" ) unary )* ;
 
-// unary          → ( "!" | "-" ) unary | call ;
+// unary          → ( "!" | "-" ) unary | power ;
+// power          → postfix ( "**" unary )? ;
+// postfix        → call ( "++" | "--" )? ;  a "--" that an operand follows is "-" "-" instead
 // call           → primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
 // primary        → "true" | "false" | "nil" | "this"
 //                | NUMBER | STRING | IDENTIFIER | "(" expression ")"
-//                | "super" "." IDENTIFIER ;
+//                | "super" "." IDENTIFIER | lambda ;
+// lambda         → "\" "(" parameters? ")" "=>" expression ;